@@ -0,0 +1,106 @@
+use crate::error::NecoError;
+use anyhow::{Context, Result};
+use tokio::net::UdpSocket;
+use tokio::time::{Duration, timeout};
+
+const HEADER: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+const A2S_INFO: &[u8] = b"TSource Engine Query\0";
+const S2A_INFO: u8 = 0x49;
+const S2C_CHALLENGE: u8 = 0x41;
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct ServerInfo {
+    pub name: String,
+    pub map: String,
+    pub players: u8,
+}
+
+pub async fn query_info(address: &str) -> Result<ServerInfo> {
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .await
+        .context("Failed to bind UDP socket")?;
+    socket
+        .connect(address)
+        .await
+        .with_context(|| NecoError::Network(format!("Failed to resolve server address {}", address)))?;
+
+    let mut request = HEADER.to_vec();
+    request.extend_from_slice(A2S_INFO);
+
+    let mut response = send_and_receive(&socket, &request).await?;
+
+    // Newer servers answer with a challenge that must be echoed back
+    if response.first() == Some(&S2C_CHALLENGE) {
+        let challenge = response
+            .get(1..5)
+            .context("Truncated A2S challenge response")?;
+        request.extend_from_slice(challenge);
+        response = send_and_receive(&socket, &request).await?;
+    }
+
+    if response.first() != Some(&S2A_INFO) {
+        anyhow::bail!("Unexpected A2S_INFO response from {}", address);
+    }
+
+    // Skip header byte and protocol version
+    let mut reader = PacketReader::new(response.get(2..).context("Truncated A2S response")?);
+    let name = reader.read_string()?;
+    let map = reader.read_string()?;
+    let _folder = reader.read_string()?;
+    let _game = reader.read_string()?;
+    reader.skip(2)?; // app id
+    let players = reader.read_u8()?;
+
+    Ok(ServerInfo { name, map, players })
+}
+
+async fn send_and_receive(socket: &UdpSocket, request: &[u8]) -> Result<Vec<u8>> {
+    socket.send(request).await.context(NecoError::Network("Failed to send A2S query".to_string()))?;
+
+    let mut buffer = vec![0u8; 1400];
+    let len = timeout(QUERY_TIMEOUT, socket.recv(&mut buffer))
+        .await
+        .context("A2S query timed out")?
+        .context(NecoError::Network("Failed to receive A2S response".to_string()))?;
+
+    if len < 5 || buffer[..4] != HEADER {
+        anyhow::bail!("Malformed A2S response");
+    }
+
+    Ok(buffer[4..len].to_vec())
+}
+
+struct PacketReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PacketReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn read_string(&mut self) -> Result<String> {
+        let rest = self.data.get(self.pos..).context("Truncated A2S response")?;
+        let end = rest
+            .iter()
+            .position(|&b| b == 0)
+            .context("Unterminated string in A2S response")?;
+        self.pos += end + 1;
+        Ok(String::from_utf8_lossy(&rest[..end]).to_string())
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        let byte = *self.data.get(self.pos).context("Truncated A2S response")?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn skip(&mut self, count: usize) -> Result<()> {
+        if self.pos + count > self.data.len() {
+            anyhow::bail!("Truncated A2S response");
+        }
+        self.pos += count;
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use chrono::{DateTime, Utc};
+
+/// Node of a Valve KeyValues (VDF/ACF) document
+#[derive(Debug)]
+pub enum Value {
+    Str(String),
+    Obj(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Obj(entries) => entries
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| v),
+            Value::Str(_) => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            Value::Obj(_) => None,
+        }
+    }
+
+    fn get_u64(&self, key: &str) -> Option<u64> {
+        self.get(key)?.as_str()?.parse().ok()
+    }
+}
+
+/// Parses a KeyValues document into its root object. Returns None on
+/// malformed input.
+pub fn parse(content: &str) -> Option<Value> {
+    let tokens = tokenize(content);
+    let mut pos = 0;
+    let entries = parse_entries(&tokens, &mut pos)?;
+    Some(Value::Obj(entries))
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Str(String),
+    Open,
+    Close,
+}
+
+fn tokenize(content: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            '"' => {
+                let mut s = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => {
+                            if let Some(escaped) = chars.next() {
+                                s.push(match escaped {
+                                    'n' => '\n',
+                                    't' => '\t',
+                                    other => other,
+                                });
+                            }
+                        }
+                        other => s.push(other),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut s = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || next == '{' || next == '}' || next == '"' {
+                        break;
+                    }
+                    s.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Str(s));
+            }
+        }
+    }
+
+    tokens
+}
+
+fn parse_entries(tokens: &[Token], pos: &mut usize) -> Option<Vec<(String, Value)>> {
+    let mut entries = Vec::new();
+
+    while let Some(token) = tokens.get(*pos) {
+        let key = match token {
+            Token::Close => return Some(entries),
+            Token::Open => return None,
+            Token::Str(key) => key.clone(),
+        };
+        *pos += 1;
+
+        let value = match tokens.get(*pos)? {
+            Token::Str(value) => {
+                *pos += 1;
+                Value::Str(value.clone())
+            }
+            Token::Open => {
+                *pos += 1;
+                let children = parse_entries(tokens, pos)?;
+                if tokens.get(*pos) != Some(&Token::Close) {
+                    return None;
+                }
+                *pos += 1;
+                Value::Obj(children)
+            }
+            Token::Close => return None,
+        };
+        entries.push((key, value));
+    }
+
+    Some(entries)
+}
+
+/// What SteamCMD's appworkshop_<appid>.acf records about an installed item
+#[derive(Debug)]
+pub struct InstalledItem {
+    pub size: Option<u64>,
+    pub time_updated: Option<DateTime<Utc>>,
+    /// Update time of the newest version Steam knows about
+    pub latest_time_updated: Option<DateTime<Utc>>,
+}
+
+/// Looks up an item in a parsed appworkshop ACF document
+pub fn installed_item(root: &Value, workshop_id: &str) -> Option<InstalledItem> {
+    let app = root.get("AppWorkshop")?;
+    let installed = app.get("WorkshopItemsInstalled")?.get(workshop_id)?;
+    let details = app
+        .get("WorkshopItemDetails")
+        .and_then(|d| d.get(workshop_id));
+
+    let timestamp = |secs: Option<u64>| secs.and_then(|s| DateTime::from_timestamp(s as i64, 0));
+
+    Some(InstalledItem {
+        size: installed.get_u64("size"),
+        time_updated: timestamp(installed.get_u64("timeupdated")),
+        latest_time_updated: timestamp(details.and_then(|d| d.get_u64("latest_timeupdated"))),
+    })
+}
@@ -0,0 +1,82 @@
+use crate::error::NecoError;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Favors ratio over speed, archived maps are written once and rarely read
+const LEVEL: i32 = 19;
+
+/// Where a file of an archived item is kept, next to its original relative path
+pub fn archived_path(item_dir: &Path, rel_path: &str) -> PathBuf {
+    item_dir.join(format!("{}.zst", rel_path))
+}
+
+/// Compresses `src` into `dest` with zstd. Returns the compressed size.
+pub async fn compress(src: &Path, dest: &Path) -> Result<u64> {
+    let (src, dest) = (src.to_path_buf(), dest.to_path_buf());
+    tokio::task::spawn_blocking(move || {
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let input = File::open(&src).with_context(|| NecoError::Fs(format!("Failed to open {}", src.display())))?;
+        let output =
+            File::create(&dest).with_context(|| NecoError::Fs(format!("Failed to create {}", dest.display())))?;
+        zstd::stream::copy_encode(input, &output, LEVEL)
+            .with_context(|| NecoError::Fs(format!("Failed to compress {}", src.display())))?;
+        Ok(output.metadata()?.len())
+    })
+    .await?
+}
+
+pub async fn decompress(src: &Path, dest: &Path) -> Result<()> {
+    let (src, dest) = (src.to_path_buf(), dest.to_path_buf());
+    tokio::task::spawn_blocking(move || {
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let input = File::open(&src).with_context(|| NecoError::Fs(format!("Failed to open {}", src.display())))?;
+        let output =
+            File::create(&dest).with_context(|| NecoError::Fs(format!("Failed to create {}", dest.display())))?;
+        zstd::stream::copy_decode(input, output)
+            .with_context(|| NecoError::Fs(format!("Failed to decompress {}", src.display())))
+    })
+    .await?
+}
+
+/// State files are rewritten often, so they favor speed
+const STATE_LEVEL: i32 = 3;
+
+/// Magic number at the start of every zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Where a state file is kept when `compress_state` is on, e.g. `metadata.json.zst`
+pub fn compressed_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".zst");
+    PathBuf::from(name)
+}
+
+/// Compresses state file content in memory
+pub fn encode(data: &[u8]) -> Result<Vec<u8>> {
+    zstd::encode_all(data, STATE_LEVEL).context("Failed to compress")
+}
+
+/// Reads a state file whether it is plain or zstd-compressed, going by its
+/// content rather than its name. `None` when the file doesn't exist.
+pub async fn read_state(path: &Path) -> Result<Option<String>> {
+    let data = match tokio::fs::read(path).await {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| NecoError::Fs(format!("Failed to read {}", path.display()))),
+    };
+    let data = if data.starts_with(&ZSTD_MAGIC) {
+        // Decodes every frame, audit logs append one per entry
+        zstd::decode_all(data.as_slice())
+            .with_context(|| format!("Failed to decompress {}", path.display()))?
+    } else {
+        data
+    };
+    String::from_utf8(data)
+        .map(Some)
+        .with_context(|| format!("{} is not valid UTF-8", path.display()))
+}
@@ -0,0 +1,146 @@
+use crate::archive;
+use crate::error::NecoError;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Download,
+    Update,
+    Remove,
+    Undo,
+    Archive,
+    Unarchive,
+    /// Missing files restored without touching the rest of the item
+    Repair,
+    /// Dropped from tracking with its files left in place
+    Forget,
+}
+
+impl Operation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Download => "download",
+            Operation::Update => "update",
+            Operation::Remove => "remove",
+            Operation::Undo => "undo",
+            Operation::Archive => "archive",
+            Operation::Unarchive => "unarchive",
+            Operation::Repair => "repair",
+            Operation::Forget => "forget",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub time: DateTime<Utc>,
+    pub op: Operation,
+    pub id: String,
+    pub title: String,
+    /// Files written (download/update) or deleted (remove)
+    #[serde(default)]
+    pub files: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changelog_id: Option<String>,
+    /// Directory under trash/ holding the files this operation removed or replaced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trash: Option<String>,
+    /// Metadata of the item before the operation, used by `undo`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<crate::WorkshopMetadata>,
+    /// For undo entries, the time of the entry that was reverted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reverts: Option<DateTime<Utc>>,
+}
+
+/// Appends one JSON line to the audit log. The file is never rewritten, except
+/// once to convert it when `compress` changes. Compressed logs (`audit.log.zst`)
+/// get one zstd frame per entry.
+pub async fn append(path: &Path, entry: &AuditEntry, compress: bool) -> Result<()> {
+    let compressed = archive::compressed_path(path);
+    let (target, other) = if compress {
+        (compressed.as_path(), path)
+    } else {
+        (path, compressed.as_path())
+    };
+
+    let line = serde_json::to_string(entry)?;
+    if let Some(previous) = archive::read_state(other).await? {
+        return convert(target, other, &previous, &line, compress).await;
+    }
+
+    let mut data = format!("{}\n", line).into_bytes();
+    if compress {
+        data = archive::encode(&data)?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(target)
+        .await
+        .with_context(|| NecoError::Fs(format!("Failed to open audit log {}", target.display())))?;
+    file.write_all(&data).await?;
+    Ok(())
+}
+
+/// Merges the log in the old format into `target`. The merged log replaces
+/// `target` in one rename before `other` is removed, a crash in between
+/// leaves entries in both files that `read` and the next conversion dedupe.
+async fn convert(target: &Path, other: &Path, previous: &str, line: &str, compress: bool) -> Result<()> {
+    let current = archive::read_state(target).await?.unwrap_or_default();
+    let mut seen = HashSet::new();
+    let mut merged = String::new();
+    for entry in current.lines().chain(previous.lines()).chain([line]) {
+        if seen.insert(entry) {
+            merged.push_str(entry);
+            merged.push('\n');
+        }
+    }
+    let data = if compress { archive::encode(merged.as_bytes())? } else { merged.into_bytes() };
+
+    let mut temp = target.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    fs::write(&temp, data)
+        .await
+        .with_context(|| NecoError::Fs(format!("Failed to write {}", temp.display())))?;
+    fs::rename(&temp, target)
+        .await
+        .with_context(|| NecoError::Fs(format!("Failed to replace audit log {}", target.display())))?;
+    fs::remove_file(other)
+        .await
+        .with_context(|| NecoError::Fs(format!("Failed to remove {}", other.display())))?;
+    Ok(())
+}
+
+/// Reads all entries from the plain and compressed log, oldest first.
+/// Malformed lines are skipped.
+pub async fn read(path: &Path) -> Result<Vec<AuditEntry>> {
+    let mut entries: Vec<AuditEntry> = Vec::new();
+    let mut seen = HashSet::new();
+    for file in [path.to_path_buf(), archive::compressed_path(path)] {
+        let Some(content) = archive::read_state(&file)
+            .await
+            .context(NecoError::Fs("Failed to read audit log".to_string()))?
+        else {
+            continue;
+        };
+        // Both files hold the same entries if a conversion was interrupted
+        entries.extend(
+            content
+                .lines()
+                .filter(|line| seen.insert(line.to_string()))
+                .filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok()),
+        );
+    }
+    entries.sort_by_key(|entry| entry.time);
+    Ok(entries)
+}
@@ -0,0 +1,603 @@
+use crate::error::NecoError;
+use crate::http::RetryPolicy;
+use crate::page;
+use crate::steam_api::{PublishedFile, SteamApi};
+use crate::steamcmd::{self, Outcome};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::warn;
+
+#[derive(Clone)]
+pub struct WorkshopItem {
+    pub id: String,
+    pub title: String,
+    pub changelog_id: String,
+    pub changelog: String,
+    /// Download size declared by the Workshop, if it could be looked up
+    pub file_size: Option<u64>,
+    pub time_updated: Option<DateTime<Utc>>,
+    /// Items listed under "Required items", None if they couldn't be looked up
+    pub required_items: Option<Vec<String>>,
+}
+
+#[derive(Clone)]
+pub struct WorkshopCollection {
+    pub id: String,
+    pub title: String,
+    pub item_ids: Vec<String>,
+}
+
+#[derive(Clone)]
+pub enum ParseResult {
+    Item(WorkshopItem),
+    Collection(WorkshopCollection),
+    Unavailable(String),
+}
+
+/// Looks up what a Workshop ID currently points at
+#[async_trait]
+pub trait WorkshopInfoProvider: Send + Sync {
+    async fn fetch(&self, workshop_id: &str) -> Result<ParseResult>;
+
+    /// Looks up what it can about several IDs at once ahead of their fetch
+    /// calls, for backends that can batch requests
+    async fn prefetch(&self, _workshop_ids: &[String]) {}
+
+    /// Drops anything remembered from earlier lookups
+    fn forget(&self) {}
+
+    /// Writes out what is kept between runs, once a command is done
+    async fn flush(&self) {}
+}
+
+/// Downloads an item's content into a SteamCMD-style install dir, where it
+/// ends up under `steamapps/workshop/content/<appid>/<id>`
+#[async_trait]
+pub trait ContentDownloader: Send + Sync {
+    async fn download(&self, appid: &str, workshop_id: &str, install_dir: &Path) -> Result<Outcome>;
+}
+
+/// The backends WorkshopManager talks to
+pub struct Backends {
+    pub info: Box<dyn WorkshopInfoProvider>,
+    pub downloader: Arc<dyn ContentDownloader>,
+}
+
+/// Settings the Steam backends are built from
+pub struct SteamOptions {
+    pub steamcmd: PathBuf,
+    /// Passed to SteamCMD ahead of the download commands
+    pub steamcmd_args: Vec<String>,
+    /// Where validators of fetched changelog pages are kept between runs
+    pub page_cache: PathBuf,
+    pub retry: RetryPolicy,
+}
+
+impl Backends {
+    /// Scrapes the Steam Community and downloads with SteamCMD
+    pub fn steam(client: &reqwest::Client, api: &SteamApi, options: &SteamOptions) -> Self {
+        Self {
+            info: Box::new(SteamCommunity::new(client, api, &options.page_cache, options.retry.clone())),
+            downloader: Arc::new(SteamCmd {
+                path: options.steamcmd.clone(),
+                extra_args: options.steamcmd_args.clone(),
+            }),
+        }
+    }
+}
+
+/// Remembers what each ID pointed at for the rest of a command, so an item
+/// in several collections is looked up once. Failed lookups aren't kept.
+pub struct CachedInfo {
+    inner: Box<dyn WorkshopInfoProvider>,
+    results: Mutex<HashMap<String, ParseResult>>,
+}
+
+impl CachedInfo {
+    pub fn new(inner: Box<dyn WorkshopInfoProvider>) -> Self {
+        Self {
+            inner,
+            results: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl WorkshopInfoProvider for CachedInfo {
+    async fn fetch(&self, workshop_id: &str) -> Result<ParseResult> {
+        if let Some(result) = self.results.lock().unwrap().get(workshop_id) {
+            return Ok(result.clone());
+        }
+        let result = self.inner.fetch(workshop_id).await?;
+        self.results
+            .lock()
+            .unwrap()
+            .insert(workshop_id.to_string(), result.clone());
+        Ok(result)
+    }
+
+    async fn prefetch(&self, workshop_ids: &[String]) {
+        let missing: Vec<String> = {
+            let results = self.results.lock().unwrap();
+            workshop_ids
+                .iter()
+                .filter(|id| !results.contains_key(*id))
+                .cloned()
+                .collect()
+        };
+        self.inner.prefetch(&missing).await;
+    }
+
+    fn forget(&self) {
+        self.results.lock().unwrap().clear();
+        self.inner.forget();
+    }
+
+    async fn flush(&self) {
+        self.inner.flush().await;
+    }
+}
+
+/// IDs per Web API details request
+const DETAILS_BATCH: usize = 100;
+
+/// Item info from the Workshop changelog and details pages, with sizes from
+/// the Web API
+pub struct SteamCommunity {
+    client: reqwest::Client,
+    api: SteamApi,
+    retry: RetryPolicy,
+    cache_file: PathBuf,
+    /// Last changelog page seen per item, for conditional requests
+    changelogs: Mutex<HashMap<String, CachedChangelog>>,
+    /// Whether changelogs has entries cache_file doesn't have yet
+    changelogs_dirty: AtomicBool,
+    /// Web API details looked up in batches by prefetch
+    details: Mutex<HashMap<String, PublishedFile>>,
+}
+
+/// ETag/Last-Modified of a changelog page and what it said, so a 304 can be
+/// answered without downloading and parsing the page again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedChangelog {
+    #[serde(default)]
+    etag: Option<String>,
+    #[serde(default)]
+    last_modified: Option<String>,
+    title: String,
+    changelog_id: String,
+    #[serde(default)]
+    changelog: String,
+}
+
+impl SteamCommunity {
+    pub fn new(client: &reqwest::Client, api: &SteamApi, cache_file: &Path, retry: RetryPolicy) -> Self {
+        let changelogs = std::fs::read_to_string(cache_file)
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        Self {
+            client: client.clone(),
+            api: api.clone(),
+            retry,
+            cache_file: cache_file.to_path_buf(),
+            changelogs: Mutex::new(changelogs),
+            changelogs_dirty: AtomicBool::new(false),
+            details: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Web API details of an item, from the last prefetch or looked up alone
+    async fn file_details(&self, workshop_id: &str) -> Option<PublishedFile> {
+        if let Some(file) = self.details.lock().unwrap().get(workshop_id) {
+            return Some(file.clone());
+        }
+        match self.api.details(&[workshop_id.to_string()]).await {
+            Ok(details) => details.into_iter().next(),
+            Err(e) => {
+                warn!("could not look up size of {}: {:#}", workshop_id, e);
+                None
+            }
+        }
+    }
+
+    /// Fetches and parses an item's changelog page, sending the validators
+    /// of the last copy so an unchanged page comes back as 304 Not Modified.
+    /// Returns `None` when Steam reports the page as gone or forbidden.
+    async fn fetch_changelog(
+        &self,
+        workshop_id: &str,
+        diag: &mut page::Diagnostics,
+    ) -> Result<Option<page::ChangelogPage>> {
+        let url = format!(
+            "https://steamcommunity.com/sharedfiles/filedetails/changelog/{}",
+            workshop_id
+        );
+        let cached = self.changelogs.lock().unwrap().get(workshop_id).cloned();
+
+        let mut request = self
+            .client
+            .get(&url)
+            .header(reqwest::header::COOKIE, age_gate_cookies(workshop_id));
+        if let Some(cached) = &cached {
+            if let Some(etag) = &cached.etag {
+                request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = self
+            .retry
+            .send(request)
+            .await
+            .with_context(|| NecoError::Network(format!("Failed to fetch {}", url)))?;
+
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED
+            && let Some(cached) = cached
+        {
+            return Ok(Some(page::ChangelogPage::Entry {
+                title: cached.title,
+                changelog_id: cached.changelog_id,
+                changelog: cached.changelog,
+            }));
+        }
+        if matches!(
+            response.status(),
+            reqwest::StatusCode::NOT_FOUND
+                | reqwest::StatusCode::GONE
+                | reqwest::StatusCode::FORBIDDEN
+        ) {
+            return Ok(None);
+        }
+
+        let response = response
+            .error_for_status()
+            .with_context(|| NecoError::Network(format!("Failed to fetch {}", url)))?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+                .map(String::from)
+        };
+        let etag = header(reqwest::header::ETAG);
+        let last_modified = header(reqwest::header::LAST_MODIFIED);
+        let html = response
+            .text()
+            .await
+            .with_context(|| NecoError::Network(format!("Failed to fetch {}", url)))?;
+
+        let page = page::parse_changelog(&html, diag).map_err(anyhow::Error::msg)?;
+        if let page::ChangelogPage::Entry {
+            title,
+            changelog_id,
+            changelog,
+        } = &page
+            && (etag.is_some() || last_modified.is_some())
+        {
+            let entry = CachedChangelog {
+                etag,
+                last_modified,
+                title: title.clone(),
+                changelog_id: changelog_id.clone(),
+                changelog: changelog.clone(),
+            };
+            self.changelogs.lock().unwrap().insert(workshop_id.to_string(), entry);
+            self.changelogs_dirty.store(true, Ordering::Relaxed);
+        }
+        Ok(Some(page))
+    }
+
+    /// Writes the changelog cache if it changed since the last save
+    async fn save_changelogs(&self) {
+        if !self.changelogs_dirty.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let data = serde_json::to_string(&*self.changelogs.lock().unwrap());
+        let result = match data {
+            Ok(data) => tokio::fs::write(&self.cache_file, data).await.map_err(anyhow::Error::from),
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            warn!("failed to save {}: {:#}", self.cache_file.display(), e);
+        }
+    }
+
+    /// Returns `None` when Steam reports the page as gone or forbidden.
+    async fn fetch_html(&self, url: &str, workshop_id: &str) -> Result<Option<String>> {
+        let request = self
+            .client
+            .get(url)
+            .header(reqwest::header::COOKIE, age_gate_cookies(workshop_id));
+        let response = self.retry.send(request).await?;
+
+        if matches!(
+            response.status(),
+            reqwest::StatusCode::NOT_FOUND
+                | reqwest::StatusCode::GONE
+                | reqwest::StatusCode::FORBIDDEN
+        ) {
+            return Ok(None);
+        }
+
+        Ok(Some(response.error_for_status()?.text().await?))
+    }
+}
+
+#[async_trait]
+impl WorkshopInfoProvider for SteamCommunity {
+    async fn fetch(&self, workshop_id: &str) -> Result<ParseResult> {
+        let mut diag = page::Diagnostics::default();
+        let Some(changelog_page) = self
+            .fetch_changelog(workshop_id, &mut diag)
+            .await
+            .with_context(|| format!("Failed to fetch changelog page for id {}", workshop_id))?
+        else {
+            return Ok(ParseResult::Unavailable("page not found".to_string()));
+        };
+
+        match changelog_page {
+            page::ChangelogPage::Entry {
+                title,
+                changelog_id,
+                changelog,
+            } => {
+                diag.warn_if_degraded(workshop_id);
+
+                // Changelog entry ids are the unix time of the update
+                let mut time_updated = changelog_id
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(|secs| DateTime::from_timestamp(secs, 0));
+                let mut file_size = None;
+                let mut required_items = None;
+
+                if let Some(file) = self.file_details(workshop_id).await {
+                    file_size = Some(file.file_size).filter(|&size| size > 0);
+                    time_updated = file.time_updated.or(time_updated);
+                    if self.api.has_key() {
+                        required_items = Some(
+                            file.children
+                                .unwrap_or_default()
+                                .into_iter()
+                                .map(|child| child.publishedfileid)
+                                .collect(),
+                        );
+                    }
+                }
+
+                return Ok(ParseResult::Item(WorkshopItem {
+                    id: workshop_id.to_string(),
+                    title,
+                    changelog_id,
+                    changelog,
+                    file_size,
+                    time_updated,
+                    required_items,
+                }));
+            }
+            page::ChangelogPage::Error(reason) => return Ok(ParseResult::Unavailable(reason)),
+            page::ChangelogPage::AgeGated => anyhow::bail!(
+                "Workshop page for {} is behind an age/mature content gate that could not be bypassed",
+                workshop_id
+            ),
+            page::ChangelogPage::NoEntries => {}
+        }
+
+        let collection_url = crate::workshop_url(workshop_id);
+        let Some(collection_html) = self
+            .fetch_html(&collection_url, workshop_id)
+            .await
+            .with_context(|| NecoError::Network(format!("Failed to fetch collection page for id {}", workshop_id)))?
+        else {
+            return Ok(ParseResult::Unavailable("page not found".to_string()));
+        };
+
+        let details_page =
+            page::parse_details(&collection_html, &mut diag).map_err(anyhow::Error::msg)?;
+        diag.warn_if_degraded(workshop_id);
+
+        match details_page {
+            page::DetailsPage::Collection { title, item_ids } => {
+                Ok(ParseResult::Collection(WorkshopCollection {
+                    id: workshop_id.to_string(),
+                    title,
+                    item_ids,
+                }))
+            }
+            page::DetailsPage::Error(reason) => Ok(ParseResult::Unavailable(reason)),
+            page::DetailsPage::NotCollection => anyhow::bail!(
+                "Workshop page for {} has no changelog and is not a collection. {}",
+                workshop_id,
+                page::format_changed("details")
+            ),
+        }
+    }
+
+    async fn prefetch(&self, workshop_ids: &[String]) {
+        let missing: Vec<String> = {
+            let details = self.details.lock().unwrap();
+            workshop_ids
+                .iter()
+                .filter(|id| !details.contains_key(*id))
+                .cloned()
+                .collect()
+        };
+        for batch in missing.chunks(DETAILS_BATCH) {
+            match self.api.details(batch).await {
+                Ok(files) => {
+                    let mut details = self.details.lock().unwrap();
+                    for file in files {
+                        details.insert(file.publishedfileid.clone(), file);
+                    }
+                }
+                // fetch looks each item up on its own then
+                Err(e) => warn!("could not look up sizes of {} items: {:#}", batch.len(), e),
+            }
+        }
+    }
+
+    fn forget(&self) {
+        self.details.lock().unwrap().clear();
+    }
+
+    async fn flush(&self) {
+        self.save_changelogs().await;
+    }
+}
+
+/// Anonymous `workshop_download_item` with the configured SteamCMD
+pub struct SteamCmd {
+    path: PathBuf,
+    /// steamcmd_extra_args, ahead of our own commands so launch options and
+    /// settings like the forced platform apply to the login and download
+    extra_args: Vec<String>,
+}
+
+#[async_trait]
+impl ContentDownloader for SteamCmd {
+    async fn download(&self, appid: &str, workshop_id: &str, install_dir: &Path) -> Result<Outcome> {
+        let install_dir = crate::platform::steamcmd_path_arg(install_dir);
+        let mut args: Vec<&str> = self.extra_args.iter().map(String::as_str).collect();
+        args.extend([
+            "+force_install_dir",
+            &install_dir,
+            "+login",
+            "anonymous",
+            "+workshop_download_item",
+            appid,
+            workshop_id,
+            "+quit",
+        ]);
+        steamcmd::run(&self.path, &args, workshop_id, false).await
+    }
+}
+
+/// Cookies that make Steam skip the age check and mature content interstitials,
+/// which would otherwise replace the page we're trying to scrape
+fn age_gate_cookies(workshop_id: &str) -> String {
+    format!(
+        "birthtime=-729000000; lastagecheckage=1-January-1947; mature_content=1; \
+         wants_mature_content=1; wants_mature_content_item_{}=1",
+        workshop_id
+    )
+}
+
+/// In-memory backends for tests: Workshop pages are a map and downloads
+/// write the given files without SteamCMD
+#[cfg(test)]
+pub mod fake {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct FakeInfo {
+        pub results: Mutex<HashMap<String, ParseResult>>,
+        /// IDs looked up, in order
+        pub fetched: Mutex<Vec<String>>,
+    }
+
+    impl FakeInfo {
+        pub fn item(self, id: &str, title: &str, changelog_id: &str) -> Self {
+            let item = WorkshopItem {
+                id: id.to_string(),
+                title: title.to_string(),
+                changelog_id: changelog_id.to_string(),
+                changelog: String::new(),
+                file_size: None,
+                time_updated: None,
+                required_items: Some(Vec::new()),
+            };
+            self.results
+                .lock()
+                .unwrap()
+                .insert(id.to_string(), ParseResult::Item(item));
+            self
+        }
+    }
+
+    #[async_trait]
+    impl WorkshopInfoProvider for FakeInfo {
+        async fn fetch(&self, workshop_id: &str) -> Result<ParseResult> {
+            self.fetched.lock().unwrap().push(workshop_id.to_string());
+            match self.results.lock().unwrap().get(workshop_id) {
+                Some(result) => Ok(result.clone()),
+                None => anyhow::bail!("no fake result for {}", workshop_id),
+            }
+        }
+    }
+
+    /// What a download of an ID does
+    #[derive(Clone)]
+    pub enum FakeContent {
+        /// Writes the files, paths relative to the item's content directory
+        Files(Vec<(String, String)>),
+        Fail(steamcmd::Failure),
+    }
+
+    #[derive(Default)]
+    pub struct FakeDownloader {
+        pub content: Mutex<HashMap<String, FakeContent>>,
+        pub downloaded: Mutex<Vec<String>>,
+        /// Steam root downloads land in regardless of the install dir, like
+        /// a distro-packaged SteamCMD
+        pub steam_root: Option<PathBuf>,
+    }
+
+    impl FakeDownloader {
+        pub fn files(self, id: &str, files: &[(&str, &str)]) -> Self {
+            let files = files
+                .iter()
+                .map(|(path, data)| (path.to_string(), data.to_string()))
+                .collect();
+            self.content
+                .lock()
+                .unwrap()
+                .insert(id.to_string(), FakeContent::Files(files));
+            self
+        }
+
+        pub fn ignore_install_dir(mut self, steam_root: &Path) -> Self {
+            self.steam_root = Some(steam_root.to_path_buf());
+            self
+        }
+
+        pub fn fail(self, id: &str, failure: steamcmd::Failure) -> Self {
+            self.content
+                .lock()
+                .unwrap()
+                .insert(id.to_string(), FakeContent::Fail(failure));
+            self
+        }
+    }
+
+    #[async_trait]
+    impl ContentDownloader for FakeDownloader {
+        async fn download(&self, appid: &str, workshop_id: &str, install_dir: &Path) -> Result<Outcome> {
+            self.downloaded.lock().unwrap().push(workshop_id.to_string());
+            let content = self.content.lock().unwrap().get(workshop_id).cloned();
+            let files = match content {
+                Some(FakeContent::Files(files)) => files,
+                Some(FakeContent::Fail(failure)) => return Ok(Outcome::Failed(failure)),
+                None => return Ok(Outcome::Failed(steamcmd::Failure::Other("item not found".to_string()))),
+            };
+
+            let install_dir = self.steam_root.as_deref().unwrap_or(install_dir);
+            let dir = steamcmd::content_path(&install_dir.join("steamapps/workshop"), appid, workshop_id);
+            let mut size = 0;
+            for (path, data) in files {
+                let path = dir.join(path);
+                std::fs::create_dir_all(path.parent().unwrap())?;
+                std::fs::write(&path, &data)?;
+                size += data.len() as u64;
+            }
+            Ok(Outcome::Downloaded { path: dir, size })
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use crate::error::NecoError;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tokio::fs;
+
+/// A downloaded collection as last seen on the Workshop. Members keep the
+/// collection's id in their own metadata; this keeps what only the
+/// collection knows.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionRecord {
+    pub title: String,
+    /// Members in Workshop order, including ones that weren't downloaded
+    #[serde(default)]
+    pub item_ids: Vec<String>,
+    pub synced_at: DateTime<Utc>,
+}
+
+pub async fn load(path: &Path) -> Result<BTreeMap<String, CollectionRecord>> {
+    match fs::read_to_string(path).await {
+        Ok(data) => serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e).with_context(|| NecoError::Fs(format!("Failed to read {}", path.display()))),
+    }
+}
+
+pub async fn save(path: &Path, collections: &BTreeMap<String, CollectionRecord>) -> Result<()> {
+    let data = serde_json::to_string_pretty(collections)?;
+    fs::write(path, data)
+        .await
+        .with_context(|| NecoError::Fs(format!("Failed to save {}", path.display())))
+}
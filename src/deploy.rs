@@ -0,0 +1,216 @@
+use crate::error::NecoError;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+#[derive(Debug, Deserialize)]
+pub struct DeployTarget {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: DeployKind,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum DeployKind {
+    /// Plain directory, e.g. another server install on the same machine
+    Local { path: PathBuf },
+    /// `user@host:/path` destination pushed with rsync over SSH
+    Rsync { destination: String },
+    /// `user@host` with a remote base path, pushed with OpenSSH's sftp batch mode
+    Sftp { host: String, path: String },
+}
+
+impl DeployTarget {
+    pub fn describe(&self) -> String {
+        match &self.kind {
+            DeployKind::Local { path } => format!("local {}", path.display()),
+            DeployKind::Rsync { destination } => format!("rsync {}", destination),
+            DeployKind::Sftp { host, path } => format!("sftp {}:{}", host, path),
+        }
+    }
+}
+
+/// Pushes `changed` files from `root` to the target and deletes `removed` ones.
+/// Both lists hold paths relative to `root`, using forward slashes.
+pub async fn push(
+    target: &DeployTarget,
+    root: &Path,
+    changed: &[String],
+    removed: &[String],
+) -> Result<()> {
+    match &target.kind {
+        DeployKind::Local { path } => push_local(path, root, changed, removed).await,
+        DeployKind::Rsync { destination } => {
+            push_rsync(destination, root, changed, removed).await
+        }
+        DeployKind::Sftp { host, path } => push_sftp(host, path, root, changed, removed).await,
+    }
+}
+
+async fn push_local(dest: &Path, root: &Path, changed: &[String], removed: &[String]) -> Result<()> {
+    for rel in changed {
+        let dest_path = dest.join(rel);
+        if let Some(parent) = dest_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::copy(root.join(rel), &dest_path)
+            .await
+            .with_context(|| NecoError::Fs(format!("Failed to copy {} to {}", rel, dest_path.display())))?;
+    }
+
+    for rel in removed {
+        let dest_path = dest.join(rel);
+        if fs::try_exists(&dest_path).await? {
+            fs::remove_file(&dest_path)
+                .await
+                .with_context(|| NecoError::Fs(format!("Failed to remove {}", dest_path.display())))?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn push_rsync(
+    destination: &str,
+    root: &Path,
+    changed: &[String],
+    removed: &[String],
+) -> Result<()> {
+    if !changed.is_empty() {
+        let mut source = root.to_string_lossy().to_string();
+        if !source.ends_with('/') {
+            source.push('/');
+        }
+
+        run_with_stdin(
+            Command::new("rsync").args(["-a", "--files-from=-", &source, destination]),
+            &changed.join("\n"),
+        )
+        .await
+        .context("rsync failed")?;
+    }
+
+    if !removed.is_empty() {
+        let (host, base) = destination
+            .split_once(':')
+            .context("rsync destination must be in the form user@host:/path")?;
+
+        let mut command = Command::new("ssh");
+        command.arg(host).args(["rm", "-f", "--"]);
+        for rel in removed {
+            command.arg(shell_quote(&format!("{}/{}", base.trim_end_matches('/'), rel)));
+        }
+
+        let status = command.status().await.context("Failed to start ssh")?;
+        if !status.success() {
+            anyhow::bail!("ssh exited with {} while removing files", status);
+        }
+    }
+
+    Ok(())
+}
+
+async fn push_sftp(
+    host: &str,
+    base: &str,
+    root: &Path,
+    changed: &[String],
+    removed: &[String],
+) -> Result<()> {
+    let base = base.trim_end_matches('/');
+    let mut batch = String::new();
+
+    for rel in changed {
+        // Leading '-' makes sftp ignore "already exists" errors
+        if let Some((parent, _)) = rel.rsplit_once('/') {
+            let mut dir = base.to_string();
+            for component in parent.split('/') {
+                dir.push('/');
+                dir.push_str(component);
+                batch.push_str(&format!("-mkdir {}\n", sftp_quote(&dir, false)?));
+            }
+        }
+
+        let mut local = root.join(rel).to_string_lossy().to_string();
+        if cfg!(windows) {
+            local = local.replace('\\', "/");
+        }
+        batch.push_str(&format!(
+            "put {} {}\n",
+            sftp_quote(&local, true)?,
+            sftp_quote(&format!("{}/{}", base, rel), false)?
+        ));
+    }
+
+    for rel in removed {
+        batch.push_str(&format!("-rm {}\n", sftp_quote(&format!("{}/{}", base, rel), true)?));
+    }
+
+    if batch.is_empty() {
+        return Ok(());
+    }
+
+    run_with_stdin(Command::new("sftp").args(["-b", "-", host]), &batch)
+        .await
+        .context("sftp failed")
+}
+
+async fn run_with_stdin(command: &mut Command, input: &str) -> Result<()> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Failed to start deploy command")?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes()).await?;
+    }
+
+    let status = child.wait().await?;
+    if !status.success() {
+        anyhow::bail!("exited with {}", status);
+    }
+    Ok(())
+}
+
+/// Quotes a path for an sftp batch file. Arguments sftp expands as globs
+/// (put's local path, rm) get their wildcards escaped. Quotes, backslashes
+/// and line breaks can't be passed reliably and are refused.
+fn sftp_quote(path: &str, glob: bool) -> Result<String> {
+    if path.contains(['"', '\\', '\n', '\r']) {
+        anyhow::bail!("sftp can't transfer {:?}, rename it or use an rsync target", path);
+    }
+    let mut quoted = String::from('"');
+    for c in path.chars() {
+        if glob && matches!(c, '*' | '?' | '[') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    Ok(quoted)
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_sftp_paths() {
+        assert_eq!(sftp_quote("/srv/maps/de dust.bsp", false).unwrap(), r#""/srv/maps/de dust.bsp""#);
+        // Wildcards only need escaping where sftp globs
+        assert_eq!(sftp_quote("/srv/maps/[x]*?.bsp", false).unwrap(), r#""/srv/maps/[x]*?.bsp""#);
+        assert_eq!(sftp_quote("/srv/maps/[x]*?.bsp", true).unwrap(), r#""/srv/maps/\[x]\*\?.bsp""#);
+        assert!(sftp_quote("/srv/maps/a\"b.bsp", false).is_err());
+        assert!(sftp_quote(r"/srv/maps/a\b.bsp", true).is_err());
+        assert!(sftp_quote("/srv/maps/a\nrm b", false).is_err());
+    }
+}
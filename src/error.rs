@@ -0,0 +1,98 @@
+use thiserror::Error;
+
+/// Error categories surfaced through the status file and the exit code.
+/// Errors still travel as anyhow::Error; a NecoError is attached as context
+/// at the failure site and recovered with `kind_of`.
+#[derive(Debug, Error)]
+pub enum NecoError {
+    #[error("{0}")]
+    Config(String),
+    #[error("{0}")]
+    Network(String),
+    #[error("{0}")]
+    SteamCmd(String),
+    #[error("{0}")]
+    Fs(String),
+    #[error("{0}")]
+    NotFound(String),
+    /// The operation would overwrite or clash with existing files or state
+    #[error("{0}")]
+    Conflict(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Config,
+    Network,
+    SteamCmd,
+    Fs,
+    NotFound,
+    Conflict,
+    Other,
+}
+
+impl ErrorKind {
+    /// Stable identifier for the status file
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::Config => "config",
+            ErrorKind::Network => "network",
+            ErrorKind::SteamCmd => "steamcmd",
+            ErrorKind::Fs => "fs",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::Conflict => "conflict",
+            ErrorKind::Other => "other",
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::Config => 2,
+            ErrorKind::Network => 3,
+            ErrorKind::SteamCmd => 4,
+            ErrorKind::Fs => 5,
+            ErrorKind::NotFound => 6,
+            ErrorKind::Conflict => 7,
+        }
+    }
+}
+
+impl NecoError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            NecoError::Config(_) => ErrorKind::Config,
+            NecoError::Network(_) => ErrorKind::Network,
+            NecoError::SteamCmd(_) => ErrorKind::SteamCmd,
+            NecoError::Fs(_) => ErrorKind::Fs,
+            NecoError::NotFound(_) => ErrorKind::NotFound,
+            NecoError::Conflict(_) => ErrorKind::Conflict,
+        }
+    }
+}
+
+/// Category of an error: the outermost NecoError attached to it
+pub fn kind_of(error: &anyhow::Error) -> ErrorKind {
+    error
+        .downcast_ref::<NecoError>()
+        .map_or(ErrorKind::Other, NecoError::kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn kind_comes_from_the_tagged_failure_site() {
+        let io = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        let error = Err::<(), _>(io)
+            .context(NecoError::Fs("Failed to save metadata".to_string()))
+            .context("Failed to install 100")
+            .unwrap_err();
+        assert_eq!(kind_of(&error), ErrorKind::Fs);
+
+        let untagged = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        assert_eq!(kind_of(&anyhow::Error::new(untagged)), ErrorKind::Other);
+    }
+}
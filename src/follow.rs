@@ -0,0 +1,160 @@
+use crate::error::NecoError;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::Path;
+use tokio::fs;
+
+/// Sources `update` checks for items that aren't subscribed yet
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Follows {
+    #[serde(default)]
+    pub authors: Vec<FollowedAuthor>,
+    #[serde(default)]
+    pub queries: Vec<FollowedQuery>,
+    /// Discovered items that wait for `approve` before being downloaded
+    #[serde(default)]
+    pub pending: Vec<PendingItem>,
+}
+
+impl Follows {
+    pub fn is_empty(&self) -> bool {
+        self.authors.is_empty() && self.queries.is_empty()
+    }
+
+    /// Adds an item to the approval queue unless it is already waiting there
+    pub fn queue(&mut self, item: PendingItem) {
+        if !self.pending.iter().any(|p| p.id == item.id) {
+            self.pending.push(item);
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FollowedAuthor {
+    pub steamid: String,
+    /// Profile as entered by the user, for display
+    pub profile: String,
+    pub since: DateTime<Utc>,
+    /// Download new items right away instead of queueing them for approval
+    #[serde(default)]
+    pub auto_approve: bool,
+    /// Items already picked up, so removed ones aren't downloaded again
+    #[serde(default)]
+    pub seen: BTreeSet<String>,
+}
+
+/// A saved Workshop search, e.g. the newest items tagged "Survival"
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FollowedQuery {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub text: String,
+    pub sort: QuerySort,
+    /// Only the first `cap` results are considered on each check
+    pub cap: u32,
+    pub since: DateTime<Utc>,
+    #[serde(default)]
+    pub seen: BTreeSet<String>,
+}
+
+impl FollowedQuery {
+    pub fn new(tags: Vec<String>) -> Self {
+        Self {
+            tags,
+            text: String::new(),
+            sort: QuerySort::Recent,
+            cap: 20,
+            since: Utc::now(),
+            seen: BTreeSet::new(),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.tags.is_empty() {
+            parts.push(format!("tags {}", self.tags.join(", ")));
+        }
+        if !self.text.is_empty() {
+            parts.push(format!("\"{}\"", self.text));
+        }
+        parts.push(format!("{}, top {}", self.sort.as_str(), self.cap));
+        parts.join(" ")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuerySort {
+    Recent,
+    Popular,
+    Trending,
+}
+
+impl QuerySort {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "recent" => Some(QuerySort::Recent),
+            "popular" => Some(QuerySort::Popular),
+            "trending" => Some(QuerySort::Trending),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuerySort::Recent => "recent",
+            QuerySort::Popular => "popular",
+            QuerySort::Trending => "trending",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingItem {
+    pub id: String,
+    pub title: String,
+    /// What discovered the item, for display
+    pub source: String,
+    pub found_at: DateTime<Utc>,
+}
+
+pub async fn load(path: &Path) -> Result<Follows> {
+    match fs::read_to_string(path).await {
+        Ok(data) => serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Follows::default()),
+        Err(e) => Err(e).with_context(|| NecoError::Fs(format!("Failed to read {}", path.display()))),
+    }
+}
+
+pub async fn save(path: &Path, follows: &Follows) -> Result<()> {
+    let data = serde_json::to_string_pretty(follows)?;
+    fs::write(path, data)
+        .await
+        .with_context(|| NecoError::Fs(format!("Failed to save {}", path.display())))
+}
+
+pub enum Profile {
+    SteamId(String),
+    /// Custom URL name, resolved through the Web API
+    Vanity(String),
+}
+
+/// Accepts a SteamID64, a /profiles/ or /id/ community URL or a bare vanity name
+pub fn parse_profile(input: &str) -> Profile {
+    let trimmed = input.trim().trim_end_matches('/');
+
+    if let Some((_, rest)) = trimmed.split_once("/profiles/") {
+        return Profile::SteamId(rest.to_string());
+    }
+    if let Some((_, rest)) = trimmed.split_once("/id/") {
+        return Profile::Vanity(rest.to_string());
+    }
+    if trimmed.len() == 17 && trimmed.parse::<u64>().is_ok() {
+        return Profile::SteamId(trimmed.to_string());
+    }
+    Profile::Vanity(trimmed.to_string())
+}
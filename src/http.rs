@@ -0,0 +1,111 @@
+use reqwest::{RequestBuilder, Response, StatusCode, Url};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Token bucket per host shared by every request NecoDL makes, so bursts
+/// from concurrent checks stay under Steam's rate limits
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Requests per second per host, 0 disables the limit
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Negative while requests are waiting for their turn
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64, burst: u32) -> Arc<Self> {
+        Arc::new(Self {
+            rate,
+            burst: f64::from(burst.max(1)),
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Takes a token for the URL's host, waiting until one is available
+    pub async fn acquire(&self, url: &Url) {
+        if self.rate <= 0.0 {
+            return;
+        }
+
+        let wait = {
+            let mut buckets = self.buckets.lock().unwrap();
+            let now = Instant::now();
+            let bucket = buckets
+                .entry(url.host_str().unwrap_or_default().to_string())
+                .or_insert(Bucket {
+                    tokens: self.burst,
+                    refilled: now,
+                });
+            let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+            bucket.refilled = now;
+
+            // Reserve the token now and wait off the debt, keeping requests in order
+            bucket.tokens -= 1.0;
+            (bucket.tokens < 0.0).then(|| Duration::from_secs_f64(-bucket.tokens / self.rate))
+        };
+
+        if let Some(wait) = wait {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// How often a failed HTTP request is tried again. Connection errors,
+/// timeouts, 429 and 5xx answers are retried, waiting `backoff` and then
+/// twice as long after each further attempt. Every attempt first waits for
+/// the rate limiter.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub backoff: Duration,
+    pub limiter: Arc<RateLimiter>,
+}
+
+impl RetryPolicy {
+    pub async fn send(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        let (client, request) = request.build_split();
+        let request = request?;
+        let mut attempt = 0;
+        loop {
+            // Streaming bodies can't be sent twice
+            let Some(this) = request.try_clone() else {
+                self.limiter.acquire(request.url()).await;
+                return client.execute(request).await;
+            };
+
+            self.limiter.acquire(this.url()).await;
+            let retry = match client.execute(this).await {
+                Ok(response) if attempt < self.retries && is_transient(response.status()) => {
+                    format!("{} returned {}", response.url(), response.status())
+                }
+                Err(e) if attempt < self.retries && (e.is_timeout() || e.is_connect()) => e.to_string(),
+                result => return result,
+            };
+
+            let wait = self.backoff * 2u32.saturating_pow(attempt);
+            attempt += 1;
+            warn!(
+                "{}, retrying in {:.1}s ({}/{})",
+                retry,
+                wait.as_secs_f64(),
+                attempt,
+                self.retries
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
@@ -0,0 +1,49 @@
+use std::fmt;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Prints warnings and errors to stderr the way NecoDL always has: a
+/// `Warning:` or `Error:` prefix, no timestamps or targets
+struct Plain;
+
+impl<S, N> FormatEvent<S, N> for Plain
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        match *event.metadata().level() {
+            Level::ERROR => write!(writer, "Error: ")?,
+            Level::WARN => write!(writer, "Warning: ")?,
+            _ => {}
+        }
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// Installs the global subscriber. Run as a systemd service, events go to
+/// journald with their level as priority; otherwise they're printed to stderr.
+pub fn init() {
+    let targets = Targets::new().with_target(env!("CARGO_CRATE_NAME"), Level::INFO);
+
+    #[cfg(unix)]
+    if std::env::var_os("JOURNAL_STREAM").is_some() {
+        match tracing_journald::layer() {
+            Ok(journald) => {
+                tracing_subscriber::registry().with(journald.with_filter(targets)).init();
+                return;
+            }
+            Err(e) => eprintln!("Warning: journald is unavailable, logging to stderr: {}", e),
+        }
+    }
+
+    let stderr = tracing_subscriber::fmt::layer()
+        .event_format(Plain)
+        .with_writer(std::io::stderr);
+    tracing_subscriber::registry().with(stderr.with_filter(targets)).init();
+}
@@ -1,20 +1,47 @@
-// TODO
-// - reuse steamcmd process
+mod a2s;
+mod acf;
+mod archive;
+mod audit;
+mod backend;
+mod collection;
+mod deploy;
+mod error;
+mod follow;
+mod http;
+mod logging;
+mod page;
+mod platform;
+mod rcon;
+mod report;
+mod resume;
+mod schedule;
+mod steam_api;
+mod steamcmd;
+mod throughput;
+#[cfg(test)]
+mod testutil;
 
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use globset::{Glob, GlobSet, GlobSetBuilder};
-use once_cell::sync::Lazy;
+use backend::{ParseResult, WorkshopCollection, WorkshopItem};
+use error::NecoError;
+use chrono::{DateTime, Utc};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
 use rustyline::{Editor, error::ReadlineError};
-use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{IsTerminal, Write};
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::Arc;
 use tokio::fs;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::io::AsyncReadExt;
 use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 use tokio::time::Duration;
+use tracing::{error, warn};
 use path_clean::PathClean;
 
 #[derive(Parser)]
@@ -23,54 +50,597 @@ use path_clean::PathClean;
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// Answer yes to all confirmation prompts
+    #[arg(short, long, global = true, env = "NECODL_YES")]
+    yes: bool,
+    /// Directory holding config.toml and the state files, instead of the
+    /// executable's. Also becomes the working directory.
+    #[arg(short = 'C', long, global = true, env = "NECODL_DIR", value_name = "DIR")]
+    chdir: Option<PathBuf>,
+    /// Keep config, state and SteamCMD in necodl-data/ next to the executable
+    /// and never look in the home directory
+    #[arg(long, global = true, env = "NECODL_PORTABLE", conflicts_with = "chdir")]
+    portable: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     Download {
-        workshop_id: String,
+        /// Several IDs are downloaded in one batch without prompts
+        #[arg(required = true)]
+        workshop_id: Vec<String>,
+        /// Download again from Steam even if up-to-date
         #[arg(short, long)]
         force: bool,
+        /// Install again from the cached download, without contacting Steam
+        #[arg(long, conflicts_with = "force")]
+        reinstall: bool,
+        /// What to do if the item is already subscribed: update, verify or skip
+        #[arg(long, value_name = "ACTION")]
+        existing: Option<String>,
+        /// Download under this appid instead of the configured one, also
+        /// for future updates
+        #[arg(long)]
+        appid: Option<String>,
     },
     Update {
         #[arg(short, long)]
         force: bool,
+        #[arg(long)]
+        trust_changelog: bool,
+        #[arg(long, conflicts_with = "trust_changelog")]
+        verify: bool,
+        #[arg(long)]
+        report: Option<String>,
+        #[arg(long)]
+        by_size: bool,
+        #[arg(long)]
+        max_size: Option<String>,
+        #[arg(long)]
+        only: Option<usize>,
+        /// Only report available updates, without downloading
+        #[arg(long)]
+        check: bool,
+        /// Download updates even if auto_update is off
+        #[arg(long, conflicts_with = "check")]
+        apply: bool,
+        /// Continue an interrupted update or collection download
+        #[arg(long, conflicts_with = "check")]
+        resume: bool,
     },
     List {
         #[arg(short, long)]
         verbose: bool,
+        /// List downloaded collections instead of items
+        #[arg(short, long)]
+        collections: bool,
+        #[arg(long)]
+        format: Option<String>,
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Only items downloaded under this appid
+        #[arg(long)]
+        appid: Option<String>,
     },
     Remove {
         workshop_id: String,
+        /// Remove items only the collection brought in without asking
+        #[arg(long, conflicts_with = "keep_orphans")]
+        cascade: bool,
+        /// Keep items only the collection brought in as standalone items
+        #[arg(long)]
+        keep_orphans: bool,
+        /// Remove items other tracked items require
+        #[arg(short, long)]
+        force: bool,
+    },
+    Info {
+        #[arg(long)]
+        recalculate: bool,
+        #[arg(long)]
+        json: bool,
+        /// config, paths, subs, server or storage; can be repeated
+        #[arg(long)]
+        section: Vec<String>,
+    },
+    /// Check the environment and time requests to Steam
+    Doctor {
+        /// Skip the SteamCMD login test
+        #[arg(long)]
+        no_steamcmd: bool,
     },
-    Info,
     Import {
+        #[arg(required_unless_present = "steam_user")]
+        path: Option<String>,
+        #[arg(long)]
+        download: bool,
+        #[arg(long, conflicts_with = "path")]
+        steam_user: Option<String>,
+        #[arg(long, requires = "steam_user")]
+        subscriptions: bool,
+        #[arg(long, requires = "steam_user")]
+        favorites: bool,
+    },
+    Export {
+        path: Option<String>,
+        #[arg(long, default_value = "json")]
+        format: String,
+    },
+    Open {
+        workshop_id: String,
+        #[arg(short, long)]
+        copy: bool,
+    },
+    Diff,
+    WhereUsed {
+        query: String,
+    },
+    History {
+        filter: Option<String>,
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    Undo,
+    Archive {
+        workshop_id: String,
+    },
+    Unarchive {
+        workshop_id: String,
+    },
+    Report {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    Prune {
+        /// Items marked unavailable on the Workshop for longer than prune_grace_days
+        #[arg(long, required = true)]
+        unavailable: bool,
+        /// Archive them instead of removing them
+        #[arg(long, conflicts_with = "keep_files")]
+        archive: bool,
+        /// Stop tracking them but leave their files in place
+        #[arg(long)]
+        keep_files: bool,
+        /// Override prune_grace_days
+        #[arg(long)]
+        grace: Option<u32>,
+    },
+    Verify {
+        workshop_id: Option<String>,
+    },
+    /// Run `update` on an interval until SIGTERM
+    Daemon {
+        /// Seconds between updates
+        #[arg(short = 'n', long, default_value_t = 3600)]
+        interval: u64,
+        /// Notify systemd of readiness and ping its watchdog
+        #[arg(long)]
+        systemd: bool,
+    },
+    /// Move managed files to a new output_dir and update config.toml
+    MoveOutput {
         path: String,
     },
+    /// List subscriptions that look like re-uploads of each other
+    Dedupe {
+        /// Move collections, order and group to the newer ID and remove the older one
+        #[arg(long)]
+        merge: bool,
+    },
+    Stats,
+    Status {
+        workshop_id: String,
+        #[arg(long)]
+        offline: bool,
+    },
+    Follow {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    Review,
+    Approve {
+        ids: Vec<String>,
+    },
+    Reject {
+        ids: Vec<String>,
+    },
+    Search {
+        #[arg(required = true)]
+        text: Vec<String>,
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: u32,
+    },
+    Order {
+        args: Vec<String>,
+    },
+    Deploy {
+        targets: Vec<String>,
+        #[arg(long)]
+        full: bool,
+        /// Deploy even outside the update windows
+        #[arg(long)]
+        now: bool,
+    },
+    #[command(external_subcommand)]
+    Alias(Vec<String>),
 }
 
-static TITLE_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse(".workshopItemTitle").unwrap());
-static CHANGELOG_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse(".changeLogCtn p[id]").unwrap());
-static ITEM_SELECTOR: Lazy<Selector> =
-    Lazy::new(|| Selector::parse(r#"[id^="sharedfile_"]"#).unwrap());
+/// Directory next to the executable that `--portable` keeps everything in
+const PORTABLE_DIR: &str = "necodl-data";
+
+const BUILTIN_COMMANDS: &[&str] = &[
+    "download", "update", "list", "remove", "info", "import", "export", "open", "order", "history", "stats", "status", "undo", "archive", "unarchive", "prune", "dedupe", "move-output", "report", "verify", "where-used", "search", "follow", "review", "approve", "reject", "diff", "watch", "daemon", "config", "whitelist", "deploy", "doctor", "help", "exit", "quit",
+];
+
+/// Commands that change installed items or NecoDL's state. Only their runs
+/// are written to the status file, so a `list` or `info` doesn't hide the
+/// result of the last update from monitoring.
+const STATE_COMMANDS: &[&str] = &[
+    "download", "update", "remove", "import", "order", "undo", "archive", "unarchive", "prune", "dedupe", "move-output", "follow", "approve", "reject", "daemon", "deploy",
+];
 
 #[derive(Debug, Deserialize)]
 struct Config {
     appid: String,
+    /// Detected automatically when unset or not found
+    #[serde(default)]
     steam_cmd: String,
     output_dir: String,
+    #[serde(default)]
     whitelist: Vec<String>,
+    /// Install every file regardless of whitelist
+    #[serde(default)]
+    allow_all: bool,
+    /// Match whitelist patterns regardless of case, `Maps/` like `maps/`
+    #[serde(default)]
+    whitelist_ignore_case: bool,
+    /// Let whitelist patterns match at any depth, as if prefixed with `**/`
+    #[serde(default)]
+    whitelist_any_depth: bool,
+    #[serde(default)]
+    alias: HashMap<String, String>,
+    server: Option<ServerConfig>,
+    default_command: Option<String>,
+    status_file: Option<String>,
+    checksums_file: Option<String>,
+    #[serde(default)]
+    collection_dirs: HashMap<String, String>,
+    /// Subdirectory of output_dir for items of another appid than `appid`
+    #[serde(default)]
+    appid_dirs: HashMap<String, String>,
+    /// Top-level content directory (e.g. "materials") to the output root its
+    /// files go to instead of output_dir
+    #[serde(default)]
+    content_dirs: BTreeMap<String, String>,
+    #[serde(default)]
+    interleave_groups: bool,
+    /// Files larger than this are not installed, e.g. "500MB"
+    max_file_size: Option<String>,
+    /// Per-item max_file_size overrides, keyed by workshop ID
+    #[serde(default)]
+    max_file_size_items: HashMap<String, String>,
+    /// What to do when an update would replace files changed locally
+    #[serde(default)]
+    modified_files: ModifiedFilesPolicy,
+    /// What to do with empty (0-byte) files in downloaded content
+    #[serde(default)]
+    zero_byte_files: ZeroBytePolicy,
+    /// Hard link files whose content another item already installed
+    #[serde(default)]
+    dedupe: bool,
+    /// Keep each item's last SteamCMD download for `download --reinstall`
+    #[serde(default)]
+    keep_download_cache: bool,
+    /// Store metadata.json and audit.log compressed with zstd
+    #[serde(default)]
+    compress_state: bool,
+    #[serde(default)]
+    trust_changelog: bool,
+    /// When off, `update` only reports available updates unless --apply
+    #[serde(default = "default_auto_update")]
+    auto_update: bool,
+    /// URL that gets a JSON POST when `update` finds updates it doesn't apply
+    notify_webhook: Option<String>,
+    /// Daily time ranges like "04:00-06:00" in which `update` downloads and
+    /// `deploy` pushes; outside them `update` only checks
+    #[serde(default)]
+    update_windows: Vec<String>,
+    /// Days an item stays unavailable before `prune --unavailable` drops it
+    #[serde(default = "default_prune_grace_days")]
+    prune_grace_days: u32,
+    /// Platform whose version of the content SteamCMD downloads, instead of
+    /// the one NecoDL runs on
+    platform: Option<ContentPlatform>,
+    /// Passed to SteamCMD ahead of its download commands, e.g. "-textmode"
+    /// or "+@sSteamCmdForcePlatformType windows"; entries split on whitespace
+    #[serde(default)]
+    steamcmd_extra_args: Vec<String>,
+    /// Number of separate SteamCMD install dirs, and so of downloads that
+    /// run at the same time
+    #[serde(default = "default_steamcmd_instances")]
+    steamcmd_instances: usize,
+    /// Extra attempts for collection items that failed with a timeout or a
+    /// lost connection, made at the end of the collection
+    #[serde(default = "default_item_retries")]
+    item_retries: u32,
+    /// Seconds before the first round of item retries, doubled after each
+    #[serde(default = "default_item_retry_delay")]
+    item_retry_delay: u64,
+    steam_api_key: Option<String>,
+    /// Octal mode for installed files, e.g. "644" (Unix)
+    file_mode: Option<String>,
+    /// Octal mode for directories created while installing (Unix)
+    dir_mode: Option<String>,
+    /// User and group installed files are chowned to, by name or id (Unix)
+    owner: Option<String>,
+    group: Option<String>,
+    #[serde(default)]
+    map_list: MapListConfig,
+    #[serde(default)]
+    outputs: Vec<OutputConfig>,
+    /// The server's own map rotation files (mapcycle.txt, maplist.txt),
+    /// relative to output_dir unless absolute, for `report unused`
+    #[serde(default)]
+    map_cycles: Vec<String>,
+    #[serde(default)]
+    deploy: Vec<deploy::DeployTarget>,
+    #[serde(default)]
+    http: HttpConfig,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Settings of the client used for Steam pages, the Web API and webhooks
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct HttpConfig {
+    /// Seconds before a request is abandoned
+    timeout: u64,
+    /// Attempts after a failed request, see http::RetryPolicy
+    retries: u32,
+    /// Seconds to wait before the first retry, doubled for each further one
+    retry_backoff: f64,
+    user_agent: String,
+    /// Requests per second to any one host, 0 for no limit
+    rate_limit: f64,
+    /// Requests a host may get at once before rate_limit applies
+    burst: u32,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            timeout: 30,
+            retries: 0,
+            retry_backoff: 1.0,
+            user_agent: format!("NecoDL/{}", env!("CARGO_PKG_VERSION")),
+            rate_limit: 2.0,
+            burst: 10,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct MapListConfig {
+    enabled: bool,
+    /// Relative to output_dir unless absolute
+    path: String,
+    root_key: String,
+    indent: String,
+    separator: String,
+    /// Write item attributes set with `order attr` as nested keys
+    attributes: bool,
+}
+
+impl Default for MapListConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: "workshop_maps.txt".to_string(),
+            root_key: "WorkshopMaps".to_string(),
+            indent: "\t".to_string(),
+            separator: "\t\t".to_string(),
+            attributes: false,
+        }
+    }
+}
+
+/// Additional generated map list, declared with `[[outputs]]`
+#[derive(Debug, Clone, Deserialize)]
+struct OutputConfig {
+    /// Relative to output_dir unless absolute
+    path: String,
+    #[serde(default)]
+    format: OutputFormat,
+    #[serde(default = "default_root_key")]
+    root_key: String,
+    #[serde(default = "default_indent")]
+    indent: String,
+    #[serde(default = "default_separator")]
+    separator: String,
+    /// Write item attributes set with `order attr` as nested keys
+    #[serde(default)]
+    attributes: bool,
+    /// Only list maps of items downloaded under this appid
+    appid: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    /// `"Root" { "map" "id" }` as read by the NecoDL SourceMod plugin
+    #[default]
+    KeyValues,
+    /// One map name per line, e.g. for mapcycle.txt
+    List,
+    /// Ultimate Mapchooser's umc_mapcycle.txt, maps grouped by their
+    /// `order group`, else their collection
+    Umc,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ModifiedFilesPolicy {
+    /// Update, keeping the modified files in trash/ for `undo`
+    #[default]
+    Backup,
+    /// Leave the item at its current version
+    Skip,
+    /// Ask before updating
+    Prompt,
+    /// Update without mentioning it
+    Overwrite,
+}
+
+/// Empty files are usually placeholders left in an upload, which break the
+/// server when they replace real assets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ZeroBytePolicy {
+    /// Install them and list them at the end of the run
+    #[default]
+    Warn,
+    /// Leave them out of the install
+    Skip,
+    /// Install them without mentioning it
+    Allow,
+}
+
+/// Value of SteamCMD's `@sSteamCmdForcePlatformType`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ContentPlatform {
+    Windows,
+    Linux,
+}
+
+impl ContentPlatform {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ContentPlatform::Windows => "windows",
+            ContentPlatform::Linux => "linux",
+        }
+    }
+}
+
+/// Options of `update`, see WorkshopManager::update_all
+#[derive(Debug, Clone, Copy, Default)]
+struct UpdateOptions {
+    force: bool,
+    trust_changelog: bool,
+    /// Only report available updates
+    check: bool,
+    /// Download even if auto_update is off or outside the update windows
+    apply: bool,
+    /// Smallest updates first
+    by_size: bool,
+    max_size: Option<u64>,
+    only: Option<usize>,
+    /// Continue the interrupted run instead
+    resume: bool,
+}
+
+/// What `download` does with an item that is already subscribed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExistingAction {
+    /// Go through the regular download, which only fetches what changed
+    Update,
+    /// Check the installed files instead of downloading
+    Verify,
+    Skip,
+}
+
+impl ExistingAction {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "update" => Some(ExistingAction::Update),
+            "verify" => Some(ExistingAction::Verify),
+            "skip" => Some(ExistingAction::Skip),
+            _ => None,
+        }
+    }
+}
+
+fn default_auto_update() -> bool {
+    true
+}
+
+fn default_prune_grace_days() -> u32 {
+    30
+}
+
+fn default_item_retries() -> u32 {
+    2
+}
+
+fn default_steamcmd_instances() -> usize {
+    1
+}
+
+fn default_item_retry_delay() -> u64 {
+    30
+}
+
+fn default_root_key() -> String {
+    MapListConfig::default().root_key
+}
+
+fn default_indent() -> String {
+    MapListConfig::default().indent
+}
+
+fn default_separator() -> String {
+    MapListConfig::default().separator
+}
+
+struct GeneratedOutput {
+    path: PathBuf,
+    config: OutputConfig,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerConfig {
+    address: String,
+    rcon_address: Option<String>,
+    rcon_password: Option<String>,
+    #[serde(default)]
+    post_update_commands: Vec<String>,
+}
+
+/// Last known size of output_dir, so `info` doesn't have to walk the whole
+/// tree. Adjusted as the manager adds and removes files.
+#[derive(Debug, Serialize, Deserialize)]
+struct SizeCache {
+    bytes: u64,
+    calculated_at: DateTime<Utc>,
+}
+
+/// Tracked files with the same content as another tracked file at a
+/// different location: those hard linked to it, which take no extra space,
+/// and those still stored as separate copies
+#[derive(Debug, Default)]
+struct SharedContent {
+    linked: usize,
+    saved_bytes: u64,
+    copies: usize,
+    copy_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct FileInfo {
     path: String,
     hash: String,
+    #[serde(default)]
+    size: u64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    sha256: String,
+    /// content_dirs entry the file was placed under, output_dir if none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    root: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct WorkshopMetadata {
     title: String,
     changelog_id: String,
@@ -78,129 +648,702 @@ struct WorkshopMetadata {
     files: Vec<FileInfo>,
     #[serde(default)]
     collection_ids: Vec<String>,
+    #[serde(default)]
+    updated_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    unavailable: Option<Unavailable>,
+    /// When the item's files were compressed into archive/ by `archive`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    archived: Option<DateTime<Utc>>,
+    /// Newer version found by `update --check`, until the item is updated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    update_available: Option<AvailableUpdate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    order: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    /// Size and last update as declared by the Workshop
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    time_updated: Option<DateTime<Utc>>,
+    /// Earlier titles, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    previous_titles: Vec<TitleChange>,
+    /// The Workshop's "Required items", known when steam_api_key is set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    requires: Vec<String>,
+    /// Forced platform the content was downloaded for, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    platform: Option<String>,
+    /// Appid the item is downloaded under when it isn't the configured one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    appid: Option<String>,
+    /// Files of the last download the whitelist rejected
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    skipped_files: Vec<String>,
+    /// Extra keys listed with the item's maps, e.g. a display name for a
+    /// plugin's map vote
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    attributes: BTreeMap<String, String>,
 }
 
-struct WorkshopItem {
-    id: String,
+/// Renames kept per item, older ones are dropped
+const TITLE_HISTORY: usize = 5;
+
+impl WorkshopMetadata {
+    /// Sets the title, remembering the old one. Returns whether it changed.
+    fn rename(&mut self, title: &str) -> bool {
+        if self.title == title {
+            return false;
+        }
+        if !self.title.is_empty() {
+            self.previous_titles.push(TitleChange {
+                title: std::mem::replace(&mut self.title, title.to_string()),
+                until: Utc::now(),
+            });
+            let excess = self.previous_titles.len().saturating_sub(TITLE_HISTORY);
+            self.previous_titles.drain(..excess);
+        } else {
+            self.title = title.to_string();
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TitleChange {
     title: String,
+    /// When the item was found to be renamed
+    until: DateTime<Utc>,
+}
+
+/// Set when the Workshop page was removed, banned or made private.
+/// Local files are kept untouched until the item is removed explicitly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Unavailable {
+    reason: String,
+    since: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AvailableUpdate {
     changelog_id: String,
+    #[serde(default)]
+    changelog: String,
+    found_at: DateTime<Utc>,
+}
+
+/// One line of `info`: `label: text` on the terminal, `key: value` with
+/// --json. Fields without a label only appear in JSON.
+struct InfoField {
+    key: &'static str,
+    label: String,
+    text: String,
+    value: serde_json::Value,
+}
+
+impl InfoField {
+    fn new(
+        key: &'static str,
+        label: &str,
+        text: impl std::fmt::Display,
+        value: impl Into<serde_json::Value>,
+    ) -> Self {
+        Self {
+            key,
+            label: label.to_string(),
+            text: text.to_string(),
+            value: value.into(),
+        }
+    }
+
+    fn hidden(key: &'static str, value: impl Into<serde_json::Value>) -> Self {
+        Self::new(key, "", "", value)
+    }
+}
+
+/// What moving an item's downloaded content into place did with each file
+#[derive(Default)]
+struct MovedFiles {
+    files: Vec<FileInfo>,
+    /// Left out for exceeding max_file_size, with their size
+    oversized: Vec<(String, u64)>,
+    /// Left out by the whitelist
+    rejected: Vec<String>,
+    /// Empty files, left out or installed depending on zero_byte_files
+    empty: Vec<String>,
+    /// The content directory was gone by the time files were moved
+    source_missing: bool,
+}
+
+/// Why a download left an item without any installed files
+#[derive(Debug)]
+enum EmptyDownload {
+    /// SteamCMD reported a path outside the expected workshop layout
+    Elsewhere { reported: PathBuf, expected: PathBuf },
+    /// SteamCMD finished but the content is in none of the known locations
+    NotFound,
+    /// The content directory disappeared before it could be moved
+    SourceMissing,
+    /// The item itself has no files
+    EmptyItem,
+    AllRejected { count: usize, whitelist_empty: bool },
+    AllOversized(usize),
+    /// Every file was empty and zero_byte_files is "skip"
+    AllEmpty(usize),
+}
+
+impl EmptyDownload {
+    /// Stable identifier for the status file
+    fn kind(&self) -> &'static str {
+        match self {
+            EmptyDownload::Elsewhere { .. } => "content_elsewhere",
+            EmptyDownload::NotFound => "content_not_found",
+            EmptyDownload::SourceMissing => "content_missing",
+            EmptyDownload::EmptyItem => "empty_item",
+            EmptyDownload::AllRejected { .. } => "whitelist_rejected",
+            EmptyDownload::AllOversized(_) => "too_large",
+            EmptyDownload::AllEmpty(_) => "empty_files",
+        }
+    }
+
+    fn classify(moved: &MovedFiles, whitelist_empty: bool) -> Self {
+        if moved.source_missing {
+            EmptyDownload::SourceMissing
+        } else if !moved.rejected.is_empty() {
+            EmptyDownload::AllRejected {
+                count: moved.rejected.len(),
+                whitelist_empty,
+            }
+        } else if !moved.oversized.is_empty() {
+            EmptyDownload::AllOversized(moved.oversized.len())
+        } else if !moved.empty.is_empty() {
+            EmptyDownload::AllEmpty(moved.empty.len())
+        } else {
+            EmptyDownload::EmptyItem
+        }
+    }
+}
+
+impl std::fmt::Display for EmptyDownload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmptyDownload::Elsewhere { reported, expected } => write!(
+                f,
+                "SteamCMD downloaded to {}, expected {}",
+                reported.display(),
+                expected.display()
+            ),
+            EmptyDownload::NotFound => write!(f, "downloaded files not found at expected location"),
+            EmptyDownload::SourceMissing => {
+                write!(f, "downloaded files disappeared before they could be moved")
+            }
+            EmptyDownload::EmptyItem => write!(f, "the Workshop item contains no files"),
+            EmptyDownload::AllRejected { count, whitelist_empty: true } => {
+                write!(f, "all {} files rejected, the whitelist is empty", count)
+            }
+            EmptyDownload::AllRejected { count, .. } => write!(
+                f,
+                "all {} files rejected by the whitelist, see 'whitelist test'",
+                count
+            ),
+            EmptyDownload::AllOversized(count) => {
+                write!(f, "all {} files exceed max_file_size", count)
+            }
+            EmptyDownload::AllEmpty(count) => {
+                write!(f, "all {} files are empty, see zero_byte_files", count)
+            }
+        }
+    }
+}
+
+/// A sandbox reserved for one item, with the download download_ahead
+/// already started in it, if any
+struct SandboxJob {
+    sandbox: steamcmd::Sandbox,
+    ahead: Option<AheadDownload>,
+}
+
+/// A SteamCMD download running in a spare sandbox while earlier items are
+/// installed, with how long it took once done
+struct AheadDownload {
+    started_at: std::time::SystemTime,
+    task: tokio::task::JoinHandle<(Result<steamcmd::Outcome>, Duration)>,
+}
+
+/// A file left out of an install for exceeding max_file_size
+struct SkippedFile {
+    id: String,
+    path: String,
+    size: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct FailedItem {
+    id: String,
+    reason: String,
+    /// Failure category for SteamCMD errors and empty downloads, see
+    /// steamcmd::Failure::kind and EmptyDownload::kind
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<&'static str>,
+    /// Set when the item still failed after being retried
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attempts: Option<u32>,
+}
+
+impl FailedItem {
+    /// Whether trying again later may succeed
+    fn is_transient(&self) -> bool {
+        matches!(self.kind, Some("timeout" | "no_connection"))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RunStatus<'a> {
+    command: &'a str,
+    finished_at: DateTime<Utc>,
+    result: &'a str,
+    error: Option<String>,
+    /// Category of `error`, see error::ErrorKind::as_str
+    error_kind: Option<&'static str>,
+    updated_items: Vec<&'a str>,
+    failed_items: &'a [FailedItem],
+    /// Set by commands that downloaded or updated items
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<report::RunTotals>,
+    /// Per-item outcome of those commands
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    items: &'a [report::ItemResult],
+}
+
+/// Written by a running daemon for `info`, removed when it stops
+#[derive(Debug, Serialize, Deserialize)]
+struct DaemonStatus {
+    pid: u32,
+    started_at: DateTime<Utc>,
+    interval: u64,
+    /// Unset while an update runs
+    next_run: Option<DateTime<Utc>>,
+}
+
+/// Portable list of subscriptions, free of machine-specific paths
+#[derive(Debug, Serialize, Deserialize)]
+struct SubscriptionSet {
+    appid: String,
+    items: Vec<SubscriptionEntry>,
 }
 
-struct WorkshopCollection {
+#[derive(Debug, Serialize, Deserialize)]
+struct SubscriptionEntry {
     id: String,
+    #[serde(default)]
     title: String,
-    item_ids: Vec<String>,
+    #[serde(default)]
+    collection_ids: Vec<String>,
+}
+
+/// A map and the files that belong to it (navmesh, map scripts, overviews...)
+struct MapFiles<'a> {
+    name: String,
+    bsp: &'a FileInfo,
+    companions: Vec<&'a FileInfo>,
 }
 
-enum ParseResult {
-    Item(WorkshopItem),
-    Collection(WorkshopCollection),
+impl MapFiles<'_> {
+    fn has_navmesh(&self) -> bool {
+        self.companions
+            .iter()
+            .any(|f| f.path.to_lowercase().ends_with(".nav"))
+    }
 }
 
 pub struct WorkshopManager {
     config: Config,
     paths: PathManager,
-    metadata: HashMap<String, WorkshopMetadata>,
-    client: reqwest::Client,
+    metadata: BTreeMap<String, WorkshopMetadata>,
+    collections: BTreeMap<String, collection::CollectionRecord>,
+    api: steam_api::SteamApi,
+    backends: backend::Backends,
+    http: reqwest::Client,
+    retry: http::RetryPolicy,
+    sandboxes: Arc<steamcmd::SandboxPool>,
+    /// Downloads started ahead of their turn, by workshop ID
+    ahead: HashMap<String, SandboxJob>,
+    permissions: platform::InstallPermissions,
     whitelist: Option<GlobSet>,
+    updates: Vec<report::ItemUpdate>,
+    failed_items: Vec<FailedItem>,
+    skipped_files: Vec<SkippedFile>,
+    /// Files the whitelist rejected per item in this run
+    rejected_files: Vec<(String, Vec<String>)>,
+    /// Empty files per item in this run, see zero_byte_files
+    empty_files: Vec<(String, Vec<String>)>,
+    /// Items synced in this run, collections sharing them only get attached
+    processed: HashSet<String>,
+    /// Appids given with `download --appid` for items not recorded yet
+    appid_overrides: HashMap<String, String>,
+    stats: report::RunStats,
+    /// Size and duration of recent SteamCMD downloads
+    throughput: Vec<throughput::Sample>,
+    /// Map the game server was on when first asked in this command
+    live_map: tokio::sync::OnceCell<Option<String>>,
+    assume_yes: bool,
 }
 
 struct PathManager {
+    base_dir: PathBuf,
     local_files: PathBuf,
+    content_roots: BTreeMap<String, PathBuf>,
     steamcmd: PathBuf,
+    /// Directory the SteamCMD sandboxes are created in
+    steamcmd_root: PathBuf,
+    /// Steam roots a distro-packaged SteamCMD downloads into on its own.
+    /// Content is only copied from there, never moved or cleaned up.
+    distro_steam_roots: Vec<PathBuf>,
     metadata_file: PathBuf,
-    workshop_maps_file: PathBuf,
+    outputs: Vec<GeneratedOutput>,
+    map_cycles: Vec<PathBuf>,
+    deploy_state_file: PathBuf,
+    audit_log: PathBuf,
+    trash_dir: PathBuf,
+    archive_dir: PathBuf,
+    size_cache_file: PathBuf,
+    follows_file: PathBuf,
+    collections_file: PathBuf,
+    /// ETag/Last-Modified of fetched changelog pages
+    page_cache_file: PathBuf,
+    throughput_file: PathBuf,
+    /// Progress of the current update or collection download
+    run_state_file: PathBuf,
+    /// Interactive prompt history, kept with the rest of the state so each
+    /// install (-C directory) has its own
+    history_file: PathBuf,
+    status_file: Option<PathBuf>,
+    checksums_file: Option<PathBuf>,
+    /// DaemonStatus of a running daemon, next to the lock it holds
+    daemon_file: PathBuf,
 }
 
 impl PathManager {
-    fn new(config: &Config) -> Result<Self> {
-        let exe_dir = std::env::current_exe()?
-            .parent()
-            .context("Executable has no parent dir")?
-            .to_path_buf()
-            .clean();
+    fn new(config: &Config, base_dir: &Path) -> Result<Self> {
+        let base_dir = base_dir.to_path_buf();
+        let local_files = base_dir.join(&config.output_dir).clean();
+        let map_list = &config.map_list;
+        let default_output = map_list.enabled.then(|| OutputConfig {
+            path: map_list.path.clone(),
+            format: OutputFormat::KeyValues,
+            root_key: map_list.root_key.clone(),
+            indent: map_list.indent.clone(),
+            separator: map_list.separator.clone(),
+            attributes: map_list.attributes,
+            appid: None,
+        });
+
+        let outputs = default_output
+            .into_iter()
+            .chain(config.outputs.iter().cloned())
+            .map(|output| GeneratedOutput {
+                path: local_files.join(&output.path).clean(),
+                config: output,
+            })
+            .collect();
+
+        let checksums_file = config
+            .checksums_file
+            .as_ref()
+            .map(|p| local_files.join(p).clean());
 
-        let local_files = exe_dir.join(&config.output_dir).clean();
-        let workshop_maps = local_files.join("workshop_maps.txt").clean();
+        let steamcmd = Self::find_steamcmd(config, &base_dir);
+        // A distro wrapper script sits in a system directory, keep the
+        // sandboxes next to the executable instead
+        let (steamcmd_root, distro_steam_roots) = if platform::is_wrapper(&steamcmd) {
+            (base_dir.join("steamcmd"), platform::distro_steam_roots())
+        } else {
+            (steamcmd.parent().unwrap_or(&base_dir).to_path_buf(), Vec::new())
+        };
+
+        let map_cycles = config.map_cycles.iter().map(|p| local_files.join(p).clean()).collect();
+
+        let content_roots = config
+            .content_dirs
+            .iter()
+            .map(|(category, dir)| (category.to_lowercase(), base_dir.join(dir).clean()))
+            .collect();
 
         Ok(Self {
             local_files,
-            steamcmd: exe_dir.join(&config.steam_cmd).clean(),
-            metadata_file: exe_dir.join("metadata.json").clean(),
-            workshop_maps_file: workshop_maps,
+            content_roots,
+            steamcmd,
+            steamcmd_root,
+            distro_steam_roots,
+            metadata_file: base_dir.join("metadata.json").clean(),
+            outputs,
+            map_cycles,
+            deploy_state_file: base_dir.join("deploy_state.json").clean(),
+            audit_log: base_dir.join("audit.log").clean(),
+            trash_dir: base_dir.join("trash").clean(),
+            archive_dir: base_dir.join("archive").clean(),
+            size_cache_file: base_dir.join("size_cache.json").clean(),
+            follows_file: base_dir.join("follows.json").clean(),
+            collections_file: base_dir.join("collections.json").clean(),
+            page_cache_file: base_dir.join("page_cache.json").clean(),
+            throughput_file: base_dir.join("throughput.json").clean(),
+            run_state_file: base_dir.join("run_state.json").clean(),
+            history_file: base_dir.join(".history").clean(),
+            status_file: config.status_file.as_ref().map(|p| base_dir.join(p).clean()),
+            checksums_file,
+            daemon_file: base_dir.join("daemon.json").clean(),
+            base_dir,
         })
     }
 
-    fn steamcmd_workshop_path(&self, appid: &str, workshop_id: &str) -> PathBuf {
-        self.steamcmd
-            .parent()
-            .expect("SteamCMD path has parent") 
-            .join("necodl/steamapps/workshop/content")
-            .join(appid)
-            .join(workshop_id)
+    /// The configured steam_cmd, or a detected SteamCMD if it is unset or
+    /// doesn't exist
+    fn find_steamcmd(config: &Config, base_dir: &Path) -> PathBuf {
+        let configured = (!config.steam_cmd.trim().is_empty())
+            .then(|| platform::resolve_steamcmd(&base_dir.join(&config.steam_cmd).clean()));
+        if let Some(path) = &configured
+            && path.is_file()
+        {
+            return path.clone();
+        }
+
+        match (platform::detect_steamcmd(), configured) {
+            (Some(found), configured) => {
+                // A missing steam_cmd is worth a warning every time, a detected
+                // one only when detection picks a different path than before
+                let marker = base_dir.join("steamcmd_detected.txt");
+                let known = std::fs::read_to_string(&marker)
+                    .is_ok_and(|previous| Path::new(previous.trim()) == found);
+                match configured {
+                    Some(path) => warn!(
+                        "SteamCMD not found at {}, using {}",
+                        path.display(),
+                        found.display()
+                    ),
+                    None if known => return found,
+                    None => {
+                        println!("Using SteamCMD found at {}", found.display());
+                        let _ = std::fs::write(&marker, found.to_string_lossy().as_bytes());
+                    }
+                }
+                println!("Set steam_cmd = {:?} in config.toml to keep this", found.display().to_string());
+                found
+            }
+            // Reported once SteamCMD is needed, commands like list still work
+            (None, Some(path)) => path,
+            (None, None) => PathBuf::from(platform::STEAMCMD_NAMES[0]),
+        }
+    }
+
+    /// Where a tracked file lives on disk
+    fn file_path(&self, file_info: &FileInfo) -> PathBuf {
+        file_info
+            .root
+            .as_ref()
+            .and_then(|category| self.content_roots.get(category))
+            .unwrap_or(&self.local_files)
+            .join(&file_info.path)
+    }
+
+    /// content_dirs entry for a path relative to the item's content, matched
+    /// on its top-level directory
+    fn content_root(&self, rel_path: &Path) -> Option<(&String, &PathBuf)> {
+        let category = rel_path.components().next()?.as_os_str().to_str()?;
+        self.content_roots.get_key_value(&category.to_lowercase())
+    }
+
+    /// Whether SteamCMD downloads found at `path` can be installed: inside
+    /// NecoDL's SteamCMD root, or the Steam root of a distro package
+    fn accepts_download(&self, path: &Path) -> bool {
+        self.owns_download(path) || self.distro_steam_roots.iter().any(|root| path.starts_with(root))
+    }
+
+    /// Whether NecoDL may move and delete downloaded files at `path`, only
+    /// inside its own SteamCMD root
+    fn owns_download(&self, path: &Path) -> bool {
+        path.starts_with(&self.steamcmd_root)
+    }
+
+    fn steamcmd_install_dir(&self, sandbox: usize) -> PathBuf {
+        self.steamcmd_root
+            .join(steamcmd::install_dir_name(sandbox))
             .clean()
     }
+
+    fn steamcmd_workshop_dir(&self, sandbox: usize) -> PathBuf {
+        self.steamcmd_install_dir(sandbox).join("steamapps/workshop")
+    }
 }
 
 
 impl WorkshopManager {
-    pub async fn new() -> Result<Self> {
-        let config = Self::load_config().await?;
+    /// Loads config.toml from `base_dir`, relative paths in it resolve
+    /// against the same directory
+    pub async fn new(base_dir: &Path) -> Result<Self> {
+        Self::with_backends(base_dir, backend::Backends::steam).await
+    }
+
+    /// Builds the manager with custom item lookup and download backends,
+    /// created once the HTTP client, Web API and SteamCMD settings are known
+    pub async fn with_backends(
+        base_dir: &Path,
+        make_backends: impl FnOnce(&reqwest::Client, &steam_api::SteamApi, &backend::SteamOptions) -> backend::Backends,
+    ) -> Result<Self> {
+        let mut config = Self::load_config(base_dir).await?;
         Self::validate_config(&config)?;
-        let paths = PathManager::new(&config)?;
+        let permissions = platform::InstallPermissions::resolve(
+            config.file_mode.as_deref(),
+            config.dir_mode.as_deref(),
+            config.owner.as_deref(),
+            config.group.as_deref(),
+        )
+        .map_err(NecoError::Config)?;
+        let paths = PathManager::new(&config, base_dir)?;
+
+        for target in &mut config.deploy {
+            if let deploy::DeployKind::Local { path } = &mut target.kind {
+                *path = paths.base_dir.join(&*path).clean();
+            }
+        }
 
         fs::create_dir_all(&paths.local_files)
             .await
-            .context("Failed to create download directory")?;
+            .context(NecoError::Fs("Failed to create download directory".to_string()))?;
+        if platform::is_wrapper(&paths.steamcmd) {
+            fs::create_dir_all(&paths.steamcmd_root)
+                .await
+                .context(NecoError::Fs("Failed to create SteamCMD sandbox directory".to_string()))?;
+        }
 
         let whitelist = if !config.whitelist.is_empty() {
             let mut builder = GlobSetBuilder::new();
 
             for pattern in &config.whitelist {
-                let glob = Glob::new(pattern)
+                let expanded = if config.whitelist_any_depth && !pattern.starts_with("**/") {
+                    format!("**/{}", pattern.trim_start_matches('/'))
+                } else {
+                    pattern.clone()
+                };
+                let glob = GlobBuilder::new(&expanded)
+                    .case_insensitive(config.whitelist_ignore_case)
+                    .build()
                     .with_context(|| format!("Invalid glob pattern: {}", pattern))?;
                 builder.add(glob);
             }
 
             Some(builder.build()?)
         } else {
+            if !config.allow_all {
+                warn!(
+                    "whitelist is empty, every downloaded file will be rejected. \
+                     Add patterns to whitelist, or set allow_all = true to install everything."
+                );
+            }
             None
         };
 
+        // Bulk lookups hit steamcommunity.com and the Web API back to back,
+        // keep connections open between them and accept compressed pages
         let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
+            .timeout(Duration::from_secs(config.http.timeout))
+            .user_agent(&config.http.user_agent)
+            .pool_idle_timeout(Duration::from_secs(90))
+            .pool_max_idle_per_host(8)
+            .tcp_keepalive(Duration::from_secs(60))
+            .http2_adaptive_window(true)
+            .gzip(true)
+            .brotli(true)
             .build()
             .context("Failed to build HTTP client")?;
 
+        let api_key = std::env::var("NECODL_STEAM_API_KEY")
+            .ok()
+            .or_else(|| config.steam_api_key.clone());
+        let retry = http::RetryPolicy {
+            retries: config.http.retries,
+            backoff: Duration::from_secs_f64(config.http.retry_backoff),
+            limiter: http::RateLimiter::new(config.http.rate_limit, config.http.burst),
+        };
+        let api = steam_api::SteamApi::new(client.clone(), api_key, retry.clone());
+
+        let sandboxes = steamcmd::SandboxPool::new(config.steamcmd_instances);
+        let steamcmd_args = Self::steamcmd_args(&config);
+        let mut backends = make_backends(
+            &client,
+            &api,
+            &backend::SteamOptions {
+                steamcmd: paths.steamcmd.clone(),
+                steamcmd_args,
+                page_cache: paths.page_cache_file.clone(),
+                retry: retry.clone(),
+            },
+        );
+        backends.info = Box::new(backend::CachedInfo::new(backends.info));
+
         let mut mgr = Self {
+            sandboxes,
+            ahead: HashMap::new(),
+            permissions,
             config,
             paths,
-            metadata: HashMap::new(),
-            client,
+            metadata: BTreeMap::new(),
+            collections: BTreeMap::new(),
+            api,
+            backends,
+            http: client,
+            retry,
             whitelist, // globset
+            updates: Vec::new(),
+            failed_items: Vec::new(),
+            skipped_files: Vec::new(),
+            rejected_files: Vec::new(),
+            empty_files: Vec::new(),
+            processed: HashSet::new(),
+            appid_overrides: HashMap::new(),
+            stats: report::RunStats::default(),
+            throughput: Vec::new(),
+            live_map: tokio::sync::OnceCell::new(),
+            assume_yes: false,
         };
 
         mgr.load_metadata().await?;
+        mgr.collections = collection::load(&mgr.paths.collections_file).await?;
+        mgr.throughput = throughput::load(&mgr.paths.throughput_file).await?;
         Ok(mgr)
     }
 
-    async fn load_config() -> Result<Config> {
-        let exe_dir = std::env::current_exe()
-            .context("Failed to get executable path")?
-            .parent()
-            .context("Couldn't fetch parent directory for executable")?
-            .to_path_buf();
+    /// Arguments passed to SteamCMD ahead of its commands
+    fn steamcmd_args(config: &Config) -> Vec<String> {
+        let mut args: Vec<String> = config
+            .steamcmd_extra_args
+            .iter()
+            .flat_map(|arg| arg.split_whitespace().map(String::from))
+            .collect();
+        if let Some(platform) = config.platform {
+            args.extend([
+                "+@sSteamCmdForcePlatformType".to_string(),
+                platform.as_str().to_string(),
+            ]);
+        }
+        args
+    }
 
-        let config_path = exe_dir.join("config.toml");
+    async fn load_config(base_dir: &Path) -> Result<Config> {
+        let config_path = base_dir.join("config.toml");
 
-        let content = fs::read_to_string(&config_path)
-            .await
-            .context("Failed to read config.toml")?;
+        let content = fs::read_to_string(&config_path).await.with_context(|| {
+            NecoError::Config(format!("Failed to read {}", config_path.display()))
+        })?;
 
-        toml::from_str(&content).context("Failed to parse config.toml")
+        toml::from_str(&content).context(NecoError::Config("Failed to parse config.toml".to_string()))
     }
 
     fn is_allowed(&self, file_path: &Path) -> bool {
+        if self.config.allow_all {
+            return true;
+        }
         let Some(ref globset) = self.whitelist else {
             return false;
         };
@@ -213,26 +1356,93 @@ impl WorkshopManager {
     }
 
     fn validate_config(config: &Config) -> Result<()> {
+        Self::check_config(config).map_err(|e| NecoError::Config(e.to_string()).into())
+    }
+
+    fn check_config(config: &Config) -> Result<()> {
         if config.appid.trim().is_empty() {
             anyhow::bail!("appid must not be empty in config.toml");
         }
         if config.output_dir.trim().is_empty() {
             anyhow::bail!("output_dir must not be empty in config.toml");
         }
-        if config.steam_cmd.trim().is_empty() {
-            anyhow::bail!("steam_cmd must not be empty in config.toml");
+        for (name, command) in &config.alias {
+            if BUILTIN_COMMANDS.contains(&name.to_lowercase().as_str()) {
+                anyhow::bail!("alias '{}' shadows a built-in command", name);
+            }
+            if command.trim().is_empty() {
+                anyhow::bail!("alias '{}' must not be empty", name);
+            }
+        }
+        for size in config.max_file_size.iter().chain(config.max_file_size_items.values()) {
+            if parse_file_size(size).is_none() {
+                anyhow::bail!("invalid max_file_size '{}', expected e.g. \"500MB\"", size);
+            }
+        }
+        if config.http.timeout == 0 {
+            anyhow::bail!("http.timeout must be at least 1 second");
+        }
+        if !config.http.retry_backoff.is_finite() || config.http.retry_backoff < 0.0 {
+            anyhow::bail!("http.retry_backoff must be a number of seconds");
+        }
+        if !config.http.rate_limit.is_finite() || config.http.rate_limit < 0.0 {
+            anyhow::bail!("http.rate_limit must be a number of requests per second, 0 for no limit");
+        }
+        for window in &config.update_windows {
+            if schedule::TimeWindow::parse(window).is_none() {
+                anyhow::bail!("invalid update_windows entry '{}', expected e.g. \"04:00-06:00\"", window);
+            }
+        }
+        for category in config.content_dirs.keys() {
+            if category.is_empty() || category.contains(['/', '\\']) {
+                anyhow::bail!(
+                    "content_dirs key '{}' must be a single top-level directory name",
+                    category
+                );
+            }
+        }
+        let subdirs = [("collection_dirs", &config.collection_dirs), ("appid_dirs", &config.appid_dirs)];
+        for (key, dirs) in subdirs {
+            for (id, dir) in dirs {
+                let dir = Path::new(dir);
+                if dir.is_absolute()
+                    || dir
+                        .components()
+                        .any(|c| matches!(c, std::path::Component::ParentDir))
+                {
+                    anyhow::bail!("{} entry for {} must be a relative path inside output_dir", key, id);
+                }
+            }
+        }
+        for (i, target) in config.deploy.iter().enumerate() {
+            if config.deploy[..i].iter().any(|t| t.name == target.name) {
+                anyhow::bail!("duplicate deploy target name '{}'", target.name);
+            }
         }
         Ok(())
     }
 
+    /// Where metadata is saved (`metadata.json.zst` with `compress_state`),
+    /// and where it is kept in the other format
+    fn metadata_paths(&self) -> (PathBuf, PathBuf) {
+        let plain = self.paths.metadata_file.clone();
+        let compressed = archive::compressed_path(&plain);
+        if self.config.compress_state {
+            (compressed, plain)
+        } else {
+            (plain, compressed)
+        }
+    }
+
     async fn load_metadata(&mut self) -> Result<()> {
-        match fs::read_to_string(&self.paths.metadata_file).await {
-            Ok(data) => {
-                self.metadata =
-                    serde_json::from_str(&data).context("Failed to parse metadata.json")?;
-            }
-            Err(_) => {
-                self.metadata = HashMap::new();
+        // Fall back to the other format so toggling compress_state loses nothing
+        let (current, other) = self.metadata_paths();
+        self.metadata = BTreeMap::new();
+        for path in [current, other] {
+            if let Some(data) = archive::read_state(&path).await? {
+                self.metadata = serde_json::from_str(&data)
+                    .with_context(|| format!("Failed to parse {}", path.display()))?;
+                break;
             }
         }
         Ok(())
@@ -240,73 +1450,66 @@ impl WorkshopManager {
 
     async fn save_metadata(&self) -> Result<()> {
         let data = serde_json::to_string_pretty(&self.metadata)?;
-        fs::write(&self.paths.metadata_file, data)
+        let (path, stale) = self.metadata_paths();
+        let data = if self.config.compress_state {
+            archive::encode(data.as_bytes())?
+        } else {
+            data.into_bytes()
+        };
+        write_if_changed(&path, &data)
             .await
-            .context("Failed to save metadata")
-    }
+            .context(NecoError::Fs("Failed to save metadata".to_string()))?;
 
-    async fn fetch_html(&self, url: &str) -> Result<String> {
-        self.client
-            .get(url)
-            .send()
-            .await?
-            .error_for_status()?
-            .text()
-            .await
-            .map_err(Into::into)
+        if fs::try_exists(&stale).await.unwrap_or(false) {
+            fs::remove_file(&stale)
+                .await
+                .with_context(|| NecoError::Fs(format!("Failed to remove {}", stale.display())))?;
+        }
+        Ok(())
     }
 
-    async fn parse_workshop_item(&self, workshop_id: &str) -> Result<ParseResult> {
-        let changelog_url = format!(
-            "https://steamcommunity.com/sharedfiles/filedetails/changelog/{}",
-            workshop_id
-        );
-        let changelog_html = self
-            .fetch_html(&changelog_url)
-            .await
-            .with_context(|| format!("Failed to fetch changelog page for id {}", workshop_id))?;
-        let changelog_doc = Html::parse_document(&changelog_html);
-
-        let title = changelog_doc
-            .select(&TITLE_SELECTOR)
-            .next()
-            .map(|el| el.text().collect::<String>().trim().to_string())
-            .unwrap_or_else(|| "Untitled".to_string());
+    async fn save_collections(&self) -> Result<()> {
+        collection::save(&self.paths.collections_file, &self.collections).await
+    }
 
-        if let Some(changelog_id) = changelog_doc
-            .select(&CHANGELOG_SELECTOR)
-            .next()
-            .and_then(|el| el.value().attr("id"))
-        {
-            return Ok(ParseResult::Item(WorkshopItem {
-                id: workshop_id.to_string(),
-                title,
-                changelog_id: changelog_id.to_string(),
-            }));
+    /// Fetches a tracked item, recording or clearing its unavailable state.
+    /// Returns `None` for collections and unavailable items.
+    async fn resolve_item(&mut self, workshop_id: &str) -> Result<Option<WorkshopItem>> {
+        platform::watchdog_ping();
+        match self.backends.info.fetch(workshop_id).await? {
+            ParseResult::Item(item) => {
+                if let Some(metadata) = self.metadata.get_mut(workshop_id)
+                    && metadata.unavailable.take().is_some()
+                {
+                    println!("{} is available again", workshop_id);
+                    self.save_metadata().await?;
+                }
+                Ok(Some(item))
+            }
+            ParseResult::Unavailable(reason) => {
+                self.mark_unavailable(workshop_id, &reason).await?;
+                Ok(None)
+            }
+            ParseResult::Collection(_) => Ok(None),
         }
+    }
 
-        let collection_url = format!(
-            "https://steamcommunity.com/sharedfiles/filedetails/?id={}",
-            workshop_id
+    async fn mark_unavailable(&mut self, workshop_id: &str, reason: &str) -> Result<()> {
+        println!(
+            "Warning: {} is unavailable on the Workshop ({}), keeping local files",
+            workshop_id, reason
         );
-        let collection_html = self
-            .fetch_html(&collection_url)
-            .await
-            .with_context(|| format!("Failed to fetch collection page for id {}", workshop_id))?;
-        let collection_doc = Html::parse_document(&collection_html);
-
-        let item_ids = collection_doc
-            .select(&ITEM_SELECTOR)
-            .filter_map(|el| el.value().attr("id"))
-            .filter_map(|id| id.strip_prefix("sharedfile_"))
-            .map(String::from)
-            .collect();
 
-        Ok(ParseResult::Collection(WorkshopCollection {
-            id: workshop_id.to_string(),
-            title,
-            item_ids,
-        }))
+        if let Some(metadata) = self.metadata.get_mut(workshop_id)
+            && metadata.unavailable.is_none()
+        {
+            metadata.unavailable = Some(Unavailable {
+                reason: reason.to_string(),
+                since: Utc::now(),
+            });
+            self.save_metadata().await?;
+        }
+        Ok(())
     }
 
     async fn quick_update(
@@ -325,62 +1528,396 @@ impl WorkshopManager {
 
         let files = metadata.files.clone();
 
-        for file_info in &files {
-            if !self.verify_file(file_info).await? {
+        // A few deleted files are restored on their own, an item with none
+        // left gets a regular download
+        let mut missing = Vec::new();
+        let mut present = Vec::new();
+        for file_info in files {
+            if fs::try_exists(self.paths.file_path(&file_info)).await? {
+                present.push(file_info);
+            } else {
+                missing.push(file_info);
+            }
+        }
+        if present.is_empty() && !missing.is_empty() {
+            return Ok(false);
+        }
+
+        if !self.verify_files(&present).await? {
+            return Ok(false);
+        }
+
+        if !missing.is_empty() {
+            let mut job = self.sandbox_for(&item.id).await;
+            let result = self.repair_item(item, &missing, &mut job).await;
+            self.clean_sandbox(job.sandbox.index(), &item.id, false).await;
+            if !result? {
                 return Ok(false);
             }
         }
 
         if let Some(cid) = collection_id {
             let cid_string = cid.to_string();
-            if let Some(metadata) = self.metadata.get_mut(&item.id) {
-                if !metadata.collection_ids.contains(&cid_string) {
-                    metadata.collection_ids.push(cid_string);
-                }
+            if let Some(metadata) = self.metadata.get_mut(&item.id)
+                && !metadata.collection_ids.contains(&cid_string)
+            {
+                metadata.collection_ids.push(cid_string);
             }
         }
 
         self.save_metadata().await?;
-        self.update_workshop_maps().await?;
+        self.update_outputs().await?;
+
+        if missing.is_empty() {
+            println!("Successfully downloaded {} (up-to-date, skipped)", item.id);
+        } else {
+            println!("Successfully repaired {} ({} files restored)", item.id, missing.len());
+        }
+        Ok(true)
+    }
+
+    /// Downloads the item again and puts back only the missing files, as
+    /// long as the fresh copies match their recorded hashes. False if some
+    /// couldn't be restored, the item then needs a full download.
+    async fn repair_item(
+        &mut self,
+        item: &WorkshopItem,
+        missing: &[FileInfo],
+        job: &mut SandboxJob,
+    ) -> Result<bool> {
+        println!("{} is missing {} files, restoring them", item.id, missing.len());
+
+        let Some((workshop_dir, source_path)) = self.fetch_content(item, job).await? else {
+            return Ok(false);
+        };
+
+        let mut restored: Vec<String> = Vec::new();
+        let mut restored_size = 0;
+        let result = async {
+            let mut stack = vec![(platform::long_path(&source_path), PathBuf::new())];
+            while let Some((src_dir, rel_dir)) = stack.pop() {
+                let mut entries = fs::read_dir(&src_dir).await?;
+                while let Some(entry) = entries.next_entry().await? {
+                    let src_path = entry.path();
+                    let rel_path = rel_dir.join(entry.file_name());
+                    if entry.file_type().await?.is_dir() {
+                        stack.push((src_path, rel_path));
+                        continue;
+                    }
+
+                    let Some(file_info) = missing
+                        .iter()
+                        .find(|f| !restored.contains(&f.path) && Path::new(&f.path) == rel_path)
+                    else {
+                        continue;
+                    };
+                    if !file_info.hash.is_empty() && md5_file(&src_path).await? != file_info.hash {
+                        continue;
+                    }
+
+                    let dest = platform::long_path(&self.paths.file_path(file_info));
+                    if let Some(parent) = dest.parent() {
+                        fs::create_dir_all(parent).await?;
+                    }
+                    if self.paths.owns_download(&source_path) {
+                        move_file(&src_path, &dest).await?;
+                    } else {
+                        fs::copy(&src_path, &dest).await?;
+                    }
+                    self.apply_permissions(&dest, false);
+                    restored_size += file_info.size;
+                    println!("Restored: {}", file_info.path);
+                    restored.push(file_info.path.clone());
+                }
+            }
+            Ok::<_, anyhow::Error>(())
+        }
+        .await;
+        self.adjust_cached_size(restored_size as i64).await;
+        result?;
+        if workshop_dir != self.paths.steamcmd_workshop_dir(job.sandbox.index()) {
+            self.clean_workshop_item(&workshop_dir, &item.id).await;
+        }
+
+        if !restored.is_empty() {
+            self.record_audit(audit::AuditEntry {
+                time: Utc::now(),
+                op: audit::Operation::Repair,
+                id: item.id.clone(),
+                title: item.title.clone(),
+                files: restored.clone(),
+                changelog_id: Some(item.changelog_id.clone()),
+                trash: None,
+                previous: None,
+                reverts: None,
+            })
+            .await;
+        }
 
-        println!("Successfully downloaded {} (up-to-date, skipped)", item.id);
+        if restored.len() < missing.len() {
+            println!(
+                "{} missing files of {} could not be restored from the Workshop copy, downloading it again",
+                missing.len() - restored.len(),
+                item.id
+            );
+            return Ok(false);
+        }
         Ok(true)
     }
 
-    async fn update_workshop_maps(&self) -> Result<()> {
-        let mut content = String::from("\"WorkshopMaps\"\n{\n");
+    /// The server's current map, queried once per command
+    async fn live_map(&self) -> Option<&str> {
+        let server = self.config.server.as_ref()?;
+
+        self.live_map
+            .get_or_init(|| async {
+                match a2s::query_info(&server.address).await {
+                    Ok(info) => Some(info.map),
+                    Err(e) => {
+                        warn!("failed to query server {}: {:#}", server.address, e);
+                        None
+                    }
+                }
+            })
+            .await
+            .as_deref()
+    }
+
+    /// Drops what earlier commands looked up, so the next one sees the
+    /// Workshop and the game server as they are now
+    fn forget_lookups(&mut self) {
+        self.backends.info.forget();
+        self.live_map = tokio::sync::OnceCell::new();
+    }
+
+    async fn is_map_live(&self, metadata: &WorkshopMetadata) -> bool {
+        let Some(map_name) = self.extract_map_name(metadata) else {
+            return false;
+        };
+        let Some(live_map) = self.live_map().await else {
+            return false;
+        };
+
+        let live_map = Path::new(live_map)
+            .file_name()
+            .map(|s| s.to_string_lossy())
+            .unwrap_or(live_map.into());
+
+        live_map.eq_ignore_ascii_case(&map_name)
+    }
+
+    /// Maps sorted by their assigned order (unordered maps last), then by name.
+    /// With `interleave_groups`, maps from different groups alternate.
+    fn ordered_maps(&self) -> Vec<(String, &str)> {
+        let mut maps: Vec<(Option<i64>, &str, String, &str)> = self
+            .metadata
+            .iter()
+            .filter(|(_, metadata)| metadata.archived.is_none())
+            .filter_map(|(id, metadata)| {
+                let map_name = self.extract_map_name(metadata)?;
+                let group = metadata.group.as_deref().unwrap_or("");
+                Some((metadata.order, group, map_name, id.as_str()))
+            })
+            .collect();
+
+        maps.sort_by(|a, b| {
+            let order_a = a.0.unwrap_or(i64::MAX);
+            let order_b = b.0.unwrap_or(i64::MAX);
+            order_a.cmp(&order_b).then_with(|| a.2.cmp(&b.2))
+        });
+
+        if !self.config.interleave_groups {
+            return maps.into_iter().map(|(_, _, map, id)| (map, id)).collect();
+        }
+
+        let mut groups: Vec<(&str, std::collections::VecDeque<(String, &str)>)> = Vec::new();
+        for (_, group, map, id) in maps {
+            match groups.iter_mut().find(|(name, _)| *name == group) {
+                Some((_, queue)) => queue.push_back((map, id)),
+                None => groups.push((group, [(map, id)].into())),
+            }
+        }
+
+        let mut result = Vec::new();
+        while groups.iter().any(|(_, queue)| !queue.is_empty()) {
+            for (_, queue) in &mut groups {
+                if let Some(entry) = queue.pop_front() {
+                    result.push(entry);
+                }
+            }
+        }
+        result
+    }
+
+    async fn update_outputs(&self) -> Result<()> {
+        let maps = self.ordered_maps();
+
+        for output in &self.paths.outputs {
+            let format = &output.config;
+            let maps: Vec<&(String, &str)> = maps
+                .iter()
+                .filter(|(_, id)| format.appid.as_deref().is_none_or(|appid| self.appid(id) == appid))
+                .collect();
+            let content = match format.format {
+                OutputFormat::KeyValues => {
+                    let mut content = format!("\"{}\"\n{{\n", format.root_key);
+                    for (map_name, workshop_id) in &maps {
+                        let attributes = &self.metadata[*workshop_id].attributes;
+                        if !format.attributes || attributes.is_empty() {
+                            content.push_str(&format!(
+                                "{}\"{}\"{}\"{}\"\n",
+                                format.indent, map_name, format.separator, workshop_id
+                            ));
+                            continue;
+                        }
+
+                        // "map" { "id" "123" "key" "value" ... }
+                        let (indent, inner) = (&format.indent, format.indent.repeat(2));
+                        content.push_str(&format!("{}\"{}\"\n{}{{\n", indent, map_name, indent));
+                        let entries = std::iter::once(("id", *workshop_id))
+                            .chain(attributes.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+                        for (key, value) in entries {
+                            content.push_str(&format!(
+                                "{}\"{}\"{}\"{}\"\n",
+                                inner,
+                                key,
+                                format.separator,
+                                escape_kv(value)
+                            ));
+                        }
+                        content.push_str(&format!("{}}}\n", indent));
+                    }
+                    content.push_str("}\n");
+                    content
+                }
+                OutputFormat::List => maps
+                    .iter()
+                    .map(|(map_name, _)| format!("{}\n", map_name))
+                    .collect(),
+                OutputFormat::Umc => self.render_umc(&maps, format),
+            };
+
+            if let Some(parent) = output.path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+
+            write_if_changed(&output.path, &content)
+                .await
+                .with_context(|| NecoError::Fs(format!("Failed to write map list to {}", output.path.display())))?;
+        }
+
+        self.write_checksums().await
+    }
 
-        for (workshop_id, metadata) in &self.metadata {
-            if let Some(map_name) = self.extract_map_name(metadata) {
-                content.push_str(&format!("\t\"{}\"\t\t\"{}\"\n", map_name, workshop_id));
+    /// umc_mapcycle.txt with a map group per `order group` or collection.
+    /// Item attributes become keys of the map, e.g. "display" or "min_players".
+    fn render_umc(&self, maps: &[&(String, &str)], format: &OutputConfig) -> String {
+        let indent = &format.indent;
+        let mut groups: Vec<(String, Vec<&(String, &str)>)> = Vec::new();
+        for entry in maps {
+            let metadata = &self.metadata[entry.1];
+            let group = metadata
+                .group
+                .clone()
+                .or_else(|| {
+                    metadata
+                        .collection_ids
+                        .first()
+                        .map(|cid| self.collections.get(cid).map_or(cid.clone(), |c| c.title.clone()))
+                })
+                .unwrap_or_else(|| "Workshop".to_string());
+            match groups.iter_mut().find(|(name, _)| *name == group) {
+                Some((_, entries)) => entries.push(entry),
+                None => groups.push((group, vec![entry])),
             }
         }
 
+        let mut content = String::from("\"umc_mapcycle\"\n{\n");
+        for (group, entries) in groups {
+            content.push_str(&format!("{0}\"{1}\"\n{0}{{\n", indent, escape_kv(&group)));
+            for (map_name, workshop_id) in entries {
+                let inner = indent.repeat(2);
+                content.push_str(&format!("{0}\"{1}\"\n{0}{{\n", inner, map_name));
+                for (key, value) in &self.metadata[*workshop_id].attributes {
+                    content.push_str(&format!(
+                        "{}\"{}\"{}\"{}\"\n",
+                        indent.repeat(3),
+                        key,
+                        format.separator,
+                        escape_kv(value)
+                    ));
+                }
+                content.push_str(&format!("{}}}\n", inner));
+            }
+            content.push_str(&format!("{}}}\n", indent));
+        }
         content.push_str("}\n");
+        content
+    }
+
+    /// Writes a `sha256sum -c` compatible manifest of every managed file
+    async fn write_checksums(&self) -> Result<()> {
+        let Some(manifest) = &self.paths.checksums_file else {
+            return Ok(());
+        };
+
+        let mut entries = BTreeMap::new();
+
+        for metadata in self.metadata.values() {
+            // The manifest covers output_dir only, like its relative paths
+            for file_info in metadata.files.iter().filter(|f| f.root.is_none()) {
+                let full_path = self.paths.file_path(file_info);
+                if !fs::try_exists(&full_path).await? {
+                    continue;
+                }
+
+                let sha256 = if file_info.sha256.is_empty() {
+                    self.calculate_file_hashes(&full_path).await?.1
+                } else {
+                    file_info.sha256.clone()
+                };
+                entries.insert(file_info.path.replace('\\', "/"), sha256);
+            }
+        }
+
+        for output in &self.paths.outputs {
+            if let Ok(rel) = output.path.strip_prefix(&self.paths.local_files)
+                && fs::try_exists(&output.path).await?
+            {
+                let sha256 = self.calculate_file_hashes(&output.path).await?.1;
+                entries.insert(rel.to_string_lossy().replace('\\', "/"), sha256);
+            }
+        }
 
-        if let Some(parent) = self.paths.workshop_maps_file.parent() {
+        let content: String = entries
+            .iter()
+            .map(|(path, sha256)| format!("{}  {}\n", sha256, path))
+            .collect();
+
+        if let Some(parent) = manifest.parent() {
             fs::create_dir_all(parent).await?;
         }
 
-        fs::write(&self.paths.workshop_maps_file, content)
+        write_if_changed(manifest, &content)
             .await
-            .with_context(|| {
-                format!(
-                    "Failed to write workshop maps to {}",
-                    self.paths.workshop_maps_file.display()
-                )
-            })?;
-
+            .with_context(|| NecoError::Fs(format!("Failed to write checksums to {}", manifest.display())))?;
         Ok(())
     }
 
     async fn calculate_file_hash(&self, path: &Path) -> Result<String> {
+        md5_file(path).await
+    }
+
+    /// MD5 (used for change detection) and SHA-256 (used for the checksums
+    /// manifest) in a single read.
+    async fn calculate_file_hashes(&self, path: &Path) -> Result<(String, String)> {
         const BUFFER_SIZE: usize = 64 * 1024;
         let mut file = fs::File::open(path)
             .await
-            .with_context(|| format!("Failed to open file: {}", path.display()))?;
+            .with_context(|| NecoError::Fs(format!("Failed to open file: {}", path.display())))?;
 
-        let mut context = md5::Context::new();
+        let mut md5 = md5::Context::new();
+        let mut sha256 = Sha256::new();
         let mut buffer = vec![0u8; BUFFER_SIZE];
 
         loop {
@@ -388,14 +1925,18 @@ impl WorkshopManager {
             if bytes_read == 0 {
                 break;
             }
-            context.consume(&buffer[..bytes_read]);
+            md5.consume(&buffer[..bytes_read]);
+            sha256.update(&buffer[..bytes_read]);
         }
 
-        Ok(format!("{:x}", context.compute()))
+        Ok((
+            format!("{:x}", md5.compute()),
+            format!("{:x}", sha256.finalize()),
+        ))
     }
 
     async fn verify_file(&self, file_info: &FileInfo) -> Result<bool> {
-        let full_path = self.paths.local_files.join(&file_info.path);
+        let full_path = self.paths.file_path(file_info);
 
         if !fs::try_exists(&full_path).await? {
             return Ok(false);
@@ -409,54 +1950,71 @@ impl WorkshopManager {
         Ok(current_hash == file_info.hash)
     }
 
-    async fn run_steamcmd(&self, args: &[&str], verbose: bool) -> Result<bool> {
-        let mut child = Command::new(&self.paths.steamcmd)
-            .args(args)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .context("Failed to start SteamCMD")?;
-
-        let stdout = child
-            .stdout
-            .take()
-            .context("Failed to capture SteamCMD stdout")?;
-        let reader = BufReader::new(stdout);
-        let mut lines = reader.lines();
+    /// Same as `verify_file` for every file, hashing up to VERIFY_CONCURRENCY
+    /// files at once and stopping at the first mismatch.
+    async fn verify_files(&self, files: &[FileInfo]) -> Result<bool> {
+        const VERIFY_CONCURRENCY: usize = 8;
+        let semaphore = Arc::new(Semaphore::new(VERIFY_CONCURRENCY));
+        let mut tasks = JoinSet::new();
+
+        for file_info in files {
+            let full_path = self.paths.file_path(file_info);
+            let expected = file_info.hash.clone();
+            let semaphore = semaphore.clone();
+
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await?;
+                if !fs::try_exists(&full_path).await? {
+                    return Ok(false);
+                }
+                if expected.is_empty() {
+                    return Ok(true);
+                }
+                Ok::<_, anyhow::Error>(md5_file(&full_path).await? == expected)
+            });
+        }
 
-        let mut success = false;
-        while let Some(line) = lines.next_line().await? {
-            if verbose {
-                println!("{}", line);
-            }
-            if line.contains("Success. Downloaded item") || line.contains("item state : 4") {
-                success = true;
-                break;
+        while let Some(result) = tasks.join_next().await {
+            if !result?? {
+                tasks.abort_all();
+                return Ok(false);
             }
         }
 
-        let status = child.wait().await?;
-        Ok(success || status.success())
+        Ok(true)
     }
 
-    async fn move_and_track_files(&self, src: &Path, dest: &Path) -> Result<Vec<FileInfo>> {
+    /// Moves an item's downloaded files into place. Returns the installed
+    /// files, and the relative paths and sizes of those over `max_size`.
+    async fn move_and_track_files(
+        &self,
+        src: &Path,
+        dest: &Path,
+        max_size: Option<u64>,
+    ) -> Result<MovedFiles> {
+        let mut moved = MovedFiles::default();
         if !fs::try_exists(src).await? {
-            return Ok(Vec::new());
+            moved.source_missing = true;
+            return Ok(moved);
         }
 
         fs::create_dir_all(dest).await?;
-        let mut files = Vec::new();
-        self.move_directory(src, dest, &mut files).await?;
-        Ok(files)
+        self.move_directory(src, dest, max_size, &mut moved).await?;
+        self.adjust_cached_size(moved.files.iter().map(|f| f.size as i64).sum())
+            .await;
+        Ok(moved)
     }
 
     async fn move_directory(
         &self,
         src: &Path,
         dest: &Path,
-        files: &mut Vec<FileInfo>,
+        max_size: Option<u64>,
+        moved: &mut MovedFiles,
     ) -> Result<()> {
-        let mut stack = vec![(src.to_path_buf(), PathBuf::new())];
+        let keep_source = self.config.keep_download_cache || !self.paths.owns_download(src);
+        let dest = platform::long_path(dest);
+        let mut stack = vec![(platform::long_path(src), PathBuf::new())];
 
         while let Some((src_dir, rel_dir)) = stack.pop() {
             if !fs::try_exists(&src_dir).await? {
@@ -465,6 +2023,9 @@ impl WorkshopManager {
 
             let dest_dir = dest.join(&rel_dir);
             fs::create_dir_all(&dest_dir).await?;
+            if !rel_dir.as_os_str().is_empty() {
+                self.apply_permissions(&dest_dir, true);
+            }
 
             let mut entries = fs::read_dir(&src_dir).await?;
             while let Some(entry) = entries.next_entry().await? {
@@ -476,19 +2037,40 @@ impl WorkshopManager {
                 if meta.is_dir() {
                     stack.push((src_path, rel_path));
                 } else {
-                    if !self.is_allowed(&rel_path) {
-                        println!("Skipping {} - not in whitelist", rel_path.display());
+                    if !self.admit_file(&rel_path, meta.len(), max_size, moved, false) {
                         continue;
                     }
 
-                    let dest_path = dest.join(&rel_path);
-                    let hash = self.calculate_file_hash(&src_path).await?;
+                    let (dest_path, root) = match self.paths.content_root(&rel_path) {
+                        Some((category, root)) => {
+                            let dest_path = platform::long_path(&root.join(&rel_path));
+                            if let Some(parent) = dest_path.parent() {
+                                fs::create_dir_all(parent).await?;
+                                self.apply_permissions(parent, true);
+                            }
+                            (dest_path, Some(category.clone()))
+                        }
+                        None => (dest.join(&rel_path), None),
+                    };
+                    let (hash, sha256) = self.calculate_file_hashes(&src_path).await?;
+                    // Don't write through a hard link left by dedupe
+                    if let Err(e) = fs::remove_file(&dest_path).await
+                        && e.kind() != std::io::ErrorKind::NotFound
+                    {
+                        return Err(e.into());
+                    }
                     fs::copy(&src_path, &dest_path).await?;
-                    fs::remove_file(&src_path).await?;
+                    if !keep_source {
+                        fs::remove_file(&src_path).await?;
+                    }
+                    self.apply_permissions(&dest_path, false);
 
-                    files.push(FileInfo {
+                    moved.files.push(FileInfo {
                         path: rel_path.to_string_lossy().to_string(),
                         hash,
+                        size: meta.len(),
+                        sha256,
+                        root,
                     });
                 }
             }
@@ -497,403 +2079,5716 @@ impl WorkshopManager {
         Ok(())
     }
 
-    async fn remove_item(&mut self, workshop_id: &str) -> Result<bool> {
-        let metadata = match self.metadata.remove(workshop_id) {
-            Some(m) => m,
-            None => return Ok(false),
-        };
-
-        self.save_metadata().await?;
-
-        let mut removed_count = 0;
-
-        for file_info in &metadata.files {
-            let full_path = self.paths.local_files.join(&file_info.path);
-
-            if !fs::try_exists(&full_path).await? {
-                continue;
+    /// Whether a downloaded file gets installed, noting in `moved` why not
+    /// (or that it is empty). `quiet` leaves out the messages.
+    fn admit_file(
+        &self,
+        rel_path: &Path,
+        len: u64,
+        max_size: Option<u64>,
+        moved: &mut MovedFiles,
+        quiet: bool,
+    ) -> bool {
+        if !self.is_allowed(rel_path) {
+            if !quiet {
+                println!("Skipping {} - not in whitelist", rel_path.display());
             }
-
-            if !file_info.hash.is_empty() && !self.verify_file(file_info).await? {
+            moved.rejected.push(rel_path.to_string_lossy().to_string());
+            return false;
+        }
+        if let Some(limit) = max_size
+            && len > limit
+        {
+            if !quiet {
                 println!(
-                    "Skipping {} - file modified, delete manually",
-                    file_info.path
+                    "Skipping {} - {} exceeds max_file_size",
+                    rel_path.display(),
+                    format_file_size(len)
                 );
-                continue;
             }
-
-            let meta = fs::metadata(&full_path).await?;
-            if meta.is_dir() {
-                fs::remove_dir_all(&full_path).await?;
-            } else {
-                fs::remove_file(&full_path).await?;
+            moved.oversized.push((rel_path.to_string_lossy().to_string(), len));
+            return false;
+        }
+        if len == 0 && self.config.zero_byte_files != ZeroBytePolicy::Allow {
+            moved.empty.push(rel_path.to_string_lossy().to_string());
+            if self.config.zero_byte_files == ZeroBytePolicy::Skip {
+                if !quiet {
+                    println!("Skipping {} - empty file", rel_path.display());
+                }
+                return false;
+            }
+            if !quiet {
+                println!("Warning: {} is empty (0 bytes)", rel_path.display());
             }
+        }
+        true
+    }
 
-            println!("Removed: {}", file_info.path);
-            removed_count += 1;
+    /// What installing a download would do, without moving anything: files
+    /// carry their relative path and size only
+    async fn scan_download(&self, src: &Path, max_size: Option<u64>) -> Result<MovedFiles> {
+        let mut scanned = MovedFiles::default();
+        if !fs::try_exists(src).await? {
+            scanned.source_missing = true;
+            return Ok(scanned);
         }
 
-        Ok(removed_count > 0)
+        let mut stack = vec![(platform::long_path(src), PathBuf::new())];
+        while let Some((dir, rel_dir)) = stack.pop() {
+            let mut entries = fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let rel_path = rel_dir.join(entry.file_name());
+                let meta = fs::metadata(entry.path()).await?;
+                if meta.is_dir() {
+                    stack.push((entry.path(), rel_path));
+                } else if self.admit_file(&rel_path, meta.len(), max_size, &mut scanned, true) {
+                    scanned.files.push(FileInfo {
+                        path: rel_path.to_string_lossy().to_string(),
+                        size: meta.len(),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+        Ok(scanned)
     }
 
-    fn display_config_info(&self) {
-        println!("{:<25}: {}", "App ID", self.config.appid);
-        println!("{:<25}: {}", "Metadata File", self.paths.metadata_file.display());
-        println!("{:<25}: {}", "Output Folder", self.paths.local_files.display());
-        println!("{:<25}: {}", "SteamCMD", self.paths.steamcmd.display());
+    /// Applies the configured mode and ownership, warning if that fails
+    /// (chown usually needs root)
+    fn apply_permissions(&self, path: &Path, is_dir: bool) {
+        if let Err(e) = self.permissions.apply(path, is_dir) {
+            warn!("failed to set permissions of {}: {}", path.display(), e);
+        }
     }
 
-    async fn display_subscription_info(&self) -> Result<()> {
-        println!("{:<25}: {}", "Total Subscriptions", self.metadata.len());
-        Ok(())
-    }
+    async fn remove_item(&mut self, workshop_id: &str) -> Result<bool> {
+        if let Some(metadata) = self.metadata.get(workshop_id)
+            && self.is_map_live(metadata).await
+        {
+            println!(
+                "Skipping removal of {} - map is currently being played, retry after the next map change",
+                workshop_id
+            );
+            return Ok(false);
+        }
 
-    async fn calculate_directory_size(&self, root: &Path) -> Result<u64> {
-        let mut total = 0;
-        let mut stack = vec![root.to_path_buf()];
+        let metadata = match self.metadata.remove(workshop_id) {
+            Some(m) => m,
+            None => return Ok(false),
+        };
 
-        while let Some(path) = stack.pop() {
-            if !fs::try_exists(&path).await? {
-                continue;
-            }
+        self.save_metadata().await?;
 
-            let mut entries = fs::read_dir(&path).await?;
-            while let Some(entry) = entries.next_entry().await? {
-                let path = entry.path();
-                let meta = fs::metadata(&path).await?;
+        let (trash, removed_files) = self.move_to_trash(workshop_id, &metadata.files, true).await?;
+        for path in &removed_files {
+            println!("Removed: {}", path);
+        }
 
-                if meta.is_dir() {
-                    stack.push(path);
-                } else {
-                    total += meta.len();
-                }
+        if metadata.archived.is_some() {
+            let item_dir = self.paths.archive_dir.join(workshop_id);
+            match fs::remove_dir_all(&item_dir).await {
+                Ok(()) => println!("Removed archive {}", item_dir.display()),
+                Err(e) => warn!("failed to remove {}: {}", item_dir.display(), e),
             }
         }
 
-        Ok(total)
+        let removed_any = !removed_files.is_empty();
+        self.record_audit(audit::AuditEntry {
+            time: Utc::now(),
+            op: audit::Operation::Remove,
+            id: workshop_id.to_string(),
+            title: metadata.title.clone(),
+            files: removed_files,
+            changelog_id: Some(metadata.changelog_id.clone()),
+            trash: Some(trash),
+            previous: Some(metadata),
+            reverts: None,
+        })
+        .await;
+
+        Ok(removed_any)
     }
 
-    async fn display_storage_info(&self) -> Result<()> {
-        let output_dir = &self.paths.local_files;
-        let used_space = self.calculate_directory_size(output_dir).await?;
+    /// Moves an item's files into a fresh trash/<time>-<id> directory so the
+    /// operation can be undone. With `skip_modified`, files whose hash no longer
+    /// matches are left in place. Returns the directory name and moved paths.
+    async fn move_to_trash(
+        &self,
+        workshop_id: &str,
+        files: &[FileInfo],
+        skip_modified: bool,
+    ) -> Result<(String, Vec<String>)> {
+        let name = format!("{}-{}", Utc::now().format("%Y%m%d%H%M%S"), workshop_id);
+        let trash = self.paths.trash_dir.join(&name);
+        let mut moved = Vec::new();
+        let mut freed = 0;
+
+        let result = async {
+            for file_info in files {
+                let full_path = self.paths.file_path(file_info);
+
+                if !fs::try_exists(&full_path).await? {
+                    continue;
+                }
 
-        println!("{:<25}: {}", "Download Directory", output_dir.display());
-        println!("{:<25}: {}", "Used Space", format_file_size(used_space));
+                if let Some(other) = self.file_owner(&full_path, workshop_id) {
+                    println!("Keeping {} - also installed by {}", file_info.path, other);
+                    continue;
+                }
 
-        Ok(())
+                if skip_modified && !file_info.hash.is_empty() && !self.verify_file(file_info).await? {
+                    println!(
+                        "Skipping {} - file modified, delete manually",
+                        file_info.path
+                    );
+                    continue;
+                }
+
+                let size = fs::metadata(&full_path).await?.len();
+                let shared = self.linked_elsewhere(&full_path, file_info).await;
+                move_file(&full_path, &trash.join(&file_info.path)).await?;
+                if !shared {
+                    freed += size;
+                }
+                moved.push(file_info.path.clone());
+            }
+            Ok::<_, anyhow::Error>(())
+        }
+        .await;
+        self.adjust_cached_size(-(freed as i64)).await;
+        result?;
+
+        Ok((name, moved))
     }
 
-    async fn cmd_info(&self) -> Result<()> {
-        self.display_config_info();
-        self.display_subscription_info().await?;
-        self.display_storage_info().await?;
-        Ok(())
+    /// Another item tracking a file at the same location, if any
+    fn file_owner(&self, full_path: &Path, workshop_id: &str) -> Option<&str> {
+        self.metadata
+            .iter()
+            .filter(|(id, _)| id.as_str() != workshop_id)
+            .find(|(_, m)| m.files.iter().any(|f| self.paths.file_path(f) == full_path))
+            .map(|(id, _)| id.as_str())
     }
 
-    async fn cmd_download(&mut self, args: &[&str]) -> Result<()> {
-        if args.is_empty() {
-            println!("usage: download [-f|--force] <workshop_id>");
-            return Ok(());
+    /// Replaces newly installed files with hard links to identical files of
+    /// other items, so shared content is stored once. The filesystem keeps
+    /// the content until its last link is removed.
+    async fn link_duplicates(&self, workshop_id: &str, files: &[FileInfo]) {
+        let mut freed = 0;
+        for file_info in files.iter().filter(|f| !f.sha256.is_empty()) {
+            let path = self.paths.file_path(file_info);
+            let Some(existing) = self
+                .metadata
+                .iter()
+                .filter(|(id, _)| id.as_str() != workshop_id)
+                .flat_map(|(_, m)| &m.files)
+                .filter(|f| f.sha256 == file_info.sha256 && f.size == file_info.size)
+                .map(|f| self.paths.file_path(f))
+                .find(|p| *p != path && p.is_file())
+            else {
+                continue;
+            };
+
+            let temp = path.with_extension("necodl-link");
+            let linked = fs::hard_link(&existing, &temp).await;
+            let result = match linked {
+                Ok(()) => fs::rename(&temp, &path).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                // Only saves space where the links can be told apart
+                Ok(()) => {
+                    if file_id(&path).await.is_some() {
+                        freed += file_info.size;
+                    }
+                }
+                Err(e) => {
+                    let _ = fs::remove_file(&temp).await;
+                    warn!(
+                        "could not link {} to {}: {}",
+                        file_info.path,
+                        existing.display(),
+                        e
+                    );
+                }
+            }
         }
+        self.adjust_cached_size(-(freed as i64)).await;
+    }
 
-        let mut force = false;
-        let mut workshop_id = "";
+    /// Moves files archived by move_to_trash back to where they were.
+    async fn restore_from_trash(&self, trash: &str, files: &[FileInfo]) -> Result<usize> {
+        let trash = self.paths.trash_dir.join(trash);
+        let mut restored = 0;
+        let mut restored_size = 0;
+
+        let result = async {
+            for file_info in files {
+                let path = &file_info.path;
+                let src = trash.join(path);
+                let dest = self.paths.file_path(file_info);
+
+                if !fs::try_exists(&src).await? {
+                    println!("Warning: {} is missing from the trash", path);
+                    continue;
+                }
+                if fs::try_exists(&dest).await? {
+                    println!("Skipping {} - a file already exists at that path", path);
+                    continue;
+                }
 
-        for arg in args {
-            match *arg {
-                "-f" | "--force" => force = true,
-                id if !id.starts_with('-') => workshop_id = id,
-                _ => {
-                    println!("Unknown option: {}", arg);
-                    return Ok(());
+                let size = fs::metadata(&src).await?.len();
+                move_file(&src, &dest).await?;
+                if !self.linked_elsewhere(&dest, file_info).await {
+                    restored_size += size;
                 }
+                println!("Restored: {}", path);
+                restored += 1;
             }
+            Ok::<_, anyhow::Error>(())
         }
+        .await;
+        self.adjust_cached_size(restored_size as i64).await;
+        result?;
 
-        if workshop_id.is_empty() {
-            println!("workshop_id is required");
-            return Ok(());
-        }
-
-        self.download_generic(workshop_id, force).await
+        Ok(restored)
     }
 
-    async fn download_generic(&mut self, workshop_id: &str, force: bool) -> Result<()> {
-        let item = self
-            .parse_workshop_item(workshop_id)
-            .await
-            .context("Failed to fetch workshop information")?;
+    async fn cmd_undo(&mut self) -> Result<()> {
+        let entries = audit::read(&self.paths.audit_log).await?;
+        let reverted: Vec<DateTime<Utc>> = entries.iter().filter_map(|e| e.reverts).collect();
 
-        match item {
-            ParseResult::Item(file) => {
-                self.download_item(file, None, force).await?;
+        let target = entries.iter().rev().find_map(|e| match (&e.op, &e.trash, &e.previous) {
+            _ if reverted.contains(&e.time) => None,
+            (audit::Operation::Forget, _, Some(previous)) => Some((e, None, previous)),
+            (audit::Operation::Remove | audit::Operation::Update, Some(trash), Some(previous)) => {
+                Some((e, Some(trash), previous))
             }
-            ParseResult::Collection(collection) => {
-                self.download_collection(collection, force).await?;
+            _ => None,
+        });
+        let Some((entry, trash, previous)) = target else {
+            println!("Nothing to undo.");
+            return Ok(());
+        };
+
+        match (&entry.op, trash) {
+            // The files were kept, only the record needs to come back
+            (audit::Operation::Forget, _) => {
+                if self.metadata.contains_key(&entry.id) {
+                    println!("Cannot undo forgetting {} - it is tracked again", entry.id);
+                    return Ok(());
+                }
+                if !self.confirm(&format!("Track {} ({}) again?", entry.title, entry.id), true)? {
+                    return Ok(());
+                }
+            }
+            (_, None) => unreachable!("only forget entries have no trash"),
+            (audit::Operation::Remove, Some(trash)) => {
+                if self.metadata.contains_key(&entry.id) {
+                    println!(
+                        "Cannot undo removal of {} - it has been downloaded again since",
+                        entry.id
+                    );
+                    return Ok(());
+                }
+                if !self.confirm(
+                    &format!("Restore removed item {} ({})?", entry.title, entry.id),
+                    true,
+                )? {
+                    return Ok(());
+                }
+
+                let removed: Vec<FileInfo> = previous
+                    .files
+                    .iter()
+                    .filter(|f| entry.files.contains(&f.path))
+                    .cloned()
+                    .collect();
+                self.restore_from_trash(trash, &removed).await?;
+            }
+            (_, Some(trash)) => {
+                let current = match self.metadata.get(&entry.id) {
+                    Some(current) if Some(&current.changelog_id) == entry.changelog_id.as_ref() => {
+                        current.clone()
+                    }
+                    _ => {
+                        println!(
+                            "Cannot undo update of {} - it has changed again since",
+                            entry.id
+                        );
+                        return Ok(());
+                    }
+                };
+                if !self.confirm(
+                    &format!(
+                        "Roll back {} ({}) to changelog {}?",
+                        entry.title, entry.id, previous.changelog_id
+                    ),
+                    true,
+                )? {
+                    return Ok(());
+                }
+
+                let mut removed = 0;
+                let result = async {
+                    for file_info in &current.files {
+                        let full_path = self.paths.file_path(file_info);
+                        if !fs::try_exists(&full_path).await? {
+                            continue;
+                        }
+                        if !file_info.hash.is_empty() && !self.verify_file(file_info).await? {
+                            println!(
+                                "Skipping {} - file modified, delete manually",
+                                file_info.path
+                            );
+                            continue;
+                        }
+                        let size = fs::metadata(&full_path).await?.len();
+                        let shared = self.linked_elsewhere(&full_path, file_info).await;
+                        fs::remove_file(&full_path).await?;
+                        if !shared {
+                            removed += size;
+                        }
+                    }
+                    Ok::<_, anyhow::Error>(())
+                }
+                .await;
+                self.adjust_cached_size(-(removed as i64)).await;
+                result?;
+
+                self.restore_from_trash(trash, &previous.files).await?;
             }
         }
 
+        self.metadata.insert(entry.id.clone(), previous.clone());
+        self.save_metadata().await?;
+        self.update_outputs().await?;
+
+        self.record_audit(audit::AuditEntry {
+            time: Utc::now(),
+            op: audit::Operation::Undo,
+            id: entry.id.clone(),
+            title: entry.title.clone(),
+            files: previous.files.iter().map(|f| f.path.clone()).collect(),
+            changelog_id: Some(previous.changelog_id.clone()),
+            trash: None,
+            previous: None,
+            reverts: Some(entry.time),
+        })
+        .await;
+
+        println!("Undid {} of {}", entry.op.as_str(), entry.id);
         Ok(())
     }
 
-    async fn download_item(
-        &mut self,
-        item: WorkshopItem,
-        collection_id: Option<&str>,
-        force: bool,
-    ) -> Result<bool> {
-        println!("Downloading {}...", item.id);
-        if !force && self.quick_update(&item, collection_id).await? {
-            return Ok(true);
+    async fn record_audit(&self, entry: audit::AuditEntry) {
+        let compress = self.config.compress_state;
+        if let Err(e) = audit::append(&self.paths.audit_log, &entry, compress).await {
+            warn!("failed to write audit log: {:#}", e);
         }
+    }
 
-        let args = [
-            "+force_install_dir",
-            "./necodl",
-            "+login",
-            "anonymous",
-            "+workshop_download_item",
-            &self.config.appid,
-            &item.id,
-            "+quit",
-        ];
-
-        if !self.run_steamcmd(&args, false).await? {
-            eprintln!("Failed to download {}", item.id);
-            return Ok(false);
+    async fn cmd_history(&self, args: &[&str]) -> Result<()> {
+        let mut limit = 20;
+        let mut verbose = false;
+        let mut filter = None;
+
+        let mut iter = args.iter().copied();
+        while let Some(arg) = iter.next() {
+            match arg {
+                "-v" | "--verbose" => verbose = true,
+                "-n" | "--limit" => match iter.next().and_then(|n| n.parse().ok()) {
+                    Some(n) => limit = n,
+                    None => {
+                        println!("usage: history [<id|map>] [-n <count>] [-v]");
+                        return Ok(());
+                    }
+                },
+                value if !value.starts_with('-') => filter = Some(value),
+                _ => {
+                    println!("Unknown option: {}", arg);
+                    return Ok(());
+                }
+            }
         }
 
-        let source_path = self.paths.steamcmd_workshop_path(&self.config.appid, &item.id);
+        let entries = audit::read(&self.paths.audit_log).await?;
+        let matching: Vec<&audit::AuditEntry> = entries
+            .iter()
+            .filter(|e| match filter {
+                Some(f) => {
+                    e.id == f
+                        || e.files.iter().any(|path| {
+                            Path::new(path)
+                                .file_stem()
+                                .is_some_and(|stem| stem.eq_ignore_ascii_case(f))
+                        })
+                }
+                None => true,
+            })
+            .collect();
 
-        if !fs::try_exists(&source_path).await? {
-            eprintln!("Downloaded files not found at expected location");
-            return Ok(false);
+        if matching.is_empty() {
+            println!("No matching history entries.");
+            return Ok(());
         }
 
-        let files = self
-            .move_and_track_files(&source_path, &self.paths.local_files)
-            .await?;
-
-        if files.is_empty() {
-            eprintln!("No files found for workshop item {}", item.id);
-            return Ok(false);
+        for entry in &matching[matching.len().saturating_sub(limit)..] {
+            println!(
+                "{}  {:<8} {:<12} {} ({} files)",
+                entry.time.format("%Y-%m-%d %H:%M:%S"),
+                entry.op.as_str(),
+                entry.id,
+                entry.title,
+                entry.files.len()
+            );
+            if verbose {
+                for file in &entry.files {
+                    println!("    {}", file);
+                }
+            }
         }
 
-        let entry = self
-            .metadata
-            .entry(item.id.clone())
-            .or_insert_with(|| WorkshopMetadata {
-                title: item.title.clone(),
-                changelog_id: item.changelog_id.clone(),
-                files: Vec::new(),
-                collection_ids: Vec::new(),
-            });
+        Ok(())
+    }
 
-        entry.title = item.title;
-        entry.changelog_id = item.changelog_id;
-        entry.files = files;
+    fn info_config(&self) -> Vec<InfoField> {
+        let other_appids: Vec<String> = self
+            .metadata
+            .values()
+            .filter_map(|m| m.appid.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let map_lists: Vec<String> = self
+            .paths
+            .outputs
+            .iter()
+            .map(|o| o.path.display().to_string())
+            .collect();
+        vec![
+            InfoField::new("app_id", "App ID", &self.config.appid, self.config.appid.clone()),
+            InfoField::new(
+                "other_appids",
+                "Other App IDs",
+                if other_appids.is_empty() { "none".to_string() } else { other_appids.join(", ") },
+                other_appids.clone(),
+            ),
+            InfoField::new(
+                "steam_api_key",
+                "Steam Web API Key",
+                if self.api.has_key() { "configured" } else { "not set" },
+                self.api.has_key(),
+            ),
+            InfoField::new(
+                "map_lists",
+                "Map Lists",
+                if map_lists.is_empty() { "disabled".to_string() } else { map_lists.join(", ") },
+                map_lists,
+            ),
+            InfoField::new("auto_update", "Auto Update", self.config.auto_update, self.config.auto_update),
+        ]
+    }
 
-        if let Some(cid) = collection_id {
-            let cid_string = cid.to_string();
-            if !entry.collection_ids.contains(&cid_string) {
-                entry.collection_ids.push(cid_string);
-            }
+    fn info_paths(&self) -> Vec<InfoField> {
+        let path = |key, label: &str, path: &Path| {
+            InfoField::new(key, label, path.display(), path.display().to_string())
+        };
+        let mut fields = vec![
+            InfoField::new(
+                "profile",
+                "Profile",
+                self.profile_name(),
+                self.profile_name(),
+            ),
+            path("base_dir", "Profile Directory", &self.paths.base_dir),
+            path("metadata_file", "Metadata File", &self.metadata_paths().0),
+            path("output_dir", "Output Folder", &self.paths.local_files),
+            path("steamcmd", "SteamCMD", &self.paths.steamcmd),
+        ];
+        for (category, root) in &self.paths.content_roots {
+            fields.push(InfoField::new(
+                "content_root",
+                &format!("  {}/", category),
+                root.display(),
+                serde_json::json!({ "category": category, "path": root.display().to_string() }),
+            ));
         }
-
-        println!("Successfully downloaded {}", item.id);
-        self.save_metadata().await?;
-        self.update_workshop_maps().await?;
-        Ok(true)
+        fields
     }
 
-    async fn download_collection(
-        &mut self,
-        collection: WorkshopCollection,
-        force: bool,
-    ) -> Result<()> {
-        println!(
-            "Downloading collection: {} ({} items)",
-            collection.title,
-            collection.item_ids.len()
-        );
+    /// Name of the install, the directory holding config.toml
+    fn profile_name(&self) -> String {
+        self.paths
+            .base_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.paths.base_dir.display().to_string())
+    }
 
-        for file_id in &collection.item_ids {
-            let file = self
-                .parse_workshop_item(file_id)
-                .await
-                .context("Failed to fetch file info in collection")?;
+    async fn info_subscriptions(&self) -> Vec<InfoField> {
+        let count = |f: fn(&WorkshopMetadata) -> bool| self.metadata.values().filter(|m| f(m)).count();
+        let pending = follow::load(&self.paths.follows_file)
+            .await
+            .map(|f| f.pending.len())
+            .unwrap_or_default();
+
+        let mut fields = vec![
+            InfoField::new("total", "Total Subscriptions", self.metadata.len(), self.metadata.len()),
+            InfoField::new(
+                "updates_available",
+                "Updates Available",
+                count(|m| m.update_available.is_some()),
+                count(|m| m.update_available.is_some()),
+            ),
+            InfoField::new(
+                "unavailable",
+                "Unavailable",
+                count(|m| m.unavailable.is_some()),
+                count(|m| m.unavailable.is_some()),
+            ),
+            InfoField::new("archived", "Archived", count(|m| m.archived.is_some()), count(|m| m.archived.is_some())),
+            InfoField::new("collections", "Collections", self.collections.len(), self.collections.len()),
+            InfoField::new("pending_approval", "Pending Approval", pending, pending),
+        ];
 
-            if let ParseResult::Item(file_item) = file {
-                self.download_item(file_item, Some(&collection.id), force)
-                    .await?;
-            }
+        fields.push(self.info_daemon().await);
+        if let Some(path) = &self.paths.status_file
+            && let Ok(data) = fs::read_to_string(path).await
+            && let Ok(status) = serde_json::from_str::<serde_json::Value>(&data)
+        {
+            fields.push(InfoField::new(
+                "last_run",
+                "Last Run",
+                format!(
+                    "{} ({}) at {}",
+                    status["command"].as_str().unwrap_or("?"),
+                    status["result"].as_str().unwrap_or("?"),
+                    status["finished_at"].as_str().unwrap_or("?")
+                ),
+                serde_json::json!({
+                    "command": status["command"],
+                    "result": status["result"],
+                    "finished_at": status["finished_at"],
+                }),
+            ));
         }
-
-        Ok(())
+        fields
     }
 
-    async fn cmd_import(&mut self, path: &str) -> Result<()> {
-        let import_path = PathBuf::from(path);
-        if !import_path.exists() {
-            anyhow::bail!("File not found: {}", path);
+    async fn info_daemon(&self) -> InfoField {
+        let status = match self.daemon_running() {
+            true => fs::read_to_string(&self.paths.daemon_file)
+                .await
+                .ok()
+                .and_then(|data| serde_json::from_str::<DaemonStatus>(&data).ok()),
+            false => None,
+        };
+        let time = |t: DateTime<Utc>| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string();
+        match status {
+            Some(status) => InfoField::new(
+                "daemon",
+                "Daemon",
+                format!(
+                    "running since {} (pid {}), {}",
+                    time(status.started_at),
+                    status.pid,
+                    status
+                        .next_run
+                        .map(|next| format!("next update at {}", time(next)))
+                        .unwrap_or_else(|| "updating".to_string())
+                ),
+                serde_json::json!({
+                    "running": true,
+                    "pid": status.pid,
+                    "started_at": status.started_at,
+                    "interval": status.interval,
+                    "next_run": status.next_run,
+                }),
+            ),
+            None => InfoField::new("daemon", "Daemon", "not running", serde_json::json!({ "running": false })),
         }
+    }
 
-        let content = fs::read_to_string(&import_path)
-            .await
-            .with_context(|| format!("Failed to read {}", path))?;
-
-        let mut imported_count = 0;
-        let mut in_workshop_maps = false;
+    /// Whether a daemon holds the lock of this install
+    fn daemon_running(&self) -> bool {
+        std::fs::File::open(self.paths.daemon_file.with_extension("lock"))
+            .map(|file| fs2::FileExt::try_lock_shared(&file).is_err())
+            .unwrap_or(false)
+    }
 
-        for line in content.lines() {
-            let line = line.trim();
+    /// Takes the daemon lock, None if another daemon holds it. The lock is
+    /// released when the file is dropped, even if the process is killed.
+    fn lock_daemon(&self) -> Result<Option<std::fs::File>> {
+        let path = self.paths.daemon_file.with_extension("lock");
+        let file = std::fs::File::create(&path)
+            .with_context(|| NecoError::Fs(format!("failed to create {}", path.display())))?;
+        Ok(fs2::FileExt::try_lock_exclusive(&file).is_ok().then_some(file))
+    }
 
-            if line.is_empty() || line.starts_with("//") {
-                continue;
-            }
+    async fn write_daemon_status(&self, status: &DaemonStatus) {
+        let written = match serde_json::to_string_pretty(status) {
+            Ok(data) => fs::write(&self.paths.daemon_file, data).await.map_err(anyhow::Error::from),
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = written {
+            warn!("failed to write {}: {:#}", self.paths.daemon_file.display(), e);
+        }
+    }
 
-            if line == "\"WorkshopMaps\"" {
-                in_workshop_maps = true;
-                continue;
-            }
+    /// Total size of the files under `root`. Hard links to a file already in
+    /// `seen` count once, as the content is only stored once.
+    async fn calculate_directory_size(
+        &self,
+        root: &Path,
+        seen: &mut HashSet<(u64, u64)>,
+    ) -> Result<u64> {
+        let mut total = 0;
+        let mut stack = vec![root.to_path_buf()];
 
-            if !in_workshop_maps {
+        while let Some(path) = stack.pop() {
+            if !fs::try_exists(&path).await? {
                 continue;
             }
 
-            if line == "}" {
-                break;
-            }
-
-            if line.starts_with('"') {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() >= 2 {
-                    let map_name = parts[0].trim_matches('"');
-                    let workshop_id = parts[1].trim_matches('"');
+            let mut entries = fs::read_dir(&path).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let meta = fs::metadata(&path).await?;
 
-                    if workshop_id.parse::<u64>().is_ok()
-                        && !self.metadata.contains_key(workshop_id)
-                    {
-                        self.metadata.insert(
-                            workshop_id.to_string(),
-                            WorkshopMetadata {
-                                title: map_name.to_string(),
-                                changelog_id: "0".to_string(),
-                                files: Vec::new(),
-                                collection_ids: Vec::new(),
-                            },
-                        );
-                        imported_count += 1;
-                    }
+                if meta.is_dir() {
+                    stack.push(path);
+                } else if platform::file_id(&meta).is_none_or(|id| seen.insert(id)) {
+                    total += meta.len();
                 }
             }
         }
 
-        self.save_metadata().await?;
-        println!(
-            "Imported {} workshop IDs. Use 'update' to download them",
-            imported_count
-        );
-        Ok(())
+        Ok(total)
     }
 
-    async fn cmd_update(&mut self, args: &[&str]) -> Result<()> {
-        let force = args.contains(&"-f") || args.contains(&"--force");
+    async fn run_post_update_actions(&self) {
+        let updated = &self.updates;
+        if updated.is_empty() {
+            return;
+        }
 
-        let workshop_ids: Vec<String> = self.metadata.keys().cloned().collect();
-        if workshop_ids.is_empty() {
-            println!("No subscribed items. Use 'download <id>' to add items.");
-            return Ok(());
+        let Some(server) = &self.config.server else {
+            return;
+        };
+        let Some(password) = &server.rcon_password else {
+            return;
+        };
+        if server.post_update_commands.is_empty() {
+            return;
         }
 
-        println!(
-            "Updating {} items{}...",
-            workshop_ids.len(),
-            if force { " (forced)" } else { "" }
-        );
+        let address = server.rcon_address.as_deref().unwrap_or(&server.address);
+        let mut client = match rcon::RconClient::connect(address, password).await {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("skipping post-update commands: {:#}", e);
+                return;
+            }
+        };
 
-        for workshop_id in &workshop_ids {
-            if let ParseResult::Item(item) = self.parse_workshop_item(workshop_id).await? {
-                self.download_item(item, None, force).await?;
+        for command in &server.post_update_commands {
+            let command = command.replace("{count}", &updated.len().to_string());
+            match client.execute(&command).await {
+                Ok(response) => {
+                    println!("RCON > {}", command);
+                    if !response.trim().is_empty() {
+                        println!("{}", response.trim_end());
+                    }
+                }
+                Err(e) => {
+                    warn!("RCON command '{}' failed: {:#}", command, e);
+                    return;
+                }
             }
         }
-        Ok(())
     }
 
-    async fn cmd_list(&self, verbose: bool) -> Result<()> {
-        if self.metadata.is_empty() {
-            println!("No subscribed items. Use 'download <id>' to add items.");
-            return Ok(());
-        }
-
-        println!("Subscribed items ({}):", self.metadata.len());
-
-        if verbose {
-            println!("{}", "=".repeat(60));
-        }
-
-        for (workshop_id, metadata) in &self.metadata {
-            if verbose {
-                self.print_detailed_item(workshop_id, metadata)?;
-            } else {
-                let map_name = metadata
-                    .files
-                    .iter()
-                    .find(|f| f.path.ends_with(".bsp"))
-                    .and_then(|f| Path::new(&f.path).file_stem())
-                    .map(|s| s.to_string_lossy())
-                    .unwrap_or_else(|| "no_map".into());
+    async fn info_server(&self) -> Vec<InfoField> {
+        let Some(server) = &self.config.server else {
+            return Vec::new();
+        };
 
-                println!("{:<12} {}", workshop_id, map_name);
+        let mut fields = vec![InfoField::new("address", "Game Server", &server.address, server.address.clone())];
+        match a2s::query_info(&server.address).await {
+            Ok(info) => {
+                fields.push(InfoField::new("name", "Server Name", &info.name, info.name.clone()));
+                fields.push(InfoField::new(
+                    "map",
+                    "Current Map",
+                    format!("{} ({} players)", info.map, info.players),
+                    info.map.clone(),
+                ));
+                fields.push(InfoField::hidden("players", info.players));
             }
+            Err(e) => fields.push(InfoField::new(
+                "error",
+                "Server Status",
+                format!("unreachable ({:#})", e),
+                format!("{:#}", e),
+            )),
         }
+        fields
+    }
 
-        Ok(())
+    async fn load_size_cache(&self) -> Option<SizeCache> {
+        let data = fs::read_to_string(&self.paths.size_cache_file).await.ok()?;
+        serde_json::from_str(&data).ok()
     }
 
-    fn print_detailed_item(&self, workshop_id: &str, metadata: &WorkshopMetadata) -> Result<()> {
-        println!("ID: {}", workshop_id);
-        println!("Title: {}", metadata.title);
+    async fn save_size_cache(&self, cache: &SizeCache) -> Result<()> {
+        let data = serde_json::to_string_pretty(cache)?;
+        fs::write(&self.paths.size_cache_file, data)
+            .await
+            .context(NecoError::Fs("Failed to save size cache".to_string()))
+    }
 
-        if !metadata.collection_ids.is_empty() {
-            println!("Collections: {}", metadata.collection_ids.join(", "));
+    /// Applies a change in output_dir size to the cache. Without a cache there
+    /// is nothing to adjust, the next `info` calculates it from scratch.
+    async fn adjust_cached_size(&self, delta: i64) {
+        if delta == 0 {
+            return;
+        }
+        let Some(mut cache) = self.load_size_cache().await else {
+            return;
+        };
+        cache.bytes = cache.bytes.saturating_add_signed(delta);
+        if let Err(e) = self.save_size_cache(&cache).await {
+            warn!("{:#}", e);
         }
+    }
 
-        if !metadata.files.is_empty() {
-            println!("Files ({}):", metadata.files.len());
-            let current_dir = std::env::current_dir()?;
-            for file_info in &metadata.files {
-                let path = Path::new(&file_info.path);
-                let display_path = path.strip_prefix(&current_dir).unwrap_or(path);
-                println!("  - {}", display_path.display());
+    async fn info_storage(&self, recalculate: bool) -> Result<Vec<InfoField>> {
+        let output_dir = &self.paths.local_files;
+        let cached = if recalculate {
+            None
+        } else {
+            self.load_size_cache().await
+        };
+
+        let cache = match cached {
+            Some(cache) => cache,
+            None => {
+                let mut seen = HashSet::new();
+                let mut bytes = self.calculate_directory_size(output_dir, &mut seen).await?;
+                for (category, root) in &self.paths.content_roots {
+                    bytes += self
+                        .calculate_directory_size(&root.join(category), &mut seen)
+                        .await?;
+                }
+                let cache = SizeCache {
+                    bytes,
+                    calculated_at: Utc::now(),
+                };
+                self.save_size_cache(&cache).await?;
+                cache
             }
-        }
+        };
 
-        println!("{}", "-".repeat(40));
-        Ok(())
-    }
+        let mut fields = vec![
+            InfoField::new(
+                "used_bytes",
+                "Used Space",
+                format!(
+                    "{} (calculated {}, 'info --recalculate' to refresh)",
+                    format_file_size(cache.bytes),
+                    cache.calculated_at.format("%Y-%m-%d %H:%M UTC")
+                ),
+                cache.bytes,
+            ),
+            InfoField::hidden("calculated_at", cache.calculated_at.to_rfc3339()),
+        ];
 
-    async fn cmd_remove(&mut self, workshop_id: &str) -> Result<()> {
-        if workshop_id.is_empty() {
-            println!("usage: remove <workshop_id>");
-            return Ok(());
+        let shared = self.shared_content().await;
+        if shared.linked > 0 {
+            fields.push(InfoField::new(
+                "linked_files",
+                "Stored Once",
+                format!("{} files, {} saved", shared.linked, format_file_size(shared.saved_bytes)),
+                shared.linked,
+            ));
+            fields.push(InfoField::hidden("saved_bytes", shared.saved_bytes));
+        }
+        if shared.copies > 0 {
+            fields.push(InfoField::new(
+                "duplicate_files",
+                "Duplicate Content",
+                format!(
+                    "{} files, {} ({})",
+                    shared.copies,
+                    format_file_size(shared.copy_bytes),
+                    self.copies_note()
+                ),
+                shared.copies,
+            ));
+            fields.push(InfoField::hidden("duplicate_bytes", shared.copy_bytes));
         }
 
+        Ok(fields)
+    }
+
+    /// Counts tracked files sharing their content with another tracked file,
+    /// telling hard links apart from separate copies by inode
+    async fn shared_content(&self) -> SharedContent {
+        let mut groups: HashMap<(&str, u64), Vec<PathBuf>> = HashMap::new();
+        for file_info in self.metadata.values().flat_map(|m| &m.files) {
+            if file_info.sha256.is_empty() {
+                continue;
+            }
+            let path = self.paths.file_path(file_info);
+            let paths = groups.entry((&file_info.sha256, file_info.size)).or_default();
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+
+        let mut shared = SharedContent::default();
+        for ((_, size), paths) in groups.into_iter().filter(|(_, p)| p.len() > 1) {
+            let mut stored = HashSet::new();
+            let mut first = true;
+            for path in paths {
+                let Ok(meta) = fs::metadata(&path).await else {
+                    continue;
+                };
+                if platform::file_id(&meta).is_some_and(|id| !stored.insert(id)) {
+                    shared.linked += 1;
+                    shared.saved_bytes += size;
+                } else if first {
+                    first = false;
+                } else {
+                    shared.copies += 1;
+                    shared.copy_bytes += size;
+                }
+            }
+        }
+        shared
+    }
+
+    /// Why duplicate files are still stored as separate copies
+    fn copies_note(&self) -> &'static str {
+        if self.config.dedupe {
+            "installed before dedupe was enabled, or on another filesystem"
+        } else {
+            "set dedupe = true to store once"
+        }
+    }
+
+    /// Whether another tracked file is a hard link to the one at `path`, so
+    /// its content stays on disk when `path` goes
+    async fn linked_elsewhere(&self, path: &Path, file_info: &FileInfo) -> bool {
+        if file_info.sha256.is_empty() {
+            return false;
+        }
+        let Some(id) = file_id(path).await else {
+            return false;
+        };
+        for other in self
+            .metadata
+            .values()
+            .flat_map(|m| &m.files)
+            .filter(|f| f.sha256 == file_info.sha256 && f.size == file_info.size)
+        {
+            let other_path = self.paths.file_path(other);
+            if other_path != path && file_id(&other_path).await == Some(id) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Checks SteamCMD, the output folder and disk space, then times requests
+    /// to each Steam service NecoDL uses and an anonymous SteamCMD login, to
+    /// show which leg is slow when downloads crawl
+    async fn cmd_doctor(&self, args: &[&str]) -> Result<()> {
+        const SLOW_REQUEST: Duration = Duration::from_secs(2);
+        const SLOW_LOGIN: Duration = Duration::from_secs(30);
+        const LOW_SPACE: u64 = 1024 * 1024 * 1024;
+        const ENDPOINTS: [(&str, &str); 3] = [
+            ("Steam Community", "https://steamcommunity.com/"),
+            ("Steam Web API", "https://api.steampowered.com/ISteamWebAPIUtil/GetServerInfo/v1/"),
+            ("Steam CDN", "https://cdn.akamai.steamstatic.com/"),
+        ];
+        let mut login = true;
+        for arg in args {
+            match *arg {
+                "--no-steamcmd" => login = false,
+                _ => {
+                    println!("Unknown option: {}", arg);
+                    return Ok(());
+                }
+            }
+        }
+
+        let mut failed = 0;
+        let mut slow = Vec::new();
+        let mut report = |status: &str, label: &str, detail: String| {
+            if status == "FAIL" {
+                failed += 1;
+            }
+            if status == "SLOW" {
+                slow.push(label.to_string());
+            }
+            println!("  {:<5} {:<17} {}", status, label, detail);
+        };
+
+        println!("Environment");
+        let steamcmd = &self.paths.steamcmd;
+        let steamcmd_found = steamcmd.is_file();
+        if steamcmd_found {
+            report("OK", "SteamCMD", steamcmd.display().to_string());
+        } else {
+            report("FAIL", "SteamCMD", format!("not found at {}", steamcmd.display()));
+        }
+
+        let probe = self.paths.local_files.join(".necodl-doctor");
+        match fs::write(&probe, b"").await {
+            Ok(()) => {
+                let _ = fs::remove_file(&probe).await;
+                report("OK", "Output folder", self.paths.local_files.display().to_string());
+            }
+            Err(e) => report("FAIL", "Output folder", format!("not writable: {}", e)),
+        }
+
+        for dir in [&self.paths.local_files, &self.paths.steamcmd_root] {
+            match fs2::available_space(dir) {
+                Ok(free) => report(
+                    if free < LOW_SPACE { "WARN" } else { "OK" },
+                    "Free space",
+                    format!("{} in {}", format_file_size(free), dir.display()),
+                ),
+                Err(e) => report("WARN", "Free space", format!("unknown for {}: {}", dir.display(), e)),
+            }
+        }
+
+        println!("\nNetwork");
+        for (label, url) in ENDPOINTS {
+            let started = std::time::Instant::now();
+            let response = self.http.get(url).timeout(Duration::from_secs(15)).send().await;
+            let elapsed = started.elapsed();
+            match response {
+                Ok(response) => report(
+                    if elapsed > SLOW_REQUEST { "SLOW" } else { "OK" },
+                    label,
+                    format!("{} ms (HTTP {})", elapsed.as_millis(), response.status().as_u16()),
+                ),
+                Err(e) => report("FAIL", label, format!("{:#}", e)),
+            }
+        }
+
+        if login && steamcmd_found {
+            println!("Logging in to Steam with SteamCMD, this can take a while...");
+            let started = std::time::Instant::now();
+            let result = steamcmd::check_login(steamcmd, &Self::steamcmd_args(&self.config)).await;
+            let elapsed = started.elapsed();
+            match result {
+                Ok(None) => report(
+                    if elapsed > SLOW_LOGIN { "SLOW" } else { "OK" },
+                    "SteamCMD login",
+                    format!("{:.1} s", elapsed.as_secs_f64()),
+                ),
+                Ok(Some(failure)) => report("FAIL", "SteamCMD login", failure.to_string()),
+                Err(e) => report("FAIL", "SteamCMD login", format!("{:#}", e)),
+            }
+        }
+
+        println!();
+        if !slow.is_empty() {
+            println!("Slow: {}", slow.join(", "));
+        }
+        if failed > 0 {
+            anyhow::bail!("{} checks failed", failed);
+        }
+        if slow.is_empty() {
+            println!("All checks passed");
+        }
+        Ok(())
+    }
+
+    async fn cmd_info(&self, args: &[&str]) -> Result<()> {
+        const SECTIONS: [&str; 5] = ["config", "paths", "subs", "server", "storage"];
+        let mut recalculate = false;
+        let mut json = false;
+        let mut sections = Vec::new();
+
+        let mut iter = args.iter().copied();
+        while let Some(arg) = iter.next() {
+            match arg {
+                "--recalculate" => recalculate = true,
+                "--json" => json = true,
+                "--section" => match iter.next() {
+                    Some(list) if list.split(',').all(|s| SECTIONS.contains(&s)) => {
+                        sections.extend(list.split(','));
+                    }
+                    _ => {
+                        println!("--section must be one of: {}", SECTIONS.join(", "));
+                        return Ok(());
+                    }
+                },
+                _ => {
+                    println!("Unknown option: {}", arg);
+                    return Ok(());
+                }
+            }
+        }
+        if sections.is_empty() {
+            sections.extend(SECTIONS);
+        }
+
+        let mut report = serde_json::Map::new();
+        for section in SECTIONS.iter().filter(|s| sections.contains(s)) {
+            let fields = match *section {
+                "config" => self.info_config(),
+                "paths" => self.info_paths(),
+                "subs" => self.info_subscriptions().await,
+                "server" => self.info_server().await,
+                _ => self.info_storage(recalculate).await?,
+            };
+
+            if json {
+                let mut object = serde_json::Map::new();
+                for field in fields {
+                    match object.get_mut(field.key) {
+                        // Repeated keys, like content roots, become a list
+                        Some(serde_json::Value::Array(values)) => values.push(field.value),
+                        Some(_) => {}
+                        None if field.key == "content_root" => {
+                            object.insert(field.key.to_string(), serde_json::Value::Array(vec![field.value]));
+                        }
+                        None => {
+                            object.insert(field.key.to_string(), field.value);
+                        }
+                    }
+                }
+                report.insert(section.to_string(), serde_json::Value::Object(object));
+            } else {
+                for field in fields.iter().filter(|f| !f.label.is_empty()) {
+                    println!("{:<25}: {}", field.label, field.text);
+                }
+            }
+        }
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Ok(())
+    }
+
+    async fn cmd_download(&mut self, args: &[&str]) -> Result<()> {
+        const USAGE: &str = "usage: download [-f|--force|--reinstall] [--existing update|verify|skip] [--appid <appid>] <workshop_id>...";
+        if args.is_empty() {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+
+        let mut force = false;
+        let mut reinstall = false;
+        let mut existing = None;
+        let mut appid = None;
+        let mut workshop_ids = Vec::new();
+
+        let mut iter = args.iter().copied();
+        while let Some(arg) = iter.next() {
+            match arg {
+                "-f" | "--force" => force = true,
+                "--reinstall" => reinstall = true,
+                "--existing" => match iter.next().and_then(ExistingAction::parse) {
+                    Some(action) => existing = Some(action),
+                    None => {
+                        println!("{}", USAGE);
+                        return Ok(());
+                    }
+                },
+                "--appid" => match iter.next().filter(|a| a.parse::<u32>().is_ok()) {
+                    Some(value) => appid = Some(value),
+                    None => {
+                        println!("{}", USAGE);
+                        return Ok(());
+                    }
+                },
+                id if !id.starts_with('-') => workshop_ids.push(id),
+                _ => {
+                    println!("Unknown option: {}", arg);
+                    return Ok(());
+                }
+            }
+        }
+
+        let workshop_id = match workshop_ids.as_slice() {
+            [] => {
+                println!("workshop_id is required");
+                return Ok(());
+            }
+            [id] => *id,
+            _ if reinstall || existing.is_some() => {
+                println!("--reinstall and --existing take a single workshop_id");
+                return Ok(());
+            }
+            ids => return self.download_batch(ids, force, appid).await,
+        };
+        let Some(parsed_id) = parse_workshop_id(workshop_id) else {
+            self.suggest_items(workshop_id).await;
+            return Ok(());
+        };
+        let workshop_id = parsed_id.as_str();
+
+        if let Some(appid) = appid {
+            self.appid_overrides
+                .insert(workshop_id.to_string(), appid.to_string());
+        }
+        if reinstall {
+            return self.reinstall_item(workshop_id).await;
+        }
+        let result = self.download_generic(workshop_id, force, existing).await;
+        self.appid_overrides.clear();
+        result
+    }
+
+    /// `download` with several IDs: no prompts, a line per item at the end
+    async fn download_batch(&mut self, inputs: &[&str], force: bool, appid: Option<&str>) -> Result<()> {
+        let mut ids = Vec::new();
+        for input in inputs {
+            match parse_workshop_id(input) {
+                Some(id) => ids.push(id),
+                None => {
+                    println!("'{}' is not a Workshop item ID or URL", input);
+                    return Ok(());
+                }
+            }
+        }
+        if let Some(appid) = appid {
+            for id in &ids {
+                self.appid_overrides.insert(id.clone(), appid.to_string());
+            }
+        }
+
+        let results = self.download_many(&ids, force).await;
+        self.appid_overrides.clear();
+        let results = results?;
+
+        println!("\nResults ({} items):", results.len());
+        for result in &results {
+            println!(
+                "  {:<12} {:<11} {}{}",
+                result.id,
+                result.status.as_str(),
+                result.title,
+                result.error.as_deref().map(|e| format!(" - {}", e)).unwrap_or_default()
+            );
+        }
+        self.print_failures();
+        self.print_run_stats();
+        self.run_post_update_actions().await;
+        Ok(())
+    }
+
+    /// Downloads or updates items and collections by ID, returning what
+    /// became of each item, collection members included. An ID that fails
+    /// is recorded as failed and the others still go ahead.
+    async fn download_many(&mut self, ids: &[String], force: bool) -> Result<Vec<report::ItemResult>> {
+        let first = self.stats.results.len();
+        self.stats.start();
+
+        self.backends.info.prefetch(ids).await;
+        for workshop_id in ids {
+            let recorded = self.stats.results.len();
+            if let Err(e) = self.download_id(workshop_id, force).await {
+                let reason = format!("{:#}", e);
+                self.fail_item(workshop_id, &reason);
+                // download_item has already recorded a failed item
+                if !self.stats.results[recorded..].iter().any(|r| r.id == *workshop_id) {
+                    let title = self
+                        .metadata
+                        .get(workshop_id)
+                        .map(|m| m.title.clone())
+                        .unwrap_or_default();
+                    let result =
+                        self.item_result(workshop_id, &title, report::ItemStatus::Failed, Some(reason));
+                    self.stats.results.push(result);
+                }
+            }
+        }
+
+        Ok(self.stats.results[first..].to_vec())
+    }
+
+    /// Downloads one ID given to download_many
+    async fn download_id(&mut self, workshop_id: &str, force: bool) -> Result<()> {
+        let fetched = self
+            .backends
+            .info
+            .fetch(workshop_id)
+            .await
+            .context("Failed to fetch workshop information")?;
+        match fetched {
+            ParseResult::Item(item) => {
+                self.download_item(item, None, force).await?;
+            }
+            ParseResult::Collection(collection) => {
+                let items = self.resolve_collection(&collection).await?;
+                self.download_collection_items(&collection, items, force).await?;
+            }
+            ParseResult::Unavailable(reason) => {
+                if self.metadata.contains_key(workshop_id) {
+                    self.mark_unavailable(workshop_id, &reason).await?;
+                } else {
+                    println!("{} is unavailable on the Workshop ({})", workshop_id, reason);
+                }
+                self.record_unavailable(workshop_id, &reason);
+            }
+        }
+        Ok(())
+    }
+
+    fn record_unavailable(&mut self, workshop_id: &str, reason: &str) {
+        let title = self
+            .metadata
+            .get(workshop_id)
+            .map(|m| m.title.clone())
+            .unwrap_or_default();
+        let result = self.item_result(
+            workshop_id,
+            &title,
+            report::ItemStatus::Unavailable,
+            Some(reason.to_string()),
+        );
+        self.stats.results.push(result);
+    }
+
+    /// Explains that `query` is no Workshop ID and lists items it may have
+    /// meant, like a map name, from the subscriptions and a Workshop search
+    async fn suggest_items(&self, query: &str) {
+        println!("'{}' is not a Workshop item ID or URL", query);
+
+        let needle = query.to_lowercase();
+        let local: Vec<(&String, &WorkshopMetadata)> = self
+            .metadata
+            .iter()
+            .filter(|(_, m)| {
+                m.title.to_lowercase().contains(&needle)
+                    || group_map_files(&m.files)
+                        .0
+                        .iter()
+                        .any(|map| map.name.to_lowercase().contains(&needle))
+            })
+            .collect();
+        if !local.is_empty() {
+            println!("Subscribed items matching it:");
+            for (id, metadata) in local.iter().take(5) {
+                println!("  {:<12} {}", id, metadata.title);
+            }
+        }
+
+        if !self.api.has_key() {
+            println!("Set steam_api_key to search the Workshop by name, see 'search'");
+            return;
+        }
+        match self.api.search(&self.config.appid, query, 5).await {
+            Ok(results) if !results.is_empty() => {
+                println!("Workshop items matching it:");
+                for item in results {
+                    println!("  {:<12} {}", item.publishedfileid, item.title);
+                }
+                println!("Use 'download <id>' with one of these IDs");
+            }
+            Ok(_) => println!("No Workshop items match '{}'", query),
+            Err(e) => warn!("Workshop search failed: {:#}", e),
+        }
+    }
+
+    /// Asks what to do when `download` is given an item that is already
+    /// subscribed. Without a terminal the regular update path is taken.
+    fn existing_action(&self, workshop_id: &str) -> Result<ExistingAction> {
+        if self.assume_yes || !std::io::stdin().is_terminal() {
+            return Ok(ExistingAction::Update);
+        }
+
+        let title = &self.metadata[workshop_id].title;
+        loop {
+            print!(
+                "{} ({}) is already subscribed. [U]pdate, [v]erify files or do [n]othing? ",
+                title, workshop_id
+            );
+            std::io::stdout().flush()?;
+
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer)?;
+
+            match answer.trim().to_lowercase().as_str() {
+                "" | "u" | "update" => return Ok(ExistingAction::Update),
+                "v" | "verify" => return Ok(ExistingAction::Verify),
+                "n" | "nothing" | "no" => return Ok(ExistingAction::Skip),
+                _ => {}
+            }
+        }
+    }
+
+    async fn download_generic(
+        &mut self,
+        workshop_id: &str,
+        force: bool,
+        existing: Option<ExistingAction>,
+    ) -> Result<()> {
+        if !force && self.metadata.contains_key(workshop_id) {
+            let action = match existing {
+                Some(action) => action,
+                None => self.existing_action(workshop_id)?,
+            };
+            match action {
+                ExistingAction::Update => {}
+                ExistingAction::Verify => return self.cmd_verify(&[workshop_id]).await,
+                ExistingAction::Skip => {
+                    println!("{} is already subscribed, nothing to do", workshop_id);
+                    return Ok(());
+                }
+            }
+        }
+
+        self.stats.start();
+        let item = self
+            .backends
+            .info
+            .fetch(workshop_id)
+            .await
+            .context("Failed to fetch workshop information")?;
+
+        match item {
+            ParseResult::Item(file) => {
+                self.download_item(file, None, force).await?;
+            }
+            ParseResult::Collection(collection) => {
+                let items = self.resolve_collection(&collection).await?;
+                let items = self.preview_collection(&collection, items)?;
+                if items.is_empty() {
+                    println!("Nothing to download.");
+                    return Ok(());
+                }
+                self.download_collection_items(&collection, items, force).await?;
+                self.print_failures();
+            }
+            ParseResult::Unavailable(reason) => {
+                if self.metadata.contains_key(workshop_id) {
+                    self.mark_unavailable(workshop_id, &reason).await?;
+                } else {
+                    println!("{} is unavailable on the Workshop ({})", workshop_id, reason);
+                }
+            }
+        }
+
+        self.print_run_stats();
+        self.run_post_update_actions().await;
+        Ok(())
+    }
+
+    /// Brings an item up to date, timing it for the run's statistics
+    async fn download_item(
+        &mut self,
+        item: WorkshopItem,
+        collection_id: Option<&str>,
+        force: bool,
+    ) -> Result<bool> {
+        platform::watchdog_ping();
+        let workshop_id = item.id.clone();
+        let title = item.title.clone();
+        self.stats.start();
+        let started = std::time::Instant::now();
+        let failed_before = self.failed_items.len();
+        let updated_before = self.updates.len();
+        let bytes_before = self.stats.bytes_downloaded;
+
+        let result = self.sync_item(item, collection_id, force).await;
+        let elapsed = started.elapsed();
+        self.stats.item_times.push((workshop_id.clone(), elapsed));
+
+        let failure = self.failed_items[failed_before..]
+            .iter()
+            .find(|f| f.id == workshop_id)
+            .map(|f| f.reason.clone());
+        let (status, error) = match &result {
+            Err(e) => (report::ItemStatus::Failed, Some(format!("{:#}", e))),
+            Ok(_) if failure.is_some() => (report::ItemStatus::Failed, failure),
+            Ok(true) if self.updates.len() > updated_before => (report::ItemStatus::Updated, None),
+            Ok(true) => (report::ItemStatus::UpToDate, None),
+            Ok(false) => (report::ItemStatus::Skipped, None),
+        };
+        if status != report::ItemStatus::Failed {
+            self.processed.insert(workshop_id.clone());
+        }
+        let mut item_result = self.item_result(&workshop_id, &title, status, error);
+        item_result.bytes = self.stats.bytes_downloaded - bytes_before;
+        item_result.duration_secs = elapsed.as_secs_f64();
+        self.stats.results.push(item_result);
+        result
+    }
+
+    /// Result entry for an item with the files it has installed now
+    fn item_result(
+        &self,
+        workshop_id: &str,
+        title: &str,
+        status: report::ItemStatus,
+        error: Option<String>,
+    ) -> report::ItemResult {
+        report::ItemResult {
+            id: workshop_id.to_string(),
+            title: title.to_string(),
+            status,
+            files: self
+                .metadata
+                .get(workshop_id)
+                .map(|m| m.files.iter().map(|f| f.path.clone()).collect())
+                .unwrap_or_default(),
+            bytes: 0,
+            duration_secs: 0.0,
+            error,
+        }
+    }
+
+    /// Downloads an item, handing it back if it failed transiently so it
+    /// can be tried again
+    async fn download_retryable(
+        &mut self,
+        item: WorkshopItem,
+        collection_id: Option<&str>,
+        force: bool,
+    ) -> Result<Option<WorkshopItem>> {
+        let failed_before = self.failed_items.len();
+        let retry = item.clone();
+        self.download_item(item, collection_id, force).await?;
+        let transient = self.failed_items[failed_before..]
+            .iter()
+            .any(|f| f.id == retry.id && f.is_transient());
+        Ok(transient.then_some(retry))
+    }
+
+    async fn sync_item(
+        &mut self,
+        item: WorkshopItem,
+        collection_id: Option<&str>,
+        force: bool,
+    ) -> Result<bool> {
+        if self.metadata.get(&item.id).is_some_and(|m| m.archived.is_some()) {
+            println!("{} is archived, use 'unarchive {}' to restore it", item.id, item.id);
+            return Ok(false);
+        }
+
+        match item.file_size {
+            Some(size) => println!("Downloading {} ({})...", item.id, format_file_size(size)),
+            None => println!("Downloading {}...", item.id),
+        }
+        let platform_changed = self.platform_changed(&item.id);
+        if platform_changed {
+            println!("{} was downloaded for another platform, fetching it again", item.id);
+        }
+        let appid_changed = self.appid_overrides.get(&item.id).is_some_and(|appid| {
+            self.metadata.get(&item.id).is_some_and(|m| {
+                m.appid.as_deref().unwrap_or(&self.config.appid) != appid
+            })
+        });
+        if appid_changed {
+            println!("{} moves to appid {}, fetching it again", item.id, self.appid(&item.id));
+        }
+        let force = force || platform_changed || appid_changed;
+        let failed_before = self.failed_items.len();
+        if !force && self.quick_update(&item, collection_id).await? {
+            return Ok(true);
+        }
+        // A repair whose download failed isn't tried again right away
+        if self.failed_items.len() > failed_before {
+            return Ok(false);
+        }
+
+        if let Some(size) = item.file_size
+            && let Some(reason) = self.check_free_space(size)
+        {
+            self.fail_item(&item.id, &reason);
+            return Ok(false);
+        }
+
+        if let Some(metadata) = self.metadata.get(&item.id)
+            && self.is_map_live(metadata).await
+        {
+            println!(
+                "Deferring {} - map is currently being played, it will be updated after the next map change",
+                item.id
+            );
+            return Ok(false);
+        }
+
+        if self.config.modified_files != ModifiedFilesPolicy::Overwrite {
+            let modified = self.modified_files(&item.id).await?;
+            if !modified.is_empty() && !self.accept_modified(&item, &modified)? {
+                self.fail_item(
+                    &item.id,
+                    &format!("{} locally modified files would be overwritten", modified.len()),
+                );
+                return Ok(false);
+            }
+        }
+
+        let workshop_id = item.id.clone();
+        let mut job = self.sandbox_for(&workshop_id).await;
+        let result = self.install_item(item, collection_id, &mut job).await;
+        let keep = self.config.keep_download_cache && matches!(result, Ok(true));
+        self.clean_sandbox(job.sandbox.index(), &workshop_id, keep).await;
+        result
+    }
+
+    /// Installed files of an item that no longer match their recorded hash
+    async fn modified_files(&self, workshop_id: &str) -> Result<Vec<String>> {
+        let Some(metadata) = self.metadata.get(workshop_id) else {
+            return Ok(Vec::new());
+        };
+
+        let mut modified = Vec::new();
+        for file_info in metadata.files.iter().filter(|f| !f.hash.is_empty()) {
+            if fs::try_exists(self.paths.file_path(file_info)).await?
+                && !self.verify_file(file_info).await?
+            {
+                modified.push(file_info.path.clone());
+            }
+        }
+        Ok(modified)
+    }
+
+    /// Applies the modified_files policy to an update. Returns whether to go ahead.
+    fn accept_modified(&self, item: &WorkshopItem, modified: &[String]) -> Result<bool> {
+        println!("{} ({}) has locally modified files:", item.title, item.id);
+        for path in modified {
+            println!("  {}", path);
+        }
+
+        match self.config.modified_files {
+            ModifiedFilesPolicy::Skip => Ok(false),
+            ModifiedFilesPolicy::Prompt => {
+                self.confirm("Overwrite them? The current files are kept in trash/", false)
+            }
+            ModifiedFilesPolicy::Backup | ModifiedFilesPolicy::Overwrite => {
+                println!("They will be kept in trash/, use 'undo' to get them back");
+                Ok(true)
+            }
+        }
+    }
+
+    /// Has SteamCMD download an item into the job's sandbox, or waits for the
+    /// download started there ahead, and returns the workshop directory it
+    /// ended up in along with the item's content path. Failures are recorded
+    /// with fail_item.
+    async fn fetch_content(
+        &mut self,
+        item: &WorkshopItem,
+        job: &mut SandboxJob,
+    ) -> Result<Option<(PathBuf, PathBuf)>> {
+        let appid = self.appid(&item.id).to_string();
+        let sandbox_dir = self.paths.steamcmd_workshop_dir(job.sandbox.index());
+        let reported_dir;
+
+        let (outcome, elapsed, started_at) = match job.ahead.take() {
+            Some(ahead) => {
+                let (outcome, elapsed) = ahead
+                    .task
+                    .await
+                    .context(NecoError::SteamCmd("SteamCMD download task failed".to_string()))?;
+                (outcome?, elapsed, ahead.started_at)
+            }
+            None => {
+                let started = std::time::Instant::now();
+                let started_at = std::time::SystemTime::now();
+                let outcome = self
+                    .backends
+                    .downloader
+                    .download(&appid, &item.id, &self.paths.steamcmd_install_dir(job.sandbox.index()))
+                    .await?;
+                (outcome, started.elapsed(), started_at)
+            }
+        };
+
+        match outcome {
+            steamcmd::Outcome::Downloaded { path, size } => {
+                self.stats.bytes_downloaded += size;
+                self.record_throughput(&item.id, size, elapsed).await;
+                let path = if path.is_relative() {
+                    self.paths.steamcmd_root.join(path)
+                } else {
+                    path
+                }
+                .clean();
+
+                // Distro-packaged SteamCMD may ignore force_install_dir and
+                // download into its own Steam root, follow it there
+                match steamcmd::workshop_dir_of(&path, &appid, &item.id) {
+                    Some(dir) if self.paths.accepts_download(&dir) => reported_dir = dir,
+                    _ => {
+                        let expected = steamcmd::content_path(&sandbox_dir, &appid, &item.id);
+                        self.fail_empty(&item.id, &EmptyDownload::Elsewhere { reported: path, expected });
+                        return Ok(None);
+                    }
+                }
+                println!("SteamCMD downloaded {} ({})", item.id, format_file_size(size));
+            }
+            steamcmd::Outcome::Failed(failure) => {
+                self.fail_steamcmd(&item.id, &failure);
+                return Ok(None);
+            }
+            steamcmd::Outcome::Unknown => {
+                println!(
+                    "Warning: SteamCMD did not report a result for {}, checking for downloaded files",
+                    item.id
+                );
+                // Content left over from an earlier run (keep_download_cache)
+                // is not this download, SteamCMD rewrites the manifest
+                // whenever it installs an item. A distro package may have
+                // written it in its own Steam root instead.
+                let candidates = std::iter::once(sandbox_dir.clone()).chain(
+                    self.paths
+                        .distro_steam_roots
+                        .iter()
+                        .map(|root| root.join("steamapps/workshop")),
+                );
+                let mut fresh = None;
+                for dir in candidates {
+                    let modified = fs::metadata(steamcmd::manifest_path(&dir, &appid))
+                        .await
+                        .and_then(|m| m.modified());
+                    if modified.is_ok_and(|modified| modified >= started_at) {
+                        fresh = Some(dir);
+                        break;
+                    }
+                }
+                let Some(dir) = fresh else {
+                    self.fail_empty(&item.id, &EmptyDownload::NotFound);
+                    return Ok(None);
+                };
+                reported_dir = dir;
+            }
+        }
+
+        let Some(workshop_dir) = self.locate_download(&reported_dir, &item.id).await? else {
+            self.fail_empty(&item.id, &EmptyDownload::NotFound);
+            return Ok(None);
+        };
+        if workshop_dir != sandbox_dir {
+            println!(
+                "Warning: SteamCMD ignored force_install_dir, using files in {}",
+                workshop_dir.display()
+            );
+        }
+        let source_path = steamcmd::content_path(&workshop_dir, &appid, &item.id);
+
+        if let Some(problem) = self
+            .check_workshop_manifest(item, &source_path, &workshop_dir)
+            .await?
+        {
+            self.fail_item(&item.id, &problem);
+            return Ok(None);
+        }
+
+        Ok(Some((workshop_dir, source_path)))
+    }
+
+    /// Downloads an item with SteamCMD into the job's sandbox and moves its
+    /// files into output_dir.
+    async fn install_item(
+        &mut self,
+        item: WorkshopItem,
+        collection_id: Option<&str>,
+        job: &mut SandboxJob,
+    ) -> Result<bool> {
+        let sandbox_dir = self.paths.steamcmd_workshop_dir(job.sandbox.index());
+        let Some((workshop_dir, source_path)) = self.fetch_content(&item, job).await? else {
+            return Ok(false);
+        };
+
+        let id = item.id.clone();
+        let result = self.install_from(item, collection_id, &source_path).await;
+        if workshop_dir != sandbox_dir {
+            self.clean_workshop_item(&workshop_dir, &id).await;
+        }
+        result
+    }
+
+    /// Moves downloaded content into output_dir and records the item, keeping
+    /// the previous version in trash/. With keep_download_cache the content
+    /// is copied and stays where it is.
+    async fn install_from(
+        &mut self,
+        item: WorkshopItem,
+        collection_id: Option<&str>,
+        source_path: &Path,
+    ) -> Result<bool> {
+        let subdir = self.output_subdir(&item.id, collection_id);
+        let dest = match &subdir {
+            Some(dir) => self.paths.local_files.join(dir),
+            None => self.paths.local_files.clone(),
+        };
+
+        // Check the download before touching the installed version, so a
+        // download that would install nothing leaves the item as it was
+        let max_size = self.max_file_size(&item.id);
+        let mut scanned = self.scan_download(source_path, max_size).await?;
+        if scanned.files.is_empty() {
+            let cause = EmptyDownload::classify(&scanned, self.whitelist.is_none());
+            self.note_skipped(&item.id, &mut scanned);
+            self.fail_empty(&item.id, &cause);
+            return Ok(false);
+        }
+
+        // Keep the replaced version around so the update can be undone
+        let previous = self.metadata.get(&item.id).cloned();
+        let trashed = match &previous {
+            Some(m) if !m.files.is_empty() => Some(self.move_to_trash(&item.id, &m.files, false).await?),
+            _ => None,
+        };
+
+        let moved = match self.move_and_track_files(source_path, &dest, max_size).await {
+            Ok(moved) if !moved.files.is_empty() => Ok(moved),
+            Ok(moved) => Err(anyhow::anyhow!(EmptyDownload::classify(&moved, self.whitelist.is_none()))),
+            Err(e) => Err(e),
+        };
+        let mut moved = match moved {
+            Ok(moved) => moved,
+            Err(e) => {
+                if let (Some((trash, paths)), Some(previous)) = (&trashed, &previous) {
+                    self.put_back(trash, paths, previous).await;
+                }
+                return Err(e.context(format!("Failed to install {}", item.id)));
+            }
+        };
+        let archive = trashed.map(|(trash, _)| trash);
+
+        self.note_skipped(&item.id, &mut moved);
+        let mut files = moved.files;
+        self.stats.bytes_copied += files.iter().map(|f| f.size).sum::<u64>();
+        if let Some(dir) = &subdir {
+            for file in files.iter_mut().filter(|f| f.root.is_none()) {
+                file.path = dir.join(&file.path).to_string_lossy().to_string();
+            }
+        }
+        if self.config.dedupe {
+            self.link_duplicates(&item.id, &files).await;
+        }
+
+        self.updates
+            .push(Self::describe_update(&item, previous.as_ref(), &files));
+
+        let op = match archive {
+            Some(_) => audit::Operation::Update,
+            None => audit::Operation::Download,
+        };
+        self.record_audit(audit::AuditEntry {
+            time: Utc::now(),
+            op,
+            id: item.id.clone(),
+            title: item.title.clone(),
+            files: files.iter().map(|f| f.path.clone()).collect(),
+            changelog_id: Some(item.changelog_id.clone()),
+            trash: archive,
+            previous,
+            reverts: None,
+        })
+        .await;
+
+        let entry = self
+            .metadata
+            .entry(item.id.clone())
+            .or_default();
+
+        entry.rename(&item.title);
+        entry.changelog_id = item.changelog_id;
+        entry.update_available = None;
+        entry.files = files;
+        entry.skipped_files = moved.rejected;
+        entry.updated_at = Some(Utc::now());
+        entry.file_size = item.file_size;
+        entry.time_updated = item.time_updated;
+        if let Some(required_items) = item.required_items {
+            entry.requires = required_items;
+        }
+        entry.platform = self.config.platform.map(|p| p.as_str().to_string());
+        if let Some(appid) = self.appid_overrides.remove(&item.id) {
+            entry.appid = Some(appid).filter(|appid| *appid != self.config.appid);
+        }
+
+        if let Some(cid) = collection_id {
+            let cid_string = cid.to_string();
+            if !entry.collection_ids.contains(&cid_string) {
+                entry.collection_ids.push(cid_string);
+            }
+        }
+
+        for map in group_map_files(&entry.files).0 {
+            if !map.has_navmesh() {
+                println!("Warning: map {} has no navmesh (.nav), bots will not work", map.name);
+            }
+        }
+
+        println!("Successfully downloaded {}", item.id);
+        self.save_metadata().await?;
+        self.update_outputs().await?;
+        Ok(true)
+    }
+
+    /// Records the files a download left out for the run summary
+    fn note_skipped(&mut self, workshop_id: &str, moved: &mut MovedFiles) {
+        self.skipped_files
+            .extend(moved.oversized.drain(..).map(|(path, size)| SkippedFile {
+                id: workshop_id.to_string(),
+                path,
+                size,
+            }));
+        if !moved.rejected.is_empty() {
+            self.rejected_files.push((workshop_id.to_string(), moved.rejected.clone()));
+        }
+        if !moved.empty.is_empty() {
+            self.empty_files.push((workshop_id.to_string(), std::mem::take(&mut moved.empty)));
+        }
+    }
+
+    /// Undoes a half-finished install: drops whatever was copied over the
+    /// trashed files and moves them back
+    async fn put_back(&self, trash: &str, trashed: &[String], previous: &WorkshopMetadata) {
+        let files: Vec<FileInfo> = previous
+            .files
+            .iter()
+            .filter(|f| trashed.contains(&f.path))
+            .cloned()
+            .collect();
+        for file_info in &files {
+            let path = self.paths.file_path(file_info);
+            if let Err(e) = fs::remove_file(&path).await
+                && e.kind() != std::io::ErrorKind::NotFound
+            {
+                warn!("failed to remove {}: {}", path.display(), e);
+            }
+        }
+        match self.restore_from_trash(trash, &files).await {
+            Ok(restored) => println!("Restored {} files of the previous version", restored),
+            Err(e) => warn!(
+                "failed to restore the previous version from trash/{}: {:#}",
+                trash, e
+            ),
+        }
+    }
+
+    /// Cross-checks the downloaded content against SteamCMD's
+    /// appworkshop_<appid>.acf to catch silent partial or stale downloads.
+    /// Returns the problem found, if any. A missing manifest only warns.
+    async fn check_workshop_manifest(
+        &self,
+        item: &WorkshopItem,
+        source_path: &Path,
+        workshop_dir: &Path,
+    ) -> Result<Option<String>> {
+        let manifest_path = steamcmd::manifest_path(workshop_dir, self.appid(&item.id));
+        let installed = match fs::read_to_string(&manifest_path).await {
+            Ok(content) => acf::parse(&content).and_then(|root| acf::installed_item(&root, &item.id)),
+            Err(_) => None,
+        };
+
+        let Some(installed) = installed else {
+            println!(
+                "Warning: {} has no entry for {}, skipping completeness check",
+                manifest_path.display(),
+                item.id
+            );
+            return Ok(None);
+        };
+
+        if let Some(expected) = installed.size {
+            let actual = self
+                .calculate_directory_size(source_path, &mut HashSet::new())
+                .await?;
+            if actual != expected {
+                return Ok(Some(format!(
+                    "incomplete download ({} of {} on disk)",
+                    format_file_size(actual),
+                    format_file_size(expected)
+                )));
+            }
+        }
+
+        // The Workshop's time_updated also moves on description edits, so only
+        // trust SteamCMD's own idea of the latest content version here
+        if let (Some(installed_time), Some(newest)) =
+            (installed.time_updated, installed.latest_time_updated)
+            && installed_time < newest
+        {
+            return Ok(Some(format!(
+                "SteamCMD installed an outdated version (from {}, latest is {})",
+                installed_time.format("%Y-%m-%d %H:%M"),
+                newest.format("%Y-%m-%d %H:%M")
+            )));
+        }
+
+        Ok(None)
+    }
+
+    /// Removes an item's download from a sandbox. `keep_content` leaves a
+    /// successful download cached for `download --reinstall` instead, and
+    /// drops older copies from the other sandboxes.
+    async fn clean_sandbox(&self, sandbox: usize, workshop_id: &str, keep_content: bool) {
+        if !keep_content {
+            self.clean_workshop_item(&self.paths.steamcmd_workshop_dir(sandbox), workshop_id)
+                .await;
+            return;
+        }
+        for other in (0..self.sandboxes.size()).filter(|&i| i != sandbox) {
+            self.clean_workshop_item(&self.paths.steamcmd_workshop_dir(other), workshop_id)
+                .await;
+        }
+    }
+
+    /// The sandbox to download an item in: the one download_ahead already
+    /// started it in, or the next free one
+    async fn sandbox_for(&mut self, workshop_id: &str) -> SandboxJob {
+        match self.ahead.remove(workshop_id) {
+            Some(job) => job,
+            None => SandboxJob {
+                sandbox: self.sandboxes.acquire().await,
+                ahead: None,
+            },
+        }
+    }
+
+    /// Starts downloading upcoming items whose changelog moved on in spare
+    /// sandboxes, so they are ready by the time the loop gets to them. One
+    /// sandbox is always left for the item being processed.
+    fn download_ahead(&mut self, upcoming: &[WorkshopItem], force: bool) {
+        for item in upcoming {
+            if self.ahead.len() + 1 >= self.sandboxes.size() {
+                break;
+            }
+            if self.ahead.contains_key(&item.id)
+                || !self.needs_download(item, force)
+                || self.metadata.get(&item.id).is_some_and(|m| m.archived.is_some())
+            {
+                continue;
+            }
+            let Some(sandbox) = self.sandboxes.try_acquire() else {
+                break;
+            };
+
+            let downloader = self.backends.downloader.clone();
+            let appid = self.appid(&item.id).to_string();
+            let workshop_id = item.id.clone();
+            let install_dir = self.paths.steamcmd_install_dir(sandbox.index());
+            let started_at = std::time::SystemTime::now();
+            let task = tokio::spawn(async move {
+                let started = std::time::Instant::now();
+                let outcome = downloader.download(&appid, &workshop_id, &install_dir).await;
+                (outcome, started.elapsed())
+            });
+            let ahead = AheadDownload { started_at, task };
+            self.ahead.insert(item.id.clone(), SandboxJob { sandbox, ahead: Some(ahead) });
+        }
+    }
+
+    /// Waits for an item's download started ahead if it wasn't used, e.g.
+    /// because the item was deferred, and cleans its sandbox
+    async fn discard_ahead(&mut self, workshop_id: &str) {
+        let Some(job) = self.ahead.remove(workshop_id) else {
+            return;
+        };
+        if let Some(ahead) = job.ahead {
+            let _ = ahead.task.await;
+        }
+        self.clean_sandbox(job.sandbox.index(), workshop_id, false).await;
+    }
+
+    /// discard_ahead for every download started ahead that is left
+    async fn finish_ahead(&mut self) {
+        let ids: Vec<String> = self.ahead.keys().cloned().collect();
+        for workshop_id in ids {
+            self.discard_ahead(&workshop_id).await;
+        }
+    }
+
+    /// Content of the item's last download kept by keep_download_cache
+    async fn cached_content(&self, workshop_id: &str) -> Result<Option<PathBuf>> {
+        for sandbox in 0..self.sandboxes.size() {
+            let path = steamcmd::content_path(
+                &self.paths.steamcmd_workshop_dir(sandbox),
+                self.appid(workshop_id),
+                workshop_id,
+            );
+            if fs::try_exists(&path).await? {
+                return Ok(Some(path));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Installs an item again from its cached download, without contacting
+    /// Steam
+    async fn reinstall_item(&mut self, workshop_id: &str) -> Result<()> {
+        let Some(metadata) = self.metadata.get(workshop_id) else {
+            println!("Item {} is not subscribed", workshop_id);
+            return Ok(());
+        };
+        if metadata.archived.is_some() {
+            println!("{} is archived, use 'unarchive {}' to restore it", workshop_id, workshop_id);
+            return Ok(());
+        }
+        let Some(source_path) = self.cached_content(workshop_id).await? else {
+            println!(
+                "No cached download of {}{}, use 'download {} -f' to fetch it again",
+                workshop_id,
+                if self.config.keep_download_cache { "" } else { " (keep_download_cache is off)" },
+                workshop_id
+            );
+            return Ok(());
+        };
+
+        let item = WorkshopItem {
+            id: workshop_id.to_string(),
+            title: metadata.title.clone(),
+            changelog_id: metadata.changelog_id.clone(),
+            changelog: String::new(),
+            file_size: metadata.file_size,
+            time_updated: metadata.time_updated,
+            required_items: None,
+        };
+        let collection_id = metadata.collection_ids.first().cloned();
+
+        println!("Reinstalling {} from {}...", workshop_id, source_path.display());
+        self.install_from(item, collection_id.as_deref(), &source_path)
+            .await?;
+        Ok(())
+    }
+
+    async fn clean_workshop_item(&self, workshop_dir: &Path, workshop_id: &str) {
+        // A distro package's Steam root is the user's, not ours to clean
+        if !self.paths.owns_download(workshop_dir) {
+            return;
+        }
+        let leftovers = [
+            steamcmd::content_path(workshop_dir, self.appid(workshop_id), workshop_id),
+            workshop_dir
+                .join("downloads")
+                .join(self.appid(workshop_id))
+                .join(workshop_id),
+        ];
+
+        for dir in leftovers {
+            if let Err(e) = fs::remove_dir_all(&dir).await
+                && e.kind() != std::io::ErrorKind::NotFound
+            {
+                warn!("failed to clean {}: {}", dir.display(), e);
+            }
+        }
+    }
+
+    /// The workshop directory holding the item's downloaded content, if
+    /// SteamCMD left it where it reported
+    async fn locate_download(&self, workshop_dir: &Path, workshop_id: &str) -> Result<Option<PathBuf>> {
+        let content = steamcmd::content_path(workshop_dir, self.appid(workshop_id), workshop_id);
+        Ok(fs::try_exists(content).await?.then(|| workshop_dir.to_path_buf()))
+    }
+
+    /// Subdirectory of output_dir configured for the item's collection, if any.
+    /// Items outside an explicit collection keep the directory of the first
+    /// collection they were downloaded with.
+    fn output_subdir(&self, workshop_id: &str, collection_id: Option<&str>) -> Option<PathBuf> {
+        let known = self
+            .metadata
+            .get(workshop_id)
+            .map(|m| m.collection_ids.as_slice())
+            .unwrap_or_default();
+
+        let collection_dir = collection_id
+            .into_iter()
+            .chain(known.iter().map(String::as_str))
+            .find_map(|cid| self.config.collection_dirs.get(cid))
+            .map(PathBuf::from);
+
+        // Collection directories nest inside the appid's directory
+        match (self.config.appid_dirs.get(self.appid(workshop_id)), collection_dir) {
+            (Some(appid_dir), Some(dir)) => Some(Path::new(appid_dir).join(dir)),
+            (Some(appid_dir), None) => Some(PathBuf::from(appid_dir)),
+            (None, dir) => dir,
+        }
+    }
+
+    fn describe_update(
+        item: &WorkshopItem,
+        previous: Option<&WorkshopMetadata>,
+        files: &[FileInfo],
+    ) -> report::ItemUpdate {
+        let old_files: HashMap<&str, &FileInfo> = previous
+            .map(|m| m.files.iter().map(|f| (f.path.as_str(), f)).collect())
+            .unwrap_or_default();
+
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        for file in files {
+            match old_files.get(file.path.as_str()) {
+                None => added.push(file.path.clone()),
+                Some(old) if old.hash != file.hash => changed.push(file.path.clone()),
+                Some(_) => {}
+            }
+        }
+
+        let removed = old_files
+            .keys()
+            .filter(|path| !files.iter().any(|f| f.path == **path))
+            .map(|path| path.to_string())
+            .collect();
+
+        report::ItemUpdate {
+            id: item.id.clone(),
+            title: item.title.clone(),
+            old_changelog_id: previous
+                .map(|m| m.changelog_id.clone())
+                .filter(|id| id != "0"),
+            new_changelog_id: item.changelog_id.clone(),
+            changelog: item.changelog.clone(),
+            added,
+            removed,
+            changed,
+            old_size: old_files.values().map(|f| f.size).sum(),
+            new_size: files.iter().map(|f| f.size).sum(),
+        }
+    }
+
+    fn fail_item(&mut self, workshop_id: &str, reason: &str) {
+        error!("failed to download {}: {}", workshop_id, reason);
+        self.failed_items.push(FailedItem {
+            id: workshop_id.to_string(),
+            reason: reason.to_string(),
+            kind: None,
+            attempts: None,
+        });
+    }
+
+    fn fail_steamcmd(&mut self, workshop_id: &str, failure: &steamcmd::Failure) {
+        self.fail_item(workshop_id, &format!("SteamCMD: {}", failure));
+        if let Some(item) = self.failed_items.last_mut() {
+            item.kind = Some(failure.kind());
+        }
+    }
+
+    fn fail_empty(&mut self, workshop_id: &str, cause: &EmptyDownload) {
+        self.fail_item(workshop_id, &cause.to_string());
+        if let Some(item) = self.failed_items.last_mut() {
+            item.kind = Some(cause.kind());
+        }
+    }
+
+    /// max_file_size for an item, its own override first
+    fn max_file_size(&self, workshop_id: &str) -> Option<u64> {
+        self.config
+            .max_file_size_items
+            .get(workshop_id)
+            .or(self.config.max_file_size.as_ref())
+            .and_then(|size| parse_file_size(size))
+    }
+
+    fn print_failures(&self) {
+        if !self.skipped_files.is_empty() {
+            println!("\nSkipped files over max_file_size ({}):", self.skipped_files.len());
+            for file in &self.skipped_files {
+                println!("  {:<12} {} ({})", file.id, file.path, format_file_size(file.size));
+            }
+        }
+
+        if !self.rejected_files.is_empty() {
+            let total: usize = self.rejected_files.iter().map(|(_, files)| files.len()).sum();
+            println!("\nFiles rejected by the whitelist ({}):", total);
+            for (id, files) in &self.rejected_files {
+                let shown: Vec<&str> = files.iter().take(3).map(String::as_str).collect();
+                let more = match files.len().saturating_sub(shown.len()) {
+                    0 => String::new(),
+                    n => format!(", +{} more", n),
+                };
+                println!("  {:<12} {} files: {}{}", id, files.len(), shown.join(", "), more);
+            }
+            println!("  Check the patterns with 'whitelist test <path...>'");
+        }
+
+        if !self.empty_files.is_empty() {
+            let total: usize = self.empty_files.iter().map(|(_, files)| files.len()).sum();
+            let action = match self.config.zero_byte_files {
+                ZeroBytePolicy::Skip => "left out",
+                _ => "installed",
+            };
+            println!("\nEmpty files {} ({}):", action, total);
+            for (id, files) in &self.empty_files {
+                for path in files {
+                    println!("  {:<12} {}", id, path);
+                }
+            }
+        }
+
+        if self.failed_items.is_empty() {
+            return;
+        }
+
+        let (persistent, failed): (Vec<&FailedItem>, Vec<&FailedItem>) =
+            self.failed_items.iter().partition(|f| f.attempts.is_some());
+        let title = |id: &str| self.metadata.get(id).map(|m| m.title.as_str()).unwrap_or("");
+
+        if !failed.is_empty() {
+            println!("\nFailed items ({}):", failed.len());
+            for item in failed {
+                println!("  {:<12} {} - {}", item.id, title(&item.id), item.reason);
+            }
+        }
+        if !persistent.is_empty() {
+            println!("\nStill failing after retries ({}):", persistent.len());
+            for item in persistent {
+                println!(
+                    "  {:<12} {} - {} ({} attempts)",
+                    item.id,
+                    title(&item.id),
+                    item.reason,
+                    item.attempts.unwrap_or_default()
+                );
+            }
+        }
+    }
+
+    /// Ends a command: saves what the lookups cached and resets the per-run
+    /// results, writing them to the status file first if `write_status`
+    async fn record_run(&mut self, command: &str, error: Option<&anyhow::Error>, write_status: bool) {
+        // Downloads started ahead of an item the command failed before
+        self.finish_ahead().await;
+        self.backends.info.flush().await;
+        if let Some(path) = self.paths.status_file.as_ref().filter(|_| write_status) {
+            let status = RunStatus {
+                command: command.trim(),
+                finished_at: Utc::now(),
+                result: match (error, self.failed_items.is_empty()) {
+                    (Some(_), _) => "error",
+                    (None, false) => "partial",
+                    (None, true) => "success",
+                },
+                error: error.map(|e| format!("{:#}", e)),
+                error_kind: error.map(|e| error::kind_of(e).as_str()),
+                updated_items: self.updates.iter().map(|u| u.id.as_str()).collect(),
+                failed_items: &self.failed_items,
+                stats: (!self.stats.is_empty()).then(|| self.run_totals()),
+                items: &self.stats.results,
+            };
+
+            let written = match serde_json::to_string_pretty(&status) {
+                Ok(data) => fs::write(path, data).await.map_err(anyhow::Error::from),
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = written {
+                warn!("failed to write status file {}: {:#}", path.display(), e);
+            }
+        }
+
+        self.updates.clear();
+        self.failed_items.clear();
+        self.skipped_files.clear();
+        self.rejected_files.clear();
+        self.empty_files.clear();
+        self.processed.clear();
+        self.stats = report::RunStats::default();
+    }
+
+    fn run_totals(&self) -> report::RunTotals {
+        let failed: BTreeSet<&str> = self.failed_items.iter().map(|f| f.id.as_str()).collect();
+        self.stats.totals(self.updates.len(), failed.len())
+    }
+
+    /// Prints the run's totals if any item was processed
+    fn print_run_stats(&self) {
+        if !self.stats.is_empty() {
+            report::print_totals(&self.run_totals());
+        }
+    }
+
+    async fn download_collection(
+        &mut self,
+        collection: WorkshopCollection,
+        force: bool,
+    ) -> Result<()> {
+        println!(
+            "Downloading collection: {} ({} items)",
+            collection.title,
+            collection.item_ids.len()
+        );
+
+        let items = self.resolve_collection(&collection).await?;
+        self.download_collection_items(&collection, items, force).await
+    }
+
+    /// Looks up the collection's members, leaving out unavailable ones
+    async fn resolve_collection(&mut self, collection: &WorkshopCollection) -> Result<Vec<WorkshopItem>> {
+        self.backends.info.prefetch(&collection.item_ids).await;
+        let mut items = Vec::new();
+        for file_id in &collection.item_ids {
+            let file = self
+                .resolve_item(file_id)
+                .await
+                .context("Failed to fetch file info in collection")?;
+            items.extend(file);
+        }
+        Ok(items)
+    }
+
+    async fn download_collection_items(
+        &mut self,
+        collection: &WorkshopCollection,
+        items: Vec<WorkshopItem>,
+        force: bool,
+    ) -> Result<()> {
+        self.download_collection_run(collection, items, force, None).await
+    }
+
+    /// Downloads a collection's items, continuing `resumed` if given so the
+    /// items it already completed stay recorded
+    async fn download_collection_run(
+        &mut self,
+        collection: &WorkshopCollection,
+        items: Vec<WorkshopItem>,
+        force: bool,
+        resumed: Option<resume::RunState>,
+    ) -> Result<()> {
+        // An appid given for the collection applies to everything in it
+        if let Some(appid) = self.appid_overrides.get(&collection.id).cloned() {
+            for item in &items {
+                self.appid_overrides.insert(item.id.clone(), appid.clone());
+            }
+        }
+        // Members of a collection handled earlier in this run are only attached
+        let (shared, items): (Vec<WorkshopItem>, Vec<WorkshopItem>) =
+            items.into_iter().partition(|item| self.processed.contains(&item.id));
+        if !shared.is_empty() {
+            for item in &shared {
+                if let Some(metadata) = self.metadata.get_mut(&item.id)
+                    && !metadata.collection_ids.contains(&collection.id)
+                {
+                    metadata.collection_ids.push(collection.id.clone());
+                }
+            }
+            println!(
+                "{} items of {} were already processed in this run",
+                shared.len(),
+                collection.title
+            );
+            self.save_metadata().await?;
+            self.update_outputs().await?;
+        }
+
+        let mut retry = Vec::new();
+        let mut run = resumed.unwrap_or_else(|| {
+            resume::RunState::new(
+                Some(&collection.id),
+                force,
+                items.iter().map(|item| item.id.clone()).collect(),
+            )
+        });
+        run.appid = self.appid_overrides.get(&collection.id).cloned();
+        self.save_run_state(&run).await;
+
+        for (i, file_item) in items.iter().cloned().enumerate() {
+            if platform::stop_requested() {
+                self.finish_ahead().await;
+                println!("Stopping, 'update --resume' continues the {} items left", run.pending.len());
+                return Ok(());
+            }
+            self.download_ahead(&items[i + 1..], force);
+            let id = file_item.id.clone();
+            if let Some(item) = self
+                .download_retryable(file_item, Some(&collection.id), force)
+                .await?
+            {
+                retry.push(item);
+            }
+            self.discard_ahead(&id).await;
+            run.complete(&id);
+            self.save_run_state(&run).await;
+        }
+
+        for attempt in 1..=self.config.item_retries {
+            if retry.is_empty() {
+                break;
+            }
+            let delay = self.config.item_retry_delay.saturating_mul(1 << (attempt - 1).min(16));
+            println!(
+                "\nRetrying {} failed items in {}s (attempt {} of {})",
+                retry.len(),
+                delay,
+                attempt + 1,
+                self.config.item_retries + 1
+            );
+            tokio::time::sleep(Duration::from_secs(delay)).await;
+
+            for file_item in std::mem::take(&mut retry) {
+                self.failed_items.retain(|f| f.id != file_item.id);
+                if let Some(item) = self
+                    .download_retryable(file_item, Some(&collection.id), force)
+                    .await?
+                {
+                    if attempt == self.config.item_retries
+                        && let Some(failed) = self.failed_items.iter_mut().find(|f| f.id == item.id)
+                    {
+                        failed.attempts = Some(attempt + 1);
+                    }
+                    retry.push(item);
+                }
+            }
+        }
+
+        self.collections.insert(
+            collection.id.clone(),
+            collection::CollectionRecord {
+                title: collection.title.clone(),
+                item_ids: collection.item_ids.clone(),
+                synced_at: Utc::now(),
+            },
+        );
+        self.save_collections().await?;
+        self.clear_run_state().await;
+        Ok(())
+    }
+
+    async fn save_run_state(&self, run: &resume::RunState) {
+        if let Err(e) = resume::save(&self.paths.run_state_file, run).await {
+            warn!("failed to save run progress: {:#}", e);
+        }
+    }
+
+    async fn clear_run_state(&self) {
+        if let Err(e) = resume::clear(&self.paths.run_state_file).await {
+            warn!("{:#}", e);
+        }
+    }
+
+    /// Lists a collection's items and lets the user pick which to download.
+    /// Without a terminal, or with `--yes`, everything is kept.
+    fn preview_collection(
+        &self,
+        collection: &WorkshopCollection,
+        items: Vec<WorkshopItem>,
+    ) -> Result<Vec<WorkshopItem>> {
+        if self.assume_yes || !std::io::stdin().is_terminal() || items.is_empty() {
+            return Ok(items);
+        }
+
+        println!("Collection: {} ({} items)", collection.title, items.len());
+        for (i, item) in items.iter().enumerate() {
+            let marker = match self.metadata.get(&item.id) {
+                Some(m) if m.changelog_id == item.changelog_id => "[installed]",
+                Some(_) => "[update]",
+                None => "",
+            };
+            let size = item.file_size.map(format_file_size).unwrap_or_default();
+            println!(
+                "{:>4}  {:<11} {:<12} {} {}",
+                i + 1,
+                marker,
+                item.id,
+                item.title,
+                size
+            );
+        }
+        let total: u64 = items
+            .iter()
+            .filter(|item| !self.metadata.contains_key(&item.id))
+            .filter_map(|item| item.file_size)
+            .sum();
+        if total > 0 {
+            println!("New content: {}", format_file_size(total));
+        }
+
+        let picked = self.pick(&format!("Download {} items?", items.len()), items.len(), true)?;
+        Ok(items
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| picked.contains(&(i + 1)))
+            .map(|(_, item)| item)
+            .collect())
+    }
+
+    /// Asks which of `count` numbered entries to go ahead with: all, none or
+    /// a selection. With `--yes` all of them, without a terminal all or none
+    /// depending on `default`, like confirm. End of input picks none.
+    fn pick(&self, question: &str, count: usize, default: bool) -> Result<BTreeSet<usize>> {
+        let all = || (1..=count).collect();
+        if self.assume_yes {
+            return Ok(all());
+        }
+        if !std::io::stdin().is_terminal() {
+            return Ok(if default { all() } else { BTreeSet::new() });
+        }
+
+        loop {
+            print!(
+                "{} [{}, or a selection like 1,3-5] ",
+                question,
+                if default { "Y/n" } else { "y/N" }
+            );
+            std::io::stdout().flush()?;
+
+            let mut answer = String::new();
+            if std::io::stdin().read_line(&mut answer)? == 0 {
+                println!();
+                return Ok(BTreeSet::new());
+            }
+
+            match answer.trim().to_lowercase().as_str() {
+                "" if default => return Ok(all()),
+                "" => return Ok(BTreeSet::new()),
+                "y" | "yes" => return Ok(all()),
+                "n" | "no" => return Ok(BTreeSet::new()),
+                selection => match parse_selection(selection, count) {
+                    Some(picked) => return Ok(picked),
+                    None => println!("Invalid selection '{}'", selection),
+                },
+            }
+        }
+    }
+
+    async fn cmd_import(&mut self, args: &[&str]) -> Result<()> {
+        const USAGE: &str =
+            "usage: import [--download] <workshop_maps.txt|subs.json> | import --steam-user <id64> [--subscriptions|--favorites]";
+        let mut download = false;
+        let mut steam_user = None;
+        let mut lists = Vec::new();
+        let mut path = None;
+
+        let mut iter = args.iter().copied();
+        while let Some(arg) = iter.next() {
+            match arg {
+                "--download" => download = true,
+                "--steam-user" => match iter.next() {
+                    Some(id) if id.len() == 17 && id.parse::<u64>().is_ok() => steam_user = Some(id),
+                    _ => {
+                        println!("--steam-user expects a 64-bit SteamID (7656119...)");
+                        return Ok(());
+                    }
+                },
+                "--subscriptions" => lists.push(steam_api::UserList::Subscriptions),
+                "--favorites" => lists.push(steam_api::UserList::Favorites),
+                value if !value.starts_with('-') && path.is_none() => path = Some(value),
+                _ => {
+                    println!("{}", USAGE);
+                    return Ok(());
+                }
+            }
+        }
+
+        let entries = match (steam_user, path) {
+            (Some(steamid), None) => {
+                if lists.is_empty() {
+                    lists = vec![steam_api::UserList::Subscriptions, steam_api::UserList::Favorites];
+                }
+                let entries = self.steam_user_entries(steamid, &lists).await?;
+                println!("Found {} items for Steam user {}", entries.len(), steamid);
+
+                download = download
+                    || self.confirm(&format!("Download all {} items now?", entries.len()), false)?;
+                entries
+            }
+            (None, Some(path)) => self.read_import_file(path).await?,
+            _ => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+        };
+
+        let mut imported = Vec::new();
+
+        for entry in entries {
+            if entry.id.parse::<u64>().is_err() || self.metadata.contains_key(&entry.id) {
+                continue;
+            }
+
+            self.metadata.insert(
+                entry.id.clone(),
+                WorkshopMetadata {
+                    title: entry.title,
+                    changelog_id: "0".to_string(),
+                    collection_ids: entry.collection_ids,
+                    ..Default::default()
+                },
+            );
+            imported.push(entry.id);
+        }
+
+        self.save_metadata().await?;
+
+        if !download {
+            println!(
+                "Imported {} workshop IDs. Use 'update' to download them",
+                imported.len()
+            );
+            return Ok(());
+        }
+
+        println!("Imported {} workshop IDs, downloading...", imported.len());
+        for workshop_id in &imported {
+            if let Some(item) = self.resolve_item(workshop_id).await? {
+                self.download_item(item, None, false).await?;
+            }
+        }
+
+        self.run_post_update_actions().await;
+        Ok(())
+    }
+
+    async fn steam_user_entries(
+        &self,
+        steamid: &str,
+        lists: &[steam_api::UserList],
+    ) -> Result<Vec<SubscriptionEntry>> {
+        let mut entries: Vec<SubscriptionEntry> = Vec::new();
+
+        for list in lists {
+            for file in self.api.user_files(steamid, &self.config.appid, *list).await? {
+                if !entries.iter().any(|e| e.id == file.publishedfileid) {
+                    entries.push(SubscriptionEntry {
+                        id: file.publishedfileid,
+                        title: file.title,
+                        collection_ids: Vec::new(),
+                    });
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
+    async fn read_import_file(&self, path: &str) -> Result<Vec<SubscriptionEntry>> {
+        let import_path = PathBuf::from(path);
+        if !import_path.exists() {
+            return Err(NecoError::NotFound(format!("File not found: {}", path)).into());
+        }
+
+        let content = fs::read_to_string(&import_path)
+            .await
+            .with_context(|| NecoError::Fs(format!("Failed to read {}", path)))?;
+
+        Ok(if content.trim_start().starts_with('{') {
+            let set: SubscriptionSet = serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse subscription set {}", path))?;
+            if set.appid != self.config.appid {
+                println!(
+                    "Warning: {} was exported for appid {}, current appid is {}",
+                    path, set.appid, self.config.appid
+                );
+            }
+            set.items
+        } else {
+            parse_workshop_maps(&content, &self.config.map_list.root_key)
+        })
+    }
+
+    async fn cmd_export(&self, args: &[&str]) -> Result<()> {
+        let mut format = "json";
+        let mut path = None;
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match *arg {
+                "--format" => match iter.next().copied() {
+                    Some(value @ ("json" | "steam-collection")) => format = value,
+                    _ => {
+                        println!("--format must be one of: json, steam-collection");
+                        return Ok(());
+                    }
+                },
+                value if !value.starts_with('-') => path = Some(value),
+                _ => {
+                    println!("Unknown option: {}", arg);
+                    return Ok(());
+                }
+            }
+        }
+
+        let mut items: Vec<SubscriptionEntry> = self
+            .metadata
+            .iter()
+            .map(|(id, metadata)| SubscriptionEntry {
+                id: id.clone(),
+                title: metadata.title.clone(),
+                collection_ids: metadata.collection_ids.clone(),
+            })
+            .collect();
+        items.sort_by(|a, b| a.id.cmp(&b.id));
+        let count = items.len();
+
+        let content = match format {
+            // One Workshop URL per line, ready to paste into a collection
+            // or a community post
+            "steam-collection" => items
+                .iter()
+                .map(|item| format!("{}\n", workshop_url(&item.id)))
+                .collect(),
+            _ => serde_json::to_string_pretty(&SubscriptionSet {
+                appid: self.config.appid.clone(),
+                items,
+            })?,
+        };
+
+        let Some(path) = path else {
+            if format == "json" {
+                println!("usage: export [--format json|steam-collection] <path>");
+            } else {
+                print!("{}", content);
+            }
+            return Ok(());
+        };
+
+        fs::write(path, content)
+            .await
+            .with_context(|| NecoError::Fs(format!("Failed to write {}", path)))?;
+
+        println!("Exported {} workshop IDs to {}", count, path);
+        Ok(())
+    }
+
+    async fn cmd_update(&mut self, args: &[&str]) -> Result<()> {
+        const USAGE: &str = "usage: update [-f|--force] [--check|--apply] [--trust-changelog|--verify] [--report <file.md|file.html>] [--by-size] [--max-size <size>] [--only <n>] [--resume]";
+        let mut force = false;
+        let mut resume = false;
+        let mut report_path = None;
+        let mut trust_changelog = self.config.trust_changelog;
+        let mut by_size = false;
+        let mut max_size = None;
+        let mut only = None;
+        let mut check = !self.config.auto_update;
+        let mut apply = false;
+
+        let mut iter = args.iter().copied();
+        while let Some(arg) = iter.next() {
+            match arg {
+                "-f" | "--force" => force = true,
+                "--trust-changelog" => trust_changelog = true,
+                "--verify" => trust_changelog = false,
+                "--report" => match iter.next() {
+                    Some(path) => report_path = Some(path),
+                    None => {
+                        println!("{}", USAGE);
+                        return Ok(());
+                    }
+                },
+                "--check" => check = true,
+                "--apply" => {
+                    check = false;
+                    apply = true;
+                }
+                "--resume" => resume = true,
+                "--by-size" => by_size = true,
+                "--max-size" => match iter.next().and_then(parse_file_size) {
+                    Some(size) => max_size = Some(size),
+                    None => {
+                        println!("{}", USAGE);
+                        return Ok(());
+                    }
+                },
+                "--only" => match iter.next().and_then(|n| n.parse::<usize>().ok()) {
+                    Some(n) => only = Some(n),
+                    None => {
+                        println!("{}", USAGE);
+                        return Ok(());
+                    }
+                },
+                _ => {
+                    println!("Unknown option: {}", arg);
+                    return Ok(());
+                }
+            }
+        }
+
+        let options = UpdateOptions {
+            force,
+            trust_changelog,
+            check,
+            apply,
+            by_size,
+            max_size,
+            only,
+            resume,
+        };
+        let Some(report) = self.update_all(&options).await? else {
+            return Ok(());
+        };
+
+        if !report.check {
+            report::print_summary(&self.updates);
+            self.print_failures();
+            self.print_run_stats();
+        }
+        let unavailable = report
+            .items
+            .iter()
+            .filter(|item| item.status == report::ItemStatus::Unavailable)
+            .count();
+        if unavailable > 0 {
+            println!(
+                "{} items are unavailable on the Workshop, see 'prune --unavailable'",
+                unavailable
+            );
+        }
+        if report.check {
+            return Ok(());
+        }
+        if let Some(path) = report_path {
+            self.write_report(path).await?;
+        }
+
+        self.run_post_update_actions().await;
+        Ok(())
+    }
+
+    /// Checks every subscribed item for updates and downloads them, or with
+    /// `check` only reports them. Returns what became of each item, or None
+    /// if there was nothing to update.
+    async fn update_all(&mut self, options: &UpdateOptions) -> Result<Option<report::UpdateReport>> {
+        let started_at = Utc::now();
+        let first = self.stats.results.len();
+        let UpdateOptions {
+            force,
+            trust_changelog,
+            mut check,
+            apply,
+            ..
+        } = *options;
+
+        let interrupted = resume::load(&self.paths.run_state_file).await?;
+        if options.resume {
+            let Some(run) = interrupted else {
+                println!("No interrupted run to resume");
+                return Ok(None);
+            };
+            self.resume_run(run, trust_changelog).await?;
+            return Ok(Some(report::UpdateReport {
+                started_at,
+                check: false,
+                items: self.stats.results[first..].to_vec(),
+            }));
+        }
+        if let Some(run) = &interrupted
+            && !check
+        {
+            println!(
+                "Note: a run from {} was interrupted with {} items left, starting over. Use 'update --resume' to continue it instead",
+                run.started.format("%Y-%m-%d %H:%M UTC"),
+                run.pending.len()
+            );
+        }
+
+        if !check && !apply && !self.in_update_window() {
+            println!(
+                "Outside the update window ({}), only checking for updates",
+                self.config.update_windows.join(", ")
+            );
+            check = true;
+        }
+
+        let workshop_ids: Vec<String> = self
+            .metadata
+            .iter()
+            .filter(|(_, m)| m.archived.is_none())
+            .map(|(id, _)| id.clone())
+            .collect();
+        let mut follows = follow::load(&self.paths.follows_file).await?;
+        if workshop_ids.is_empty() && follows.is_empty() {
+            println!("No subscribed items. Use 'download <id>' to add items.");
+            return Ok(None);
+        }
+
+        println!(
+            "Updating {} items{}...",
+            workshop_ids.len(),
+            if force { " (forced)" } else { "" }
+        );
+        self.stats.start();
+
+        self.backends.info.prefetch(&workshop_ids).await;
+        let mut items = Vec::new();
+        for workshop_id in &workshop_ids {
+            match self.resolve_item(workshop_id).await? {
+                Some(item) => items.push(item),
+                None => {
+                    let reason = self.metadata[workshop_id]
+                        .unavailable
+                        .as_ref()
+                        .map(|u| u.reason.clone())
+                        .unwrap_or_else(|| "no longer a Workshop item".to_string());
+                    self.record_unavailable(workshop_id, &reason);
+                }
+            }
+        }
+
+        self.refresh_titles(&items).await;
+
+        // Discovery marks items as seen, leave it to runs that download them
+        let mut auto_approved = Vec::new();
+        if !follows.is_empty() && !check {
+            match self.discover_followed(&mut follows).await {
+                Ok(new_ids) => {
+                    for (index, workshop_id) in new_ids {
+                        match self.backends.info.fetch(&workshop_id).await {
+                            Ok(ParseResult::Item(item)) => {
+                                println!("Found new item {} ({}) from a followed author", item.title, item.id);
+                                items.push(item);
+                                auto_approved.push((index, workshop_id));
+                            }
+                            Ok(_) => {}
+                            Err(e) => warn!("failed to fetch {}: {:#}", workshop_id, e),
+                        }
+                    }
+                }
+                Err(e) => warn!("failed to check followed authors: {:#}", e),
+            }
+            if !follows.pending.is_empty() {
+                println!(
+                    "{} discovered items are waiting for approval, see 'review'",
+                    follows.pending.len()
+                );
+            }
+        }
+
+        if check {
+            self.notify_updates(&items).await?;
+            for item in &items {
+                let status = match self.metadata.get(&item.id) {
+                    Some(m) if m.changelog_id != item.changelog_id => report::ItemStatus::UpdateAvailable,
+                    _ => report::ItemStatus::UpToDate,
+                };
+                let result = self.item_result(&item.id, &item.title, status, None);
+                self.stats.results.push(result);
+            }
+            return Ok(Some(report::UpdateReport {
+                started_at,
+                check: true,
+                items: self.stats.results[first..].to_vec(),
+            }));
+        }
+
+        if options.by_size || options.max_size.is_some() || options.only.is_some() {
+            items = self.limit_by_size(items, force, options.max_size, options.only);
+        }
+
+        let to_download: Vec<&WorkshopItem> =
+            items.iter().filter(|item| self.needs_download(item, force)).collect();
+        if !to_download.is_empty() {
+            let known: u64 = to_download.iter().filter_map(|item| item.file_size).sum();
+            let unknown = to_download.iter().filter(|item| item.file_size.is_none()).count();
+            println!(
+                "{} items to download, about {}{}",
+                to_download.len(),
+                format_file_size(known),
+                if unknown > 0 {
+                    format!(" plus {} of unknown size", unknown)
+                } else {
+                    String::new()
+                }
+            );
+        }
+
+        let run = resume::RunState::new(None, force, items.iter().map(|item| item.id.clone()).collect());
+        self.update_items(items, run, trust_changelog).await?;
+        self.mark_followed_seen(&mut follows, &auto_approved).await;
+        Ok(Some(report::UpdateReport {
+            started_at,
+            check: false,
+            items: self.stats.results[first..].to_vec(),
+        }))
+    }
+
+    /// Downloads the items of an update, saving the progress after each so
+    /// an interrupted run can be resumed
+    async fn update_items(
+        &mut self,
+        items: Vec<WorkshopItem>,
+        mut run: resume::RunState,
+        trust_changelog: bool,
+    ) -> Result<()> {
+        let force = run.force;
+        self.save_run_state(&run).await;
+        for (i, item) in items.iter().cloned().enumerate() {
+            if platform::stop_requested() {
+                self.finish_ahead().await;
+                println!("Stopping, 'update --resume' continues the {} items left", run.pending.len());
+                return Ok(());
+            }
+            self.download_ahead(&items[i + 1..], force);
+            let id = item.id.clone();
+            if trust_changelog
+                && !force
+                && self
+                    .metadata
+                    .get(&item.id)
+                    .is_some_and(|m| m.changelog_id == item.changelog_id)
+            {
+                println!("{} is up-to-date (changelog unchanged, files not verified)", item.id);
+                let result = self.item_result(&item.id, &item.title, report::ItemStatus::UpToDate, None);
+                self.stats.results.push(result);
+                self.stats.item_times.push((item.id, Duration::ZERO));
+            } else {
+                self.download_item(item, None, force).await?;
+            }
+            self.discard_ahead(&id).await;
+            run.complete(&id);
+            self.save_run_state(&run).await;
+        }
+        self.clear_run_state().await;
+        Ok(())
+    }
+
+    /// Continues an interrupted update or collection download with the items
+    /// it hadn't gotten to
+    async fn resume_run(
+        &mut self,
+        run: resume::RunState,
+        trust_changelog: bool,
+    ) -> Result<()> {
+        println!(
+            "Resuming the run from {}: {} items done, {} left",
+            run.started.format("%Y-%m-%d %H:%M UTC"),
+            run.completed.len(),
+            run.pending.len()
+        );
+        self.stats.start();
+
+        let mut items = Vec::new();
+        for workshop_id in &run.pending {
+            if let Some(item) = self.resolve_item(workshop_id).await? {
+                items.push(item);
+            }
+        }
+
+        let Some(cid) = &run.collection else {
+            return self.update_items(items, run, trust_changelog).await;
+        };
+        let collection = match self.backends.info.fetch(cid).await? {
+            ParseResult::Collection(collection) => collection,
+            _ => return Err(NecoError::NotFound(format!("Collection {} is no longer available", cid)).into()),
+        };
+        if let Some(appid) = &run.appid {
+            self.appid_overrides.insert(cid.clone(), appid.clone());
+        }
+        let force = run.force;
+        self.download_collection_run(&collection, items, force, Some(run)).await
+    }
+
+    /// Picks up renames even when the content is unchanged, so `list`
+    /// doesn't keep showing a stale name until the next download
+    async fn refresh_titles(&mut self, items: &[WorkshopItem]) {
+        let mut renamed = false;
+        for item in items {
+            if let Some(metadata) = self.metadata.get_mut(&item.id) {
+                let old = metadata.title.clone();
+                if metadata.rename(&item.title) {
+                    println!("{} was renamed: {} -> {}", item.id, old, item.title);
+                    renamed = true;
+                }
+            }
+        }
+        if renamed && let Err(e) = self.save_metadata().await {
+            warn!("failed to save renamed titles: {:#}", e);
+        }
+    }
+
+    async fn record_throughput(&mut self, workshop_id: &str, bytes: u64, time: Duration) {
+        // Nothing was transferred, the speed would say nothing about the network
+        if bytes == 0 {
+            return;
+        }
+        throughput::record(
+            &mut self.throughput,
+            throughput::Sample {
+                id: workshop_id.to_string(),
+                bytes,
+                secs: time.as_secs_f64(),
+                at: Utc::now(),
+            },
+        );
+        if let Err(e) = throughput::save(&self.paths.throughput_file, &self.throughput).await {
+            warn!("failed to save download speeds: {:#}", e);
+        }
+    }
+
+    async fn cmd_stats(&self) {
+        self.print_download_speeds();
+
+        let shared = self.shared_content().await;
+        if shared.linked == 0 && shared.copies == 0 {
+            return;
+        }
+        println!("\nShared content:");
+        println!(
+            "  {:<16}: {} files, {} saved",
+            "Stored once",
+            shared.linked,
+            format_file_size(shared.saved_bytes)
+        );
+        if shared.copies > 0 {
+            println!(
+                "  {:<16}: {} files, {} ({})",
+                "Separate copies",
+                shared.copies,
+                format_file_size(shared.copy_bytes),
+                self.copies_note()
+            );
+        }
+    }
+
+    /// Rolling download speeds from the throughput log, to spot when the
+    /// Steam CDN or the host's network slows down
+    fn print_download_speeds(&self) {
+        let samples = &self.throughput;
+        let Some(first) = samples.first() else {
+            println!("No downloads recorded yet.");
+            return;
+        };
+        let speed = |average: Option<f64>| match average {
+            Some(bytes) => format!("{}/s", format_file_size(bytes as u64)),
+            None => "-".to_string(),
+        };
+
+        let now = Utc::now();
+        let week = chrono::Duration::days(7);
+        let this_week = throughput::average(samples.iter().filter(|s| s.at > now - week));
+        let last_week = throughput::average(
+            samples
+                .iter()
+                .filter(|s| s.at <= now - week && s.at > now - week * 2),
+        );
+
+        println!(
+            "Download speed ({} downloads since {}):",
+            samples.len(),
+            first.at.format("%Y-%m-%d")
+        );
+        println!("  {:<16}: {}", "Last download", speed(samples.last().map(|s| s.bytes_per_sec())));
+        println!("  {:<16}: {}", "Last 10", speed(throughput::average(samples.iter().rev().take(10))));
+        println!("  {:<16}: {}", "Last 7 days", speed(this_week));
+        println!("  {:<16}: {}", "Previous 7 days", speed(last_week));
+        println!("  {:<16}: {}", "All recorded", speed(throughput::average(samples)));
+
+        if let (Some(current), Some(previous)) = (this_week, last_week)
+            && current < previous * 0.5
+        {
+            println!(
+                "Warning: downloads are {:.0}% slower than the week before",
+                (1.0 - current / previous) * 100.0
+            );
+        }
+
+        let mut slowest: Vec<&throughput::Sample> = samples.iter().rev().take(50).collect();
+        slowest.sort_by(|a, b| a.bytes_per_sec().total_cmp(&b.bytes_per_sec()));
+        println!("\nSlowest of the last 50 downloads:");
+        for sample in slowest.into_iter().take(5) {
+            println!(
+                "  {:<12} {:>10} in {:>6.1}s  {:>12}  {}",
+                sample.id,
+                format_file_size(sample.bytes),
+                sample.secs,
+                speed(Some(sample.bytes_per_sec())),
+                sample.at.format("%Y-%m-%d %H:%M")
+            );
+        }
+    }
+
+    /// Whether update_windows allow changing content right now
+    fn in_update_window(&self) -> bool {
+        let windows: Vec<schedule::TimeWindow> = self
+            .config
+            .update_windows
+            .iter()
+            .filter_map(|w| schedule::TimeWindow::parse(w))
+            .collect();
+        schedule::is_open(&windows, chrono::Local::now().time())
+    }
+
+    /// Reports subscribed items with a newer changelog instead of updating
+    /// them. They are marked in `list` until updated; the webhook only
+    /// hears about versions it wasn't told about before.
+    async fn notify_updates(&mut self, items: &[WorkshopItem]) -> Result<()> {
+        let mut fresh = Vec::new();
+        let mut pending = 0;
+
+        for item in items {
+            let Some(metadata) = self.metadata.get_mut(&item.id) else {
+                continue;
+            };
+            if metadata.changelog_id == item.changelog_id {
+                continue;
+            }
+            pending += 1;
+
+            println!("Update available: {} ({})", item.title, item.id);
+            for line in item.changelog.lines().filter(|l| !l.trim().is_empty()) {
+                println!("    {}", line.trim());
+            }
+
+            if metadata
+                .update_available
+                .as_ref()
+                .is_none_or(|u| u.changelog_id != item.changelog_id)
+            {
+                metadata.update_available = Some(AvailableUpdate {
+                    changelog_id: item.changelog_id.clone(),
+                    changelog: item.changelog.clone(),
+                    found_at: Utc::now(),
+                });
+                fresh.push(serde_json::json!({
+                    "id": item.id,
+                    "title": item.title,
+                    "changelog_id": item.changelog_id,
+                    "changelog": item.changelog,
+                }));
+            }
+        }
+
+        if pending == 0 {
+            println!("All items are up-to-date.");
+            return Ok(());
+        }
+        println!("{} updates available, run 'update --apply' to download them", pending);
+
+        self.save_metadata().await?;
+        if let Some(url) = &self.config.notify_webhook
+            && !fresh.is_empty()
+        {
+            let payload = serde_json::json!({ "updates": fresh });
+            let result = self
+                .retry
+                .send(self.http.post(url).json(&payload))
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = result {
+                warn!("failed to send update notification: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Orders items that need downloading smallest first and drops the ones
+    /// over `max_size` or past the first `only`, so quick fixes land before a
+    /// scheduled restart. Up-to-date items are kept for verification.
+    fn limit_by_size(
+        &self,
+        items: Vec<WorkshopItem>,
+        force: bool,
+        max_size: Option<u64>,
+        only: Option<usize>,
+    ) -> Vec<WorkshopItem> {
+        let (mut pending, mut current): (Vec<_>, Vec<_>) = items
+            .into_iter()
+            .partition(|item| self.needs_download(item, force));
+
+        let size_of = |item: &WorkshopItem| item.file_size.unwrap_or(u64::MAX);
+        pending.sort_by_key(size_of);
+
+        let mut deferred = Vec::new();
+        if let Some(max) = max_size {
+            let (fits, over): (Vec<_>, Vec<_>) =
+                pending.into_iter().partition(|item| size_of(item) <= max);
+            pending = fits;
+            deferred.extend(over);
+        }
+        if let Some(n) = only
+            && pending.len() > n
+        {
+            deferred.extend(pending.split_off(n));
+        }
+
+        for item in &deferred {
+            let size = match item.file_size {
+                Some(size) => format_file_size(size),
+                None => "unknown size".to_string(),
+            };
+            println!("Deferring {} ({}, {})", item.title, item.id, size);
+        }
+
+        current.extend(pending);
+        current
+    }
+
+    /// Whether the changelog moved on since the last download. Matching items
+    /// may still be redownloaded if quick_update finds modified files.
+    fn needs_download(&self, item: &WorkshopItem, force: bool) -> bool {
+        force
+            || self.platform_changed(&item.id)
+            || self
+                .metadata
+                .get(&item.id)
+                .is_none_or(|m| m.changelog_id != item.changelog_id)
+    }
+
+    /// Appid an item is downloaded under: a `download --appid` override,
+    /// the one recorded for it, or the configured appid
+    fn appid(&self, workshop_id: &str) -> &str {
+        self.appid_overrides
+            .get(workshop_id)
+            .map(String::as_str)
+            .or_else(|| self.metadata.get(workshop_id).and_then(|m| m.appid.as_deref()))
+            .unwrap_or(&self.config.appid)
+    }
+
+    /// Whether an installed item was downloaded for another platform than
+    /// the configured one, so its files may differ
+    fn platform_changed(&self, workshop_id: &str) -> bool {
+        let wanted = self.config.platform.map(|p| p.as_str());
+        self.metadata
+            .get(workshop_id)
+            .is_some_and(|m| !m.files.is_empty() && m.platform.as_deref() != wanted)
+    }
+
+    /// Checks that the SteamCMD and output directories can hold `size` more
+    /// bytes. Returns a failure reason if not.
+    fn check_free_space(&self, size: u64) -> Option<String> {
+        for dir in [&self.paths.steamcmd_root, &self.paths.local_files] {
+            if let Ok(available) = fs2::available_space(dir)
+                && available < size
+            {
+                return Some(format!(
+                    "not enough disk space in {} (needs {}, {} free)",
+                    dir.display(),
+                    format_file_size(size),
+                    format_file_size(available)
+                ));
+            }
+        }
+        None
+    }
+
+    /// Queues new results of followed searches and authors for approval and
+    /// returns auto-approved items with the index of their author. Those are
+    /// only marked as seen by mark_followed_seen once installed.
+    async fn discover_followed(&self, follows: &mut follow::Follows) -> Result<Vec<(usize, String)>> {
+        let mut new_ids = Vec::new();
+        let mut queued = Vec::new();
+
+        for query in &mut follows.queries {
+            let order = match query.sort {
+                follow::QuerySort::Recent => steam_api::QueryOrder::ByPublicationDate,
+                follow::QuerySort::Popular => steam_api::QueryOrder::ByVote,
+                follow::QuerySort::Trending => steam_api::QueryOrder::ByTrend,
+            };
+            let files = self
+                .api
+                .query_files(&self.config.appid, order, &query.tags, &query.text, query.cap)
+                .await
+                .with_context(|| format!("Failed to run search {}", query.describe()))?;
+
+            for file in files {
+                let id = file.publishedfileid;
+                if query.seen.insert(id.clone()) && !self.metadata.contains_key(&id) {
+                    queued.push(follow::PendingItem {
+                        id,
+                        title: file.title,
+                        source: format!("search {}", query.describe()),
+                        found_at: Utc::now(),
+                    });
+                }
+            }
+        }
+
+        for (index, author) in follows.authors.iter_mut().enumerate() {
+            let files = self
+                .api
+                .user_files(&author.steamid, &self.config.appid, steam_api::UserList::Published)
+                .await
+                .with_context(|| format!("Failed to list items of {}", author.profile))?;
+
+            for file in files {
+                let id = file.publishedfileid;
+                if author.seen.contains(&id) || self.metadata.contains_key(&id) {
+                    continue;
+                }
+                if !author.auto_approve {
+                    author.seen.insert(id.clone());
+                    queued.push(follow::PendingItem {
+                        id,
+                        title: file.title,
+                        source: format!("author {}", author.profile),
+                        found_at: Utc::now(),
+                    });
+                } else if !new_ids.iter().any(|(_, new)| *new == id) {
+                    new_ids.push((index, id));
+                }
+            }
+        }
+
+        for item in queued {
+            follows.queue(item);
+        }
+        follow::save(&self.paths.follows_file, follows).await?;
+        Ok(new_ids)
+    }
+
+    /// Marks auto-approved items as seen once they are installed, ones that
+    /// failed are found again by the next update
+    async fn mark_followed_seen(&self, follows: &mut follow::Follows, found: &[(usize, String)]) {
+        let mut changed = false;
+        for (index, id) in found {
+            if self.metadata.contains_key(id)
+                && let Some(author) = follows.authors.get_mut(*index)
+            {
+                changed |= author.seen.insert(id.clone());
+            }
+        }
+        if changed && let Err(e) = follow::save(&self.paths.follows_file, follows).await {
+            warn!("{:#}", e);
+        }
+    }
+
+    async fn cmd_follow(&mut self, args: &[&str]) -> Result<()> {
+        const USAGE: &str = "usage: follow author <profile> [--auto-approve] | follow tag <tag> | follow query [--tag <tag>] [--sort recent|popular|trending] [--cap <n>] [text] | follow remove <profile|n> | follow list";
+        let mut follows = follow::load(&self.paths.follows_file).await?;
+
+        match args {
+            [] | ["list"] => {
+                if follows.is_empty() {
+                    println!("Not following anything. Use 'follow author <profile>' or 'follow tag <tag>' to start.");
+                }
+                for author in &follows.authors {
+                    println!(
+                        "author {} ({}) since {}, {} items seen{}",
+                        author.profile,
+                        author.steamid,
+                        author.since.format("%Y-%m-%d"),
+                        author.seen.len(),
+                        if author.auto_approve { ", auto-approved" } else { "" }
+                    );
+                }
+                for (i, query) in follows.queries.iter().enumerate() {
+                    println!(
+                        "search #{} {} since {}, {} items seen",
+                        i + 1,
+                        query.describe(),
+                        query.since.format("%Y-%m-%d"),
+                        query.seen.len()
+                    );
+                }
+            }
+            ["tag", tag] => {
+                let query = follow::FollowedQuery::new(vec![tag.to_string()]);
+                self.add_followed_query(&mut follows, query).await?;
+            }
+            ["query", rest @ ..] => {
+                let mut query = follow::FollowedQuery::new(Vec::new());
+                let mut words = Vec::new();
+
+                let mut iter = rest.iter().copied();
+                while let Some(arg) = iter.next() {
+                    match arg {
+                        "--tag" => match iter.next() {
+                            Some(tag) => query.tags.push(tag.to_string()),
+                            None => {
+                                println!("{}", USAGE);
+                                return Ok(());
+                            }
+                        },
+                        "--sort" => match iter.next().and_then(follow::QuerySort::parse) {
+                            Some(sort) => query.sort = sort,
+                            None => {
+                                println!("{}", USAGE);
+                                return Ok(());
+                            }
+                        },
+                        "--cap" => match iter.next().and_then(|n| n.parse().ok()) {
+                            Some(cap) if cap > 0 => query.cap = cap,
+                            _ => {
+                                println!("{}", USAGE);
+                                return Ok(());
+                            }
+                        },
+                        word => words.push(word),
+                    }
+                }
+
+                query.text = words.join(" ");
+                if query.tags.is_empty() && query.text.is_empty() {
+                    println!("{}", USAGE);
+                    return Ok(());
+                }
+                self.add_followed_query(&mut follows, query).await?;
+            }
+            ["author", profile, options @ ..] => {
+                let auto_approve = match options {
+                    [] => false,
+                    ["--auto-approve"] => true,
+                    _ => {
+                        println!("{}", USAGE);
+                        return Ok(());
+                    }
+                };
+                let steamid = match follow::parse_profile(profile) {
+                    follow::Profile::SteamId(id) => id,
+                    follow::Profile::Vanity(name) => self.api.resolve_vanity(&name).await?,
+                };
+
+                if follows.authors.iter().any(|a| a.steamid == steamid) {
+                    println!("Already following {}", profile);
+                    return Ok(());
+                }
+
+                follows.authors.push(follow::FollowedAuthor {
+                    steamid: steamid.clone(),
+                    profile: profile.to_string(),
+                    since: Utc::now(),
+                    auto_approve,
+                    seen: Default::default(),
+                });
+                follow::save(&self.paths.follows_file, &follows).await?;
+                println!(
+                    "Following {} ({}), the next 'update' {} their items",
+                    profile,
+                    steamid,
+                    if auto_approve { "downloads" } else { "queues for approval" }
+                );
+            }
+            ["remove", profile] => {
+                if let Ok(n) = profile.parse::<usize>()
+                    && (1..=follows.queries.len()).contains(&n)
+                {
+                    let query = follows.queries.remove(n - 1);
+                    follow::save(&self.paths.follows_file, &follows).await?;
+                    println!("Stopped following search {}", query.describe());
+                    return Ok(());
+                }
+
+                let before = follows.authors.len();
+                follows
+                    .authors
+                    .retain(|a| a.steamid != *profile && a.profile != *profile);
+                if follows.authors.len() == before {
+                    println!("Not following {}", profile);
+                    return Ok(());
+                }
+                follow::save(&self.paths.follows_file, &follows).await?;
+                println!("Stopped following {}, downloaded items are kept", profile);
+            }
+            _ => println!("{}", USAGE),
+        }
+
+        Ok(())
+    }
+
+    async fn cmd_review(&self) -> Result<()> {
+        let follows = follow::load(&self.paths.follows_file).await?;
+        if follows.pending.is_empty() {
+            println!("No items waiting for approval.");
+            return Ok(());
+        }
+
+        let ids: Vec<String> = follows.pending.iter().map(|p| p.id.clone()).collect();
+        let details: HashMap<String, steam_api::PublishedFile> = match self.api.details(&ids).await {
+            Ok(files) => files
+                .into_iter()
+                .map(|f| (f.publishedfileid.clone(), f))
+                .collect(),
+            Err(e) => {
+                warn!("failed to fetch item details: {:#}", e);
+                HashMap::new()
+            }
+        };
+
+        for item in &follows.pending {
+            println!("{}", "-".repeat(40));
+            println!("{:<12}: {}", "Title", item.title);
+            println!("{:<12}: {}", "ID", item.id);
+            println!("{:<12}: {} ({})", "Found", item.found_at.format("%Y-%m-%d %H:%M"), item.source);
+            println!("{:<12}: {}", "URL", workshop_url(&item.id));
+
+            if let Some(file) = details.get(&item.id) {
+                println!("{:<12}: {}", "Size", format_file_size(file.file_size));
+                if let Some(updated) = file.time_updated {
+                    println!("{:<12}: {}", "Updated", updated.format("%Y-%m-%d"));
+                }
+                println!("{:<12}: {}", "Subscribers", file.subscriptions);
+                if !file.tags.is_empty() {
+                    let tags: Vec<&str> = file.tags.iter().map(|t| t.tag.as_str()).collect();
+                    println!("{:<12}: {}", "Tags", tags.join(", "));
+                }
+                let description = if file.short_description.is_empty() {
+                    &file.file_description
+                } else {
+                    &file.short_description
+                };
+                let description: String = description.split_whitespace().collect::<Vec<_>>().join(" ");
+                if !description.is_empty() {
+                    let mut short: String = description.chars().take(200).collect();
+                    if short.len() < description.len() {
+                        short.push_str("...");
+                    }
+                    println!("{:<12}: {}", "Description", short);
+                }
+            }
+        }
+        println!("{}", "-".repeat(40));
+        println!("Use 'approve <id|all>' to download or 'reject <id|all>' to discard.");
+        Ok(())
+    }
+
+    /// The queued items selected by the command's arguments
+    async fn select_pending(&self, args: &[&str], command: &str) -> Result<Vec<follow::PendingItem>> {
+        let follows = follow::load(&self.paths.follows_file).await?;
+        match args {
+            ["all"] => Ok(follows.pending),
+            [] => {
+                println!("usage: {} <id...|all>", command);
+                Ok(Vec::new())
+            }
+            ids => {
+                for id in ids {
+                    if !follows.pending.iter().any(|p| p.id == *id) {
+                        println!("{} is not waiting for approval", id);
+                    }
+                }
+                Ok(follows
+                    .pending
+                    .into_iter()
+                    .filter(|p| ids.contains(&p.id.as_str()))
+                    .collect())
+            }
+        }
+    }
+
+    /// Removes an item from the approval queue. It stays marked as seen, so
+    /// rejected items don't come back.
+    async fn drop_pending(&self, workshop_id: &str) -> Result<()> {
+        let mut follows = follow::load(&self.paths.follows_file).await?;
+        follows.pending.retain(|p| p.id != workshop_id);
+        follow::save(&self.paths.follows_file, &follows).await
+    }
+
+    /// Downloads approved items, each leaves the queue once it installed
+    /// successfully so failed ones can be approved again
+    async fn cmd_approve(&mut self, args: &[&str]) -> Result<()> {
+        let approved = self.select_pending(args, "approve").await?;
+        for item in &approved {
+            println!("Approved {} ({})", item.title, item.id);
+            let failed_before = self.failed_items.len();
+            let result = match self.backends.info.fetch(&item.id).await {
+                Ok(ParseResult::Item(workshop_item)) => {
+                    self.download_item(workshop_item, None, false).await.map(|_| true)
+                }
+                Ok(ParseResult::Collection(collection)) => {
+                    self.download_collection(collection, false).await.map(|_| true)
+                }
+                Ok(ParseResult::Unavailable(reason)) => {
+                    println!("{} is unavailable on the Workshop ({})", item.id, reason);
+                    Ok(false)
+                }
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(true) if self.failed_items.len() == failed_before => {
+                    self.drop_pending(&item.id).await?;
+                }
+                Ok(_) => println!("{} stays in the approval queue", item.id),
+                Err(e) => {
+                    error!("failed to download {}: {:#}", item.id, e);
+                    println!("{} stays in the approval queue", item.id);
+                }
+            }
+        }
+
+        if !approved.is_empty() {
+            self.run_post_update_actions().await;
+        }
+        Ok(())
+    }
+
+    async fn cmd_reject(&self, args: &[&str]) -> Result<()> {
+        for item in self.select_pending(args, "reject").await? {
+            self.drop_pending(&item.id).await?;
+            println!("Rejected {} ({})", item.title, item.id);
+        }
+        Ok(())
+    }
+
+    async fn add_followed_query(
+        &self,
+        follows: &mut follow::Follows,
+        query: follow::FollowedQuery,
+    ) -> Result<()> {
+        let description = query.describe();
+        if follows.queries.iter().any(|q| q.describe() == description) {
+            println!("Already following search {}", description);
+            return Ok(());
+        }
+
+        follows.queries.push(query);
+        follow::save(&self.paths.follows_file, follows).await?;
+        println!(
+            "Following search {}, new results are queued for approval by the next 'update'",
+            description
+        );
+        Ok(())
+    }
+
+    /// Which item owns a file or map, and which generated outputs list it
+    async fn cmd_where_used(&self, args: &[&str]) -> Result<()> {
+        let [query] = args else {
+            println!("usage: where-used <map_name|file>");
+            return Ok(());
+        };
+        let query_path = Path::new(query);
+        let map_name = match query.len().checked_sub(4) {
+            Some(stem) if query.is_char_boundary(stem) && query[stem..].eq_ignore_ascii_case(".bsp") => &query[..stem],
+            _ => query,
+        };
+
+        let mut owners: Vec<(&String, &WorkshopMetadata, &FileInfo)> = Vec::new();
+        for (id, metadata) in &self.metadata {
+            let file = metadata.files.iter().find(|f| {
+                let path = Path::new(&f.path);
+                path.ends_with(query_path)
+                    || (f.path.to_lowercase().ends_with(".bsp")
+                        && path
+                            .file_stem()
+                            .is_some_and(|stem| stem.eq_ignore_ascii_case(map_name)))
+            });
+            if let Some(file) = file {
+                owners.push((id, metadata, file));
+            }
+        }
+
+        if owners.is_empty() {
+            println!("No subscribed item owns '{}'", query);
+            return Ok(());
+        }
+
+        let maps = self.ordered_maps();
+        for (id, metadata, file) in owners {
+            println!("{}", self.paths.file_path(file).display());
+            println!(
+                "  Owner : {} ({}){}",
+                metadata.title,
+                id,
+                if metadata.archived.is_some() { " [archived]" } else { "" }
+            );
+
+            // Outputs list one map per item, other files point at that map
+            let is_map = Path::new(&file.path)
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("bsp"));
+            let map = if is_map {
+                Path::new(&file.path).file_stem().map(|stem| stem.to_string_lossy().to_string())
+            } else {
+                self.extract_map_name(metadata)
+            };
+            let Some(map) = map.filter(|_| !self.paths.outputs.is_empty()) else {
+                println!("  Not listed in any generated output");
+                continue;
+            };
+            for output in &self.paths.outputs {
+                let Some(position) = self.output_position(output, &maps, id, &map) else {
+                    println!("  Not listed: {}", output.path.display());
+                    continue;
+                };
+                let on_disk = fs::read_to_string(&output.path)
+                    .await
+                    .is_ok_and(|content| output_lists_map(&content, &map));
+                println!(
+                    "  Listed: {} (position {}{})",
+                    output.path.display(),
+                    position + 1,
+                    if on_disk { "" } else { ", not written yet" }
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Where an item's map appears in an output, after the output's appid
+    /// filter. `maps` is ordered_maps.
+    fn output_position(&self, output: &GeneratedOutput, maps: &[(String, &str)], id: &str, map: &str) -> Option<usize> {
+        maps.iter()
+            .filter(|(_, map_id)| output.config.appid.as_deref().is_none_or(|appid| self.appid(map_id) == appid))
+            .position(|(listed, map_id)| *map_id == id && listed == map)
+    }
+
+    async fn cmd_verify(&self, args: &[&str]) -> Result<()> {
+        let ids: Vec<&String> = match args.first() {
+            Some(id) => match self.metadata.get_key_value(*id) {
+                Some((id, _)) => vec![id],
+                None => {
+                    println!("Item {} is not subscribed", id);
+                    return Ok(());
+                }
+            },
+            None => self.metadata.keys().collect(),
+        };
+
+        let mut broken_items = 0;
+        let mut empty_files = 0;
+        for id in &ids {
+            let metadata = &self.metadata[*id];
+            if metadata.archived.is_some() {
+                continue;
+            }
+            let mut problems = Vec::new();
+            let mut empty = Vec::new();
+
+            for file_info in &metadata.files {
+                let full_path = self.paths.file_path(file_info);
+                if !fs::try_exists(&full_path).await? {
+                    problems.push(format!("missing  {}", file_info.path));
+                } else if !self.verify_file(file_info).await? {
+                    problems.push(format!("modified {}", file_info.path));
+                } else if file_info.size == 0 {
+                    empty.push(format!("empty    {}", file_info.path));
+                }
+            }
+
+            if !problems.is_empty() {
+                broken_items += 1;
+            }
+            empty_files += empty.len();
+            if !problems.is_empty() || !empty.is_empty() {
+                println!("{} ({}):", metadata.title, id);
+                for problem in problems.into_iter().chain(empty) {
+                    println!("  {}", problem);
+                }
+            }
+        }
+
+        if broken_items == 0 {
+            println!("All files of {} items verified.", ids.len());
+        } else {
+            println!(
+                "{} of {} items have missing or modified files, run 'download <id> -f' to repair",
+                broken_items,
+                ids.len()
+            );
+        }
+        if empty_files > 0 {
+            println!(
+                "{} installed files are empty (0 bytes), likely placeholders. Set zero_byte_files = \"skip\" to leave them out",
+                empty_files
+            );
+        }
+        Ok(())
+    }
+
+    /// Everything known about one item: local vs remote version, file
+    /// integrity and its recent history
+    async fn cmd_status(&self, args: &[&str]) -> Result<()> {
+        let mut workshop_id = None;
+        let mut offline = false;
+        for &arg in args {
+            match arg {
+                "--offline" => offline = true,
+                value if !value.starts_with('-') && workshop_id.is_none() => workshop_id = Some(value),
+                _ => {
+                    println!("Unknown option: {}", arg);
+                    return Ok(());
+                }
+            }
+        }
+        let Some(workshop_id) = workshop_id else {
+            println!("usage: status <id> [--offline]");
+            return Ok(());
+        };
+        let Some(metadata) = self.metadata.get(workshop_id) else {
+            println!("Item {} is not subscribed", workshop_id);
+            return Ok(());
+        };
+
+        println!("{} ({})", metadata.title, workshop_id);
+
+        let remote = if offline || metadata.unavailable.is_some() {
+            None
+        } else {
+            match self.backends.info.fetch(workshop_id).await {
+                Ok(ParseResult::Item(item)) => Some(item),
+                Ok(_) => None,
+                Err(e) => {
+                    warn!("failed to check the Workshop: {:#}", e);
+                    None
+                }
+            }
+        };
+        match &remote {
+            Some(item) if item.changelog_id != metadata.changelog_id => println!(
+                "  Version     : {} (update available: {})",
+                metadata.changelog_id, item.changelog_id
+            ),
+            Some(_) => println!("  Version     : {} (up to date)", metadata.changelog_id),
+            None => println!("  Version     : {}", metadata.changelog_id),
+        }
+        if let Some(item) = &remote
+            && item.title != metadata.title
+        {
+            println!("  Remote title: {}", item.title);
+        }
+        for change in metadata.previous_titles.iter().rev() {
+            println!(
+                "  Formerly    : {} (until {})",
+                change.title,
+                change.until.format("%Y-%m-%d")
+            );
+        }
+        if let Some(updated) = metadata.updated_at {
+            println!("  Downloaded  : {}", updated.format("%Y-%m-%d %H:%M UTC"));
+        }
+
+        let local_size: u64 = metadata.files.iter().map(|f| f.size).sum();
+        println!(
+            "  Size        : {} in {} files",
+            format_file_size(local_size),
+            metadata.files.len()
+        );
+        if !metadata.skipped_files.is_empty() {
+            println!("  Skipped     : {} files rejected by the whitelist", metadata.skipped_files.len());
+        }
+        if !metadata.collection_ids.is_empty() {
+            println!("  Collections : {}", self.collection_labels(&metadata.collection_ids));
+        }
+        if let Some(appid) = &metadata.appid {
+            println!("  Appid       : {}", appid);
+        }
+        if let Some(platform) = &metadata.platform {
+            println!("  Platform    : {}", platform);
+        }
+        if !metadata.requires.is_empty() {
+            println!("  Requires    : {}", metadata.requires.join(", "));
+        }
+        let dependents = self.dependents(workshop_id);
+        if !dependents.is_empty() {
+            println!("  Required by : {}", dependents.join(", "));
+        }
+        if let Some(order) = metadata.order {
+            println!("  Order       : {}", order);
+        }
+        if let Some(group) = &metadata.group {
+            println!("  Group       : {}", group);
+        }
+        if let Some(archived) = metadata.archived {
+            println!("  Archived    : since {}", archived.format("%Y-%m-%d"));
+        }
+        if let Some(unavailable) = &metadata.unavailable {
+            println!(
+                "  Unavailable : {} (since {})",
+                unavailable.reason,
+                unavailable.since.format("%Y-%m-%d")
+            );
+        }
+
+        if metadata.archived.is_none() && !metadata.files.is_empty() {
+            println!("Files:");
+            for file_info in &metadata.files {
+                let state = if !fs::try_exists(self.paths.file_path(file_info)).await? {
+                    "missing"
+                } else if !self.verify_file(file_info).await? {
+                    "modified"
+                } else {
+                    "ok"
+                };
+                println!("  {:<8} {}", state, file_info.path);
+            }
+        }
+
+        let entries = audit::read(&self.paths.audit_log).await?;
+        let history: Vec<&audit::AuditEntry> =
+            entries.iter().filter(|e| e.id == workshop_id).collect();
+        if !history.is_empty() {
+            println!("Recent history:");
+            for entry in &history[history.len().saturating_sub(5)..] {
+                println!(
+                    "  {}  {:<8} ({} files)",
+                    entry.time.format("%Y-%m-%d %H:%M:%S"),
+                    entry.op.as_str(),
+                    entry.files.len()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn cmd_search(&self, args: &[&str]) -> Result<()> {
+        let mut count = 20;
+        let mut words = Vec::new();
+
+        let mut iter = args.iter().copied();
+        while let Some(arg) = iter.next() {
+            match arg {
+                "-n" | "--limit" => match iter.next().and_then(|n| n.parse().ok()) {
+                    Some(n) => count = n,
+                    None => {
+                        println!("usage: search <text> [-n <count>]");
+                        return Ok(());
+                    }
+                },
+                _ => words.push(arg),
+            }
+        }
+
+        if words.is_empty() {
+            println!("usage: search <text> [-n <count>]");
+            return Ok(());
+        }
+
+        let results = self
+            .api
+            .search(&self.config.appid, &words.join(" "), count)
+            .await?;
+
+        if results.is_empty() {
+            println!("No Workshop items found.");
+            return Ok(());
+        }
+
+        println!("{:<12} {:<40} {:>10} {:>8}  Updated", "ID", "Title", "Size", "Subs");
+        for item in results {
+            let marker = if self.metadata.contains_key(&item.publishedfileid) {
+                "*"
+            } else {
+                ""
+            };
+            println!(
+                "{:<12} {:<40} {:>10} {:>8}  {:<10}{}",
+                item.publishedfileid,
+                item.title.chars().take(40).collect::<String>(),
+                format_file_size(item.file_size),
+                item.subscriptions,
+                item.time_updated
+                    .map(|t| t.format("%Y-%m-%d").to_string())
+                    .unwrap_or_default(),
+                marker
+            );
+        }
+        println!("(* already subscribed)");
+        Ok(())
+    }
+
+    /// Re-checks subscriptions against the Workshop on an interval and
+    /// repaints a short summary until Ctrl+C
+    async fn cmd_watch(&mut self, args: &[&str]) -> Result<()> {
+        const USAGE: &str = "usage: watch [-n <seconds>]";
+        const MIN_INTERVAL: u64 = 30;
+        let mut interval = 300;
+
+        let mut iter = args.iter().copied();
+        while let Some(arg) = iter.next() {
+            match arg {
+                "-n" | "--interval" => match iter.next().and_then(|n| n.parse().ok()) {
+                    Some(n) => interval = n,
+                    None => {
+                        println!("{}", USAGE);
+                        return Ok(());
+                    }
+                },
+                _ => {
+                    println!("Unknown option: {}", arg);
+                    return Ok(());
+                }
+            }
+        }
+        if interval < MIN_INTERVAL {
+            println!("The interval must be at least {} seconds", MIN_INTERVAL);
+            return Ok(());
+        }
+        if !std::io::stdout().is_terminal() {
+            println!("watch needs a terminal, use 'update --check' in scripts");
+            return Ok(());
+        }
+
+        loop {
+            self.forget_lookups();
+            let lines = tokio::select! {
+                lines = self.watch_summary(interval) => lines,
+                _ = tokio::signal::ctrl_c() => break,
+            };
+            print!("\x1b[2J\x1b[H");
+            for line in lines {
+                println!("{}", line);
+            }
+            std::io::stdout().flush()?;
+
+            let reload = tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(interval)) => false,
+                _ = platform::hangup() => true,
+                _ = tokio::signal::ctrl_c() => break,
+            };
+            if reload {
+                self.reload_config().await;
+            }
+        }
+
+        println!();
+        Ok(())
+    }
+
+    /// Runs `update` on an interval for unattended operation. SIGTERM stops
+    /// after the item in progress, leaving the rest for `update --resume`.
+    /// With `--systemd` readiness and status go to systemd's notify socket
+    /// and its watchdog is pinged.
+    async fn cmd_daemon(&mut self, args: &[&str]) -> Result<()> {
+        const USAGE: &str = "usage: daemon [-n <seconds>] [--systemd]";
+        const MIN_INTERVAL: u64 = 60;
+        let mut interval = 3600;
+        let mut systemd = false;
+
+        let mut iter = args.iter().copied();
+        while let Some(arg) = iter.next() {
+            match arg {
+                "-n" | "--interval" => match iter.next().and_then(|n| n.parse().ok()) {
+                    Some(n) => interval = n,
+                    None => {
+                        println!("{}", USAGE);
+                        return Ok(());
+                    }
+                },
+                "--systemd" => systemd = true,
+                _ => {
+                    println!("Unknown option: {}", arg);
+                    return Ok(());
+                }
+            }
+        }
+        if interval < MIN_INTERVAL {
+            println!("The interval must be at least {} seconds", MIN_INTERVAL);
+            return Ok(());
+        }
+
+        // Held until the daemon returns
+        let Some(_lock) = self.lock_daemon()? else {
+            println!("A daemon is already running for {}", self.paths.base_dir.display());
+            return Ok(());
+        };
+        let mut status = DaemonStatus {
+            pid: std::process::id(),
+            started_at: Utc::now(),
+            interval,
+            next_run: None,
+        };
+
+        // Catches SIGTERM while an update runs, the loops check for it
+        tokio::spawn(platform::terminate());
+        // Pinged from the update itself (items, SteamCMD output) and while
+        // idle, never from a separate task, so a hang trips the watchdog
+        let watchdog = if systemd { platform::enable_systemd() } else { None };
+        if systemd {
+            platform::sd_notify("READY=1");
+        }
+        println!("Updating every {}s, stop with SIGTERM or Ctrl+C", interval);
+
+        loop {
+            // Every cycle has to see the Workshop as it is now
+            self.forget_lookups();
+            if systemd {
+                platform::sd_notify("STATUS=Updating");
+            }
+            status.next_run = None;
+            self.write_daemon_status(&status).await;
+            let result = self.cmd_update(&[]).await;
+            if let Err(e) = &result {
+                error!("{:#}", e);
+            }
+            self.record_run("daemon", result.as_ref().err(), true).await;
+            if platform::stop_requested() {
+                break;
+            }
+
+            let next = chrono::Local::now() + chrono::Duration::seconds(interval as i64);
+            status.next_run = Some(next.with_timezone(&Utc));
+            self.write_daemon_status(&status).await;
+            if systemd {
+                platform::sd_notify(&format!("STATUS=Idle, next update at {}", next.format("%H:%M")));
+            }
+            let idle = async {
+                let wake = tokio::time::Instant::now() + Duration::from_secs(interval);
+                match watchdog {
+                    Some(every) => {
+                        while tokio::time::Instant::now() < wake {
+                            tokio::time::sleep_until(wake.min(tokio::time::Instant::now() + every)).await;
+                            platform::watchdog_ping();
+                        }
+                    }
+                    None => tokio::time::sleep_until(wake).await,
+                }
+            };
+            let reload = tokio::select! {
+                _ = idle => false,
+                _ = platform::hangup() => true,
+                _ = platform::terminate() => break,
+                _ = tokio::signal::ctrl_c() => break,
+            };
+            if reload {
+                if systemd {
+                    platform::sd_notify("RELOADING=1");
+                }
+                self.reload_config().await;
+                if systemd {
+                    platform::sd_notify("READY=1");
+                }
+            }
+        }
+
+        if systemd {
+            platform::sd_notify("STOPPING=1");
+        }
+        let _ = fs::remove_file(&self.paths.daemon_file).await;
+        self.save_metadata().await?;
+        println!("Stopped");
+        Ok(())
+    }
+
+    async fn watch_summary(&self, interval: u64) -> Vec<String> {
+        let mut outdated = Vec::new();
+        let mut unavailable = Vec::new();
+        let mut archived = 0;
+
+        for (id, metadata) in &self.metadata {
+            if metadata.archived.is_some() {
+                archived += 1;
+                continue;
+            }
+            match self.backends.info.fetch(id).await {
+                Ok(ParseResult::Item(item)) if item.changelog_id != metadata.changelog_id => {
+                    outdated.push(format!("  {:<12} {}", id, item.title));
+                }
+                Ok(ParseResult::Item(_)) => {}
+                Ok(ParseResult::Collection(_)) => {
+                    unavailable.push(format!("  {:<12} {} (no longer an item)", id, metadata.title));
+                }
+                Ok(ParseResult::Unavailable(reason)) => {
+                    unavailable.push(format!("  {:<12} {} ({})", id, metadata.title, reason));
+                }
+                Err(e) => unavailable.push(format!("  {:<12} {} ({:#})", id, metadata.title, e)),
+            }
+        }
+        let pending = follow::load(&self.paths.follows_file)
+            .await
+            .map(|f| f.pending.len())
+            .unwrap_or_default();
+
+        let mut lines = vec![
+            format!(
+                "NecoDL watch, every {}s (Ctrl+C to stop)    {}",
+                interval,
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+            ),
+            format!(
+                "Items {}   Outdated {}   Unavailable {}   Archived {}   Pending approval {}",
+                self.metadata.len(),
+                outdated.len(),
+                unavailable.len(),
+                archived,
+                pending
+            ),
+        ];
+
+        if let Some(path) = &self.paths.status_file
+            && let Ok(data) = fs::read_to_string(path).await
+            && let Ok(status) = serde_json::from_str::<serde_json::Value>(&data)
+        {
+            lines.push(format!(
+                "Last run: {} ({}) at {}",
+                status["command"].as_str().unwrap_or("?"),
+                status["result"].as_str().unwrap_or("?"),
+                status["finished_at"].as_str().unwrap_or("?")
+            ));
+        }
+
+        for (title, rows) in [("Outdated", outdated), ("Unavailable", unavailable)] {
+            if !rows.is_empty() {
+                lines.push(String::new());
+                lines.push(format!("{}:", title));
+                lines.extend(rows);
+            }
+        }
+        lines
+    }
+
+    /// Shows for each path whether the whitelist lets it through and which
+    /// patterns matched
+    fn cmd_whitelist(&self, args: &[&str]) {
+        let ["test", paths @ ..] = args else {
+            println!("usage: whitelist test <path...>");
+            return;
+        };
+        if paths.is_empty() {
+            println!("usage: whitelist test <path...>");
+            return;
+        }
+
+        if self.config.allow_all {
+            println!("allow_all is set, every file is allowed");
+            return;
+        }
+        let Some(globset) = &self.whitelist else {
+            println!("The whitelist is empty, every file is rejected. Add patterns or set allow_all = true");
+            return;
+        };
+
+        for path in paths {
+            let matched = globset.matches(Path::new(path));
+            if matched.is_empty() {
+                println!("rejected  {}", path);
+            } else {
+                let patterns: Vec<&str> = matched
+                    .iter()
+                    .map(|&i| self.config.whitelist[i].as_str())
+                    .collect();
+                println!("allowed   {}  ({})", path, patterns.join(", "));
+            }
+        }
+    }
+
+    async fn cmd_config(&mut self, args: &[&str]) -> Result<()> {
+        match args {
+            ["reload"] => self.reload_config().await,
+            _ => println!("usage: config reload"),
+        }
+        Ok(())
+    }
+
+    /// Moves the files NecoDL manages in output_dir to another directory,
+    /// points output_dir at it in config.toml and regenerates the outputs.
+    /// Item files are recorded relative to output_dir, so metadata stays as is.
+    async fn cmd_move_output(&mut self, args: &[&str]) -> Result<()> {
+        let [dest] = args else {
+            println!("usage: move-output <path>");
+            return Ok(());
+        };
+        let old_root = self.paths.local_files.clone();
+        let new_root = self.paths.base_dir.join(dest).clean();
+        if new_root == old_root {
+            println!("output_dir is already {}", old_root.display());
+            return Ok(());
+        }
+        if new_root.starts_with(&old_root) || old_root.starts_with(&new_root) {
+            println!("The new output directory can't be inside the current one or contain it");
+            return Ok(());
+        }
+
+        let config_path = self.paths.base_dir.join("config.toml");
+        let content = fs::read_to_string(&config_path)
+            .await
+            .context(NecoError::Fs("Failed to read config.toml".to_string()))?;
+        let updated = set_toml_value(&content, "output_dir", dest);
+
+        // Item files outside content_dirs roots, plus generated and map cycle
+        // files kept in output_dir
+        let mut files: BTreeSet<PathBuf> = self
+            .metadata
+            .values()
+            .flat_map(|m| &m.files)
+            .filter(|f| f.root.is_none())
+            .map(|f| PathBuf::from(&f.path))
+            .collect();
+        let extra = self
+            .paths
+            .outputs
+            .iter()
+            .map(|output| &output.path)
+            .chain(&self.paths.checksums_file)
+            .chain(&self.paths.map_cycles);
+        for path in extra {
+            if let Ok(rel) = path.strip_prefix(&old_root) {
+                files.insert(rel.to_path_buf());
+            }
+        }
+
+        // Archived items have no files in output_dir
+        let mut present = Vec::new();
+        let mut bytes = 0;
+        for rel in files {
+            if let Ok(meta) = fs::metadata(old_root.join(&rel)).await {
+                bytes += meta.len();
+                present.push(rel);
+            }
+        }
+        let mut conflicts = Vec::new();
+        for rel in &present {
+            if fs::try_exists(new_root.join(rel)).await.unwrap_or(false) {
+                conflicts.push(rel.display().to_string());
+            }
+        }
+        if !conflicts.is_empty() {
+            println!("{} files already exist in {}:", conflicts.len(), new_root.display());
+            for path in conflicts.iter().take(5) {
+                println!("  {}", path);
+            }
+            if conflicts.len() > 5 {
+                println!("  ... and {} more", conflicts.len() - 5);
+            }
+            return Err(NecoError::Conflict(format!(
+                "{} files already exist in {}",
+                conflicts.len(),
+                new_root.display()
+            ))
+            .into());
+        }
+
+        println!(
+            "Moving {} files ({}) from {} to {}",
+            present.len(),
+            format_file_size(bytes),
+            old_root.display(),
+            new_root.display()
+        );
+        if !self.confirm("Continue?", true)? {
+            return Ok(());
+        }
+
+        // Directories the move creates, removed again if it has to be undone
+        let mut created = BTreeSet::new();
+        for dir in present.iter().flat_map(|rel| rel.ancestors().skip(1)).map(|dir| new_root.join(dir)) {
+            if !created.contains(&dir) && !fs::try_exists(&dir).await.unwrap_or(true) {
+                created.insert(dir);
+            }
+        }
+
+        for (moved, rel) in present.iter().enumerate() {
+            if let Err(e) = move_file(&old_root.join(rel), &new_root.join(rel)).await {
+                // Put back what was moved so metadata and config still agree
+                for rel in &present[..moved] {
+                    if let Err(e) = move_file(&new_root.join(rel), &old_root.join(rel)).await {
+                        warn!("could not move {} back: {:#}", rel.display(), e);
+                    }
+                }
+                // Deepest first, anything left in them keeps them in place
+                for dir in created.iter().rev() {
+                    let _ = fs::remove_dir(dir).await;
+                }
+                return Err(e.context(NecoError::Fs(format!("Failed to move {}", rel.display()))));
+            }
+        }
+
+        fs::write(&config_path, updated)
+            .await
+            .context(NecoError::Fs("Failed to update config.toml".to_string()))?;
+        // The cached size belongs to the old directory
+        if let Err(e) = fs::remove_file(&self.paths.size_cache_file).await
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            warn!("failed to clear the size cache: {}", e);
+        }
+        self.reload_config().await;
+        self.update_outputs().await?;
+
+        println!(
+            "Moved {} files, output_dir is now {}. Anything else in {} was left in place.",
+            present.len(),
+            dest,
+            old_root.display()
+        );
+        Ok(())
+    }
+
+    /// Re-reads config.toml and rebuilds everything derived from it. A
+    /// config that fails to load or validate leaves the current one in use.
+    async fn reload_config(&mut self) {
+        match Self::new(&self.paths.base_dir).await {
+            Ok(mut fresh) => {
+                fresh.assume_yes = self.assume_yes;
+                *self = fresh;
+                println!("Reloaded config.toml");
+            }
+            Err(e) => warn!("keeping the current config, config.toml was rejected: {:#}", e),
+        }
+    }
+
+    async fn cmd_diff(&self) -> Result<()> {
+        if self.metadata.is_empty() && self.collections.is_empty() {
+            println!("No subscribed items. Use 'download <id>' to add items.");
+            return Ok(());
+        }
+
+        let mut ids: Vec<String> = self.metadata.keys().cloned().collect();
+        ids.sort();
+
+        let mut outdated = Vec::new();
+        let mut renamed = Vec::new();
+        let mut unavailable = Vec::new();
+        let mut collections: HashMap<String, Vec<String>> = HashMap::new();
+
+        println!("Checking {} items against the Workshop...", ids.len());
+
+        for id in &ids {
+            let metadata = &self.metadata[id];
+            for cid in &metadata.collection_ids {
+                collections.entry(cid.clone()).or_default().push(id.clone());
+            }
+
+            match self.backends.info.fetch(id).await {
+                Ok(ParseResult::Item(item)) => {
+                    if item.changelog_id != metadata.changelog_id {
+                        outdated.push(format!(
+                            "{:<12} {} ({} -> {})",
+                            id, item.title, metadata.changelog_id, item.changelog_id
+                        ));
+                    }
+                    if item.title != metadata.title {
+                        renamed.push(format!("{:<12} '{}' -> '{}'", id, metadata.title, item.title));
+                    }
+                }
+                Ok(ParseResult::Collection(_)) => {
+                    unavailable.push(format!("{:<12} {} (no longer an item)", id, metadata.title));
+                }
+                Ok(ParseResult::Unavailable(reason)) => {
+                    unavailable.push(format!("{:<12} {} ({})", id, metadata.title, reason));
+                }
+                Err(e) => {
+                    unavailable.push(format!("{:<12} {} ({:#})", id, metadata.title, e));
+                }
+            }
+        }
+
+        for cid in self.collections.keys() {
+            collections.entry(cid.clone()).or_default();
+        }
+
+        let mut membership = Vec::new();
+        let mut collection_ids: Vec<&String> = collections.keys().collect();
+        collection_ids.sort();
+
+        for cid in collection_ids {
+            let local = &collections[cid];
+            // Members seen at the last download, so ones left out on purpose
+            // aren't reported as new
+            let known = self.collections.get(cid).map(|r| &r.item_ids);
+            match self.backends.info.fetch(cid).await {
+                Ok(ParseResult::Collection(collection)) => {
+                    for member in &collection.item_ids {
+                        match known {
+                            Some(known) if !known.contains(member) => {
+                                membership.push(format!("{:<12} + {} (new in collection)", cid, member));
+                            }
+                            None if !local.contains(member) => {
+                                membership.push(format!("{:<12} + {} (not tracked)", cid, member));
+                            }
+                            _ => {}
+                        }
+                    }
+                    if let Some(record) = self.collections.get(cid)
+                        && record.title != collection.title
+                    {
+                        membership.push(format!("{:<12} renamed '{}' -> '{}'", cid, record.title, collection.title));
+                    }
+                    for member in local {
+                        if !collection.item_ids.contains(member) {
+                            membership.push(format!("{:<12} - {} (removed from collection)", cid, member));
+                        }
+                    }
+                }
+                Ok(ParseResult::Item(_)) => {
+                    membership.push(format!("{:<12} is no longer a collection", cid));
+                }
+                Ok(ParseResult::Unavailable(reason)) => {
+                    membership.push(format!("{:<12} is unavailable ({})", cid, reason));
+                }
+                Err(e) => membership.push(format!("{:<12} failed to fetch ({:#})", cid, e)),
+            }
+        }
+
+        let sections = [
+            ("Newer changelog available", &outdated),
+            ("Title changed", &renamed),
+            ("Unavailable on the Workshop", &unavailable),
+            ("Collection membership changed", &membership),
+        ];
+
+        if sections.iter().all(|(_, lines)| lines.is_empty()) {
+            println!("Everything is in sync with the Workshop.");
+            return Ok(());
+        }
+
+        for (heading, lines) in sections {
+            if lines.is_empty() {
+                continue;
+            }
+            println!("\n{} ({}):", heading, lines.len());
+            for line in lines {
+                println!("  {}", line);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn write_report(&self, path: &str) -> Result<()> {
+        let now = Utc::now();
+        let content = if path.to_lowercase().ends_with(".html") {
+            report::render_html(&self.updates, now)
+        } else {
+            report::render_markdown(&self.updates, now)
+        };
+
+        fs::write(path, content)
+            .await
+            .with_context(|| NecoError::Fs(format!("Failed to write report to {}", path)))?;
+        println!("Report written to {}", path);
+        Ok(())
+    }
+
+    async fn cmd_list(&self, args: &[&str]) -> Result<()> {
+        let mut verbose = false;
+        let mut format = None;
+        let mut output = None;
+        let mut appid = None;
+
+        let mut iter = args.iter().copied();
+        while let Some(arg) = iter.next() {
+            match arg {
+                "-v" | "--verbose" => verbose = true,
+                "-c" | "--collections" => {
+                    self.list_collections();
+                    return Ok(());
+                }
+                "--format" => match iter.next() {
+                    Some(value @ ("csv" | "html")) => format = Some(value),
+                    _ => {
+                        println!("--format must be one of: csv, html");
+                        return Ok(());
+                    }
+                },
+                "-o" | "--output" => match iter.next() {
+                    Some(path) => output = Some(path),
+                    None => {
+                        println!("usage: list [-v|--collections] [--appid <appid>] [--format csv|html] [-o <path>]");
+                        return Ok(());
+                    }
+                },
+                "--appid" => match iter.next() {
+                    Some(value) => appid = Some(value),
+                    None => {
+                        println!("usage: list [-v|--collections] [--appid <appid>] [--format csv|html] [-o <path>]");
+                        return Ok(());
+                    }
+                },
+                _ => {
+                    println!("Unknown option: {}", arg);
+                    return Ok(());
+                }
+            }
+        }
+
+        if let Some(format) = format {
+            return self.export_inventory(format, output, appid).await;
+        }
+
+        if self.metadata.is_empty() {
+            println!("No subscribed items. Use 'download <id>' to add items.");
+            return Ok(());
+        }
+
+        let items: Vec<(&String, &WorkshopMetadata)> = self
+            .metadata
+            .iter()
+            .filter(|(id, _)| appid.is_none_or(|appid| self.appid(id) == appid))
+            .collect();
+        match appid {
+            Some(appid) => println!("Subscribed items of appid {} ({}):", appid, items.len()),
+            None => println!("Subscribed items ({}):", items.len()),
+        }
+
+        if verbose {
+            println!("{}", "=".repeat(60));
+        }
+
+        for (workshop_id, metadata) in items {
+            if verbose {
+                self.print_detailed_item(workshop_id, metadata)?;
+            } else {
+                let map_name = metadata
+                    .files
+                    .iter()
+                    .find(|f| f.path.ends_with(".bsp"))
+                    .and_then(|f| Path::new(&f.path).file_stem())
+                    .map(|s| s.to_string_lossy())
+                    .unwrap_or_else(|| "no_map".into());
+
+                let size = metadata
+                    .file_size
+                    .map(format_file_size)
+                    .unwrap_or_default();
+                let updated = metadata
+                    .time_updated
+                    .map(|t| t.format("%Y-%m-%d").to_string())
+                    .unwrap_or_default();
+
+                let state = if metadata.archived.is_some() {
+                    " [archived]"
+                } else if metadata.unavailable.is_some() {
+                    " [unavailable]"
+                } else if metadata.update_available.is_some() {
+                    " [update available]"
+                } else {
+                    ""
+                };
+                println!(
+                    "{:<12} {:<32} {:>10} {:<10}{}",
+                    workshop_id, map_name, size, updated, state
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the inventory as CSV or HTML, only items of `appid` if given
+    async fn export_inventory(&self, format: &str, output: Option<&str>, appid: Option<&str>) -> Result<()> {
+        let mut rows: Vec<report::InventoryRow> = self
+            .metadata
+            .iter()
+            .filter(|(id, _)| appid.is_none_or(|appid| self.appid(id) == appid))
+            .map(|(id, metadata)| report::InventoryRow {
+                id: id.clone(),
+                title: metadata.title.clone(),
+                map: self.extract_map_name(metadata).unwrap_or_default(),
+                size: metadata.files.iter().map(|f| f.size).sum(),
+                updated_at: metadata.updated_at,
+                collections: metadata.collection_ids.clone(),
+            })
+            .collect();
+        rows.sort_by_key(|row| row.title.to_lowercase());
+
+        let content = match format {
+            "html" => report::render_inventory_html(&rows, Utc::now()),
+            _ => report::render_inventory_csv(&rows),
+        };
+
+        match output {
+            Some(path) => {
+                fs::write(path, content)
+                    .await
+                    .with_context(|| NecoError::Fs(format!("Failed to write {}", path)))?;
+                println!("Wrote {} items to {}", rows.len(), path);
+            }
+            None => print!("{}", content),
+        }
+
+        Ok(())
+    }
+
+    /// Collection ids with their titles where known
+    fn collection_labels(&self, collection_ids: &[String]) -> String {
+        collection_ids
+            .iter()
+            .map(|cid| match self.collections.get(cid) {
+                Some(record) => format!("{} ({})", record.title, cid),
+                None => cid.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn list_collections(&self) {
+        let mut members: BTreeMap<&str, usize> = BTreeMap::new();
+        for metadata in self.metadata.values() {
+            for cid in &metadata.collection_ids {
+                *members.entry(cid.as_str()).or_default() += 1;
+            }
+        }
+        for cid in self.collections.keys() {
+            members.entry(cid.as_str()).or_default();
+        }
+
+        if members.is_empty() {
+            println!("No collections. Use 'download <id>' with a collection id to add one.");
+            return;
+        }
+
+        println!("Collections ({}):", members.len());
+        for (cid, tracked) in members {
+            match self.collections.get(cid) {
+                Some(record) => println!(
+                    "{:<12} {} ({} of {} items, synced {})",
+                    cid,
+                    record.title,
+                    tracked,
+                    record.item_ids.len(),
+                    record.synced_at.format("%Y-%m-%d")
+                ),
+                None => println!("{:<12} (unknown title, {} items)", cid, tracked),
+            }
+        }
+    }
+
+    fn print_detailed_item(&self, workshop_id: &str, metadata: &WorkshopMetadata) -> Result<()> {
+        println!("ID: {}", workshop_id);
+        println!("Title: {}", metadata.title);
+        if !metadata.previous_titles.is_empty() {
+            let titles: Vec<&str> = metadata.previous_titles.iter().rev().map(|t| t.title.as_str()).collect();
+            println!("Formerly: {}", titles.join(", "));
+        }
+
+        if let Some(size) = metadata.file_size {
+            println!("Workshop Size: {}", format_file_size(size));
+        }
+        if let Some(updated) = metadata.time_updated {
+            println!("Workshop Updated: {}", updated.format("%Y-%m-%d %H:%M UTC"));
+        }
+
+        if let Some(archived) = metadata.archived {
+            println!("Archived: since {}", archived.format("%Y-%m-%d"));
+        }
+
+        if let Some(unavailable) = &metadata.unavailable {
+            println!(
+                "Unavailable: {} (since {})",
+                unavailable.reason,
+                unavailable.since.format("%Y-%m-%d")
+            );
+        }
+
+        if !metadata.collection_ids.is_empty() {
+            println!("Collections: {}", self.collection_labels(&metadata.collection_ids));
+        }
+
+        if !metadata.files.is_empty() {
+            println!("Files ({}):", metadata.files.len());
+            let (maps, others) = group_map_files(&metadata.files);
+
+            for map in &maps {
+                println!(
+                    "  Map {}{}",
+                    map.name,
+                    if map.has_navmesh() { "" } else { " (no navmesh)" }
+                );
+                println!("    - {}", map.bsp.path);
+                for file_info in &map.companions {
+                    println!("    - {}", file_info.path);
+                }
+            }
+
+            if !others.is_empty() {
+                if !maps.is_empty() {
+                    println!("  Other files");
+                }
+                for file_info in others {
+                    println!("    - {}", file_info.path);
+                }
+            }
+        }
+
+        if !metadata.skipped_files.is_empty() {
+            println!("Skipped by whitelist ({}):", metadata.skipped_files.len());
+            for path in &metadata.skipped_files {
+                println!("    - {}", path);
+            }
+        }
+
+        println!("{}", "-".repeat(40));
+        Ok(())
+    }
+
+    /// Removes an item, or a collection along with the items only it
+    /// brought in. `--keep-orphans` keeps those as standalone items,
+    /// `--cascade` removes them without asking.
+    async fn cmd_remove(&mut self, args: &[&str]) -> Result<()> {
+        const USAGE: &str = "usage: remove <workshop_id> [--cascade|--keep-orphans] [--force]";
+        let mut workshop_id = None;
+        let mut cascade = None;
+        let mut force = false;
+
+        for &arg in args {
+            match arg {
+                "-f" | "--force" => force = true,
+                "--cascade" => cascade = Some(true),
+                "--keep-orphans" => cascade = Some(false),
+                value if !value.starts_with('-') && workshop_id.is_none() => workshop_id = Some(value),
+                _ => {
+                    println!("Unknown option: {}", arg);
+                    return Ok(());
+                }
+            }
+        }
+        let Some(workshop_id) = workshop_id else {
+            println!("{}", USAGE);
+            return Ok(());
+        };
+
         if self.metadata.contains_key(workshop_id) {
+            let dependents = self.dependents(workshop_id);
+            if !dependents.is_empty() && !force {
+                println!("{} is required by:", workshop_id);
+                for id in &dependents {
+                    println!("  {:<12} {}", id, self.metadata[id].title);
+                }
+                println!("Use 'remove {} --force' to remove it anyway", workshop_id);
+                return Ok(());
+            }
             self.remove_item(workshop_id).await?;
         }
 
-        let mut to_remove = Vec::new();
-        for (id, object) in &self.metadata {
-            if object.collection_ids.len() == 1 && object.collection_ids[0] == workshop_id {
-                to_remove.push(id.clone());
+        let mut orphans = Vec::new();
+        let mut shared = Vec::new();
+        for (id, object) in &self.metadata {
+            if !object.collection_ids.iter().any(|cid| cid == workshop_id) {
+                continue;
+            }
+            if object.collection_ids.len() == 1 {
+                orphans.push(id.clone());
+            } else {
+                shared.push(id.clone());
+            }
+        }
+
+        // Members other items depend on stay unless forced
+        let mut to_remove = Vec::new();
+        let mut required = Vec::new();
+        for id in orphans.iter().cloned() {
+            let needed_by: Vec<String> = self
+                .dependents(&id)
+                .into_iter()
+                .filter(|dependent| !orphans.contains(dependent))
+                .collect();
+            if needed_by.is_empty() || force {
+                to_remove.push(id);
+            } else {
+                required.push((id, needed_by));
+            }
+        }
+
+        if !to_remove.is_empty() || !shared.is_empty() || !required.is_empty() {
+            self.print_removal_plan(workshop_id, &to_remove, &shared, &required);
+        }
+
+        let selected: Vec<String> = match cascade {
+            Some(true) => to_remove,
+            Some(false) => Vec::new(),
+            None if to_remove.is_empty() => Vec::new(),
+            None => {
+                let picked =
+                    self.pick(&format!("Remove these {} items?", to_remove.len()), to_remove.len(), false)?;
+                to_remove
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(i, _)| picked.contains(&(i + 1)))
+                    .map(|(_, id)| id)
+                    .collect()
+            }
+        };
+
+        let mut kept = orphans.len() - selected.len();
+        for id in &selected {
+            // Deselected items may still need this one
+            let needed_by: Vec<String> = self
+                .dependents(id)
+                .into_iter()
+                .filter(|dependent| !selected.contains(dependent))
+                .collect();
+            if !needed_by.is_empty() && !force {
+                println!("Keeping {} - required by {}", id, needed_by.join(", "));
+                kept += 1;
+                continue;
+            }
+            self.remove_item(id).await?;
+        }
+        if kept > 0 {
+            println!("Kept {} items as standalone subscriptions", kept);
+        }
+
+        // Whatever is left no longer belongs to the collection
+        let mut detached = false;
+        for object in self.metadata.values_mut() {
+            let before = object.collection_ids.len();
+            object.collection_ids.retain(|cid| cid != workshop_id);
+            detached |= object.collection_ids.len() != before;
+        }
+        if detached {
+            self.save_metadata().await?;
+        }
+        if self.collections.remove(workshop_id).is_some() {
+            self.save_collections().await?;
+        }
+
+        self.update_outputs().await?;
+        Ok(())
+    }
+
+    /// Lists what removing a collection does to its members before asking
+    fn print_removal_plan(
+        &self,
+        collection_id: &str,
+        to_remove: &[String],
+        shared: &[String],
+        required: &[(String, Vec<String>)],
+    ) {
+        match self.collections.get(collection_id) {
+            Some(record) => println!("Collection {} ({}):", record.title, collection_id),
+            None => println!("Collection {}:", collection_id),
+        }
+        if !to_remove.is_empty() {
+            println!("Removed, only in this collection:");
+            for (i, id) in to_remove.iter().enumerate() {
+                println!("{:>4}  {:<12} {}", i + 1, id, self.metadata[id].title);
+            }
+        }
+        if !shared.is_empty() {
+            println!("Kept, also in other collections:");
+            for id in shared {
+                let others: Vec<String> = self.metadata[id]
+                    .collection_ids
+                    .iter()
+                    .filter(|cid| *cid != collection_id)
+                    .cloned()
+                    .collect();
+                println!(
+                    "      {:<12} {} ({})",
+                    id,
+                    self.metadata[id].title,
+                    self.collection_labels(&others)
+                );
+            }
+        }
+        if !required.is_empty() {
+            println!("Kept, required by other items (--force removes them):");
+            for (id, needed_by) in required {
+                println!(
+                    "      {:<12} {} (required by {})",
+                    id,
+                    self.metadata[id].title,
+                    needed_by.join(", ")
+                );
+            }
+        }
+    }
+
+    /// Tracked items listing `workshop_id` among their required items
+    fn dependents(&self, workshop_id: &str) -> Vec<String> {
+        self.metadata
+            .iter()
+            .filter(|(id, m)| id.as_str() != workshop_id && m.requires.iter().any(|r| r == workshop_id))
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+
+    async fn cmd_archive(&mut self, args: &[&str]) -> Result<()> {
+        let [workshop_id] = args else {
+            println!("usage: archive <workshop_id>");
+            return Ok(());
+        };
+        let Some(metadata) = self.metadata.get(*workshop_id) else {
+            println!("Item {} is not subscribed", workshop_id);
+            return Ok(());
+        };
+        if metadata.archived.is_some() {
+            println!("{} is already archived", workshop_id);
+            return Ok(());
+        }
+        if self.is_map_live(metadata).await {
+            println!("Skipping {} - map is currently being played", workshop_id);
+            return Ok(());
+        }
+
+        let item_dir = self.paths.archive_dir.join(workshop_id);
+        let files = metadata.files.clone();
+        let (mut original, mut compressed) = (0, 0);
+        let mut archived_files = Vec::new();
+
+        for file_info in &files {
+            let full_path = self.paths.file_path(file_info);
+            if !fs::try_exists(&full_path).await? {
+                println!("Warning: {} is missing, not archived", file_info.path);
+                continue;
+            }
+            if let Some(other) = self.file_owner(&full_path, workshop_id) {
+                println!("Keeping {} - also installed by {}", file_info.path, other);
+                continue;
+            }
+
+            let dest = archive::archived_path(&item_dir, &file_info.path);
+            compressed += archive::compress(&full_path, &dest).await?;
+            original += file_info.size;
+            archived_files.push(file_info.path.clone());
+        }
+
+        // Record the item as archived once everything is safely compressed,
+        // before any original is dropped
+        let metadata = self.metadata.get_mut(*workshop_id).expect("checked above");
+        metadata.archived = Some(Utc::now());
+        let title = metadata.title.clone();
+        self.save_metadata().await?;
+
+        let mut removed = 0;
+        for file_info in files.iter().filter(|f| archived_files.contains(&f.path)) {
+            let path = self.paths.file_path(file_info);
+            let shared = self.linked_elsewhere(&path, file_info).await;
+            match fs::remove_file(&path).await {
+                Ok(()) if shared => {}
+                Ok(()) => removed += file_info.size,
+                Err(e) => warn!("failed to remove {}: {}", path.display(), e),
+            }
+        }
+        self.adjust_cached_size(-(removed as i64)).await;
+        self.update_outputs().await?;
+
+        self.record_audit(audit::AuditEntry {
+            time: Utc::now(),
+            op: audit::Operation::Archive,
+            id: workshop_id.to_string(),
+            title: title.clone(),
+            files: archived_files,
+            changelog_id: None,
+            trash: None,
+            previous: None,
+            reverts: None,
+        })
+        .await;
+
+        println!(
+            "Archived {} ({}): {} -> {}",
+            title,
+            workshop_id,
+            format_file_size(original),
+            format_file_size(compressed)
+        );
+        Ok(())
+    }
+
+    async fn cmd_report(&self, args: &[&str]) -> Result<()> {
+        match args {
+            ["unused", files @ ..] => self.report_unused(files).await,
+            _ => {
+                println!("usage: report unused [<mapcycle file>...]");
+                Ok(())
+            }
+        }
+    }
+
+    /// Items none of whose maps appear in the server's map rotation files,
+    /// largest first, as candidates for removal
+    async fn report_unused(&self, files: &[&str]) -> Result<()> {
+        let cycles: Vec<PathBuf> = if files.is_empty() {
+            self.paths.map_cycles.clone()
+        } else {
+            files.iter().map(PathBuf::from).collect()
+        };
+        if cycles.is_empty() {
+            println!("No map cycle files given, set map_cycles in config.toml or pass them as arguments");
+            return Ok(());
+        }
+
+        let mut referenced = HashSet::new();
+        for path in &cycles {
+            let content = fs::read_to_string(path)
+                .await
+                .with_context(|| NecoError::Fs(format!("Failed to read {}", path.display())))?;
+            referenced.extend(map_cycle_entries(&content));
+        }
+
+        let mut unused: Vec<(&String, &WorkshopMetadata, u64)> = self
+            .metadata
+            .iter()
+            .filter(|(_, m)| m.archived.is_none())
+            .filter(|(_, m)| {
+                let (maps, _) = group_map_files(&m.files);
+                !maps.is_empty() && maps.iter().all(|map| !referenced.contains(&map.name.to_lowercase()))
+            })
+            .map(|(id, m)| (id, m, m.files.iter().map(|f| f.size).sum()))
+            .collect();
+        if unused.is_empty() {
+            println!("Every tracked map is referenced by {} map cycle files", cycles.len());
+            return Ok(());
+        }
+        unused.sort_by_key(|(_, _, size)| std::cmp::Reverse(*size));
+
+        println!("Items with no map in the map cycle ({}):", unused.len());
+        for (id, metadata, size) in &unused {
+            let maps: Vec<String> = group_map_files(&metadata.files).0.into_iter().map(|m| m.name).collect();
+            println!(
+                "  {:<12} {:>10}  {} ({})",
+                id,
+                format_file_size(*size),
+                metadata.title,
+                maps.join(", ")
+            );
+        }
+        let total: u64 = unused.iter().map(|(_, _, size)| size).sum();
+        println!("Removing them would free about {}", format_file_size(total));
+        Ok(())
+    }
+
+    async fn cmd_prune(&mut self, args: &[&str]) -> Result<()> {
+        const USAGE: &str = "usage: prune --unavailable [--archive|--keep-files] [--grace <days>]";
+        let mut unavailable = false;
+        let mut archive = false;
+        let mut keep_files = false;
+        let mut grace = self.config.prune_grace_days;
+
+        let mut iter = args.iter().copied();
+        while let Some(arg) = iter.next() {
+            match arg {
+                "--unavailable" => unavailable = true,
+                "--archive" => archive = true,
+                "--keep-files" => keep_files = true,
+                "--grace" => match iter.next().and_then(|n| n.parse().ok()) {
+                    Some(days) => grace = days,
+                    None => {
+                        println!("{}", USAGE);
+                        return Ok(());
+                    }
+                },
+                _ => {
+                    println!("Unknown option: {}", arg);
+                    return Ok(());
+                }
+            }
+        }
+        if !unavailable || (archive && keep_files) {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+
+        let cutoff = Utc::now() - chrono::Duration::days(grace.into());
+        let candidates: Vec<(String, String, String)> = self
+            .metadata
+            .iter()
+            .filter_map(|(id, m)| {
+                let unavailable = m.unavailable.as_ref().filter(|u| u.since <= cutoff)?;
+                // Already put away, archiving again would do nothing
+                if archive && m.archived.is_some() {
+                    return None;
+                }
+                Some((id.clone(), m.title.clone(), unavailable.reason.clone()))
+            })
+            .collect();
+        if candidates.is_empty() {
+            println!("No items unavailable for more than {} days", grace);
+            return Ok(());
+        }
+
+        println!("Unavailable for more than {} days:", grace);
+        for (id, title, reason) in &candidates {
+            println!("  {:<12} {} ({})", id, title, reason);
+        }
+        let action = if archive {
+            "Archive"
+        } else if keep_files {
+            "Stop tracking (keeping files)"
+        } else {
+            "Remove"
+        };
+        if !self.confirm(&format!("{} these {} items?", action, candidates.len()), true)? {
+            return Ok(());
+        }
+
+        for (id, _, _) in &candidates {
+            if archive {
+                self.cmd_archive(&[id.as_str()]).await?;
+            } else if keep_files {
+                self.forget_item(id).await?;
+            } else {
+                self.remove_item(id).await?;
+            }
+        }
+        self.update_outputs().await?;
+        Ok(())
+    }
+
+    /// Drops an item from metadata without touching its files
+    async fn forget_item(&mut self, workshop_id: &str) -> Result<()> {
+        let Some(metadata) = self.metadata.remove(workshop_id) else {
+            return Ok(());
+        };
+        self.save_metadata().await?;
+        println!("Stopped tracking {} ({}), its files were kept", metadata.title, workshop_id);
+
+        self.record_audit(audit::AuditEntry {
+            time: Utc::now(),
+            op: audit::Operation::Forget,
+            id: workshop_id.to_string(),
+            title: metadata.title.clone(),
+            files: Vec::new(),
+            changelog_id: Some(metadata.changelog_id.clone()),
+            trash: None,
+            previous: Some(metadata),
+            reverts: None,
+        })
+        .await;
+        Ok(())
+    }
+
+    /// Finds items an author re-uploaded under a new ID: identical files, or
+    /// the same map name. With `--merge` the older item's collections, order
+    /// and group move to the newer one and the older one is removed.
+    async fn cmd_dedupe(&mut self, args: &[&str]) -> Result<()> {
+        let merge = match args {
+            [] => false,
+            ["--merge"] => true,
+            _ => {
+                println!("usage: dedupe [--merge]");
+                return Ok(());
+            }
+        };
+
+        let duplicates = self.find_duplicates();
+        if duplicates.is_empty() {
+            println!("No duplicated subscriptions found.");
+            return Ok(());
+        }
+
+        println!("Possible re-uploads ({}):", duplicates.len());
+        for (stale, newer, reason) in &duplicates {
+            println!(
+                "  {:<12} {} -> {:<12} {} ({})",
+                stale, self.metadata[stale].title, newer, self.metadata[newer].title, reason
+            );
+        }
+        if !merge {
+            println!("Use 'dedupe --merge' to keep the newer IDs and remove the older ones");
+            return Ok(());
+        }
+
+        for (stale, newer, reason) in duplicates {
+            // An earlier merge in this run may have removed either of them
+            if !self.metadata.contains_key(&stale) || !self.metadata.contains_key(&newer) {
+                continue;
+            }
+            let identical = reason == "identical files";
+            if !self.confirm(&format!("Replace {} with {}?", stale, newer), identical)? {
+                continue;
+            }
+            self.merge_into(&stale, &newer).await?;
+        }
+        self.update_outputs().await?;
+        Ok(())
+    }
+
+    /// (older, newer, reason) for each pair of items that look like the same
+    /// upload. Re-uploads get a higher ID, so the highest ID is kept.
+    fn find_duplicates(&self) -> Vec<(String, String, &'static str)> {
+        let mut by_files: HashMap<BTreeSet<&str>, Vec<&String>> = HashMap::new();
+        let mut by_map: HashMap<String, Vec<&String>> = HashMap::new();
+        for (id, metadata) in &self.metadata {
+            let hashes: BTreeSet<&str> = metadata
+                .files
+                .iter()
+                .filter(|f| !f.sha256.is_empty())
+                .map(|f| f.sha256.as_str())
+                .collect();
+            if !hashes.is_empty() {
+                by_files.entry(hashes).or_default().push(id);
+            }
+            for map in group_map_files(&metadata.files).0 {
+                by_map.entry(map.name.to_lowercase()).or_default().push(id);
+            }
+        }
+
+        let newest = |ids: &[&String]| -> String {
+            ids.iter()
+                .max_by_key(|id| id.parse::<u64>().unwrap_or_default())
+                .map(|id| id.to_string())
+                .unwrap_or_default()
+        };
+        let mut pairs: Vec<(String, String, &'static str)> = Vec::new();
+        let groups = by_files
+            .values()
+            .map(|ids| (ids, "identical files"))
+            .chain(by_map.values().map(|ids| (ids, "same map name")));
+        for (ids, reason) in groups.filter(|(ids, _)| ids.len() > 1) {
+            let newer = newest(ids);
+            for stale in ids.iter().filter(|id| **id != &newer) {
+                if !pairs.iter().any(|(s, n, _)| s == *stale && *n == newer) {
+                    pairs.push((stale.to_string(), newer.clone(), reason));
+                }
+            }
+        }
+        pairs.sort();
+        pairs
+    }
+
+    /// Moves what the user set up for `stale` over to `newer`, points items
+    /// requiring it at the new ID and removes `stale`
+    async fn merge_into(&mut self, stale: &str, newer: &str) -> Result<()> {
+        let old = self.metadata[stale].clone();
+        let entry = self.metadata.get_mut(newer).expect("checked by the caller");
+        for cid in old.collection_ids {
+            if !entry.collection_ids.contains(&cid) {
+                entry.collection_ids.push(cid);
+            }
+        }
+        entry.order = entry.order.or(old.order);
+        entry.group = entry.group.take().or(old.group);
+        for metadata in self.metadata.values_mut() {
+            for required in metadata.requires.iter_mut().filter(|r| *r == stale) {
+                *required = newer.to_string();
+            }
+        }
+        self.save_metadata().await?;
+
+        if self.remove_item(stale).await? {
+            println!("Merged {} into {}", stale, newer);
+        }
+        Ok(())
+    }
+
+    async fn cmd_unarchive(&mut self, args: &[&str]) -> Result<()> {
+        let [workshop_id] = args else {
+            println!("usage: unarchive <workshop_id>");
+            return Ok(());
+        };
+        let Some(metadata) = self.metadata.get(*workshop_id) else {
+            println!("Item {} is not subscribed", workshop_id);
+            return Ok(());
+        };
+        if metadata.archived.is_none() {
+            println!("{} is not archived", workshop_id);
+            return Ok(());
+        }
+
+        let item_dir = self.paths.archive_dir.join(workshop_id);
+        let files = metadata.files.clone();
+        let mut restored = Vec::new();
+        let mut restored_size = 0;
+
+        for file_info in &files {
+            let src = archive::archived_path(&item_dir, &file_info.path);
+            if !fs::try_exists(&src).await? {
+                continue;
+            }
+
+            archive::decompress(&src, &self.paths.file_path(file_info)).await?;
+            self.apply_permissions(&self.paths.file_path(file_info), false);
+            if !file_info.hash.is_empty() && !self.verify_file(file_info).await? {
+                println!("Warning: {} does not match its recorded hash", file_info.path);
+            }
+            restored_size += file_info.size;
+            restored.push(file_info.path.clone());
+        }
+
+        fs::remove_dir_all(&item_dir)
+            .await
+            .with_context(|| NecoError::Fs(format!("Failed to remove {}", item_dir.display())))?;
+        self.adjust_cached_size(restored_size as i64).await;
+
+        let metadata = self.metadata.get_mut(*workshop_id).expect("checked above");
+        metadata.archived = None;
+        let title = metadata.title.clone();
+        self.save_metadata().await?;
+        self.update_outputs().await?;
+
+        self.record_audit(audit::AuditEntry {
+            time: Utc::now(),
+            op: audit::Operation::Unarchive,
+            id: workshop_id.to_string(),
+            title: title.clone(),
+            files: restored.clone(),
+            changelog_id: None,
+            trash: None,
+            previous: None,
+            reverts: None,
+        })
+        .await;
+
+        println!("Restored {} ({}), {} files", title, workshop_id, restored.len());
+        Ok(())
+    }
+
+    async fn cmd_order(&mut self, args: &[&str]) -> Result<()> {
+        const USAGE: &str = "usage: order set <id> <n> | order group <id> <name> | order attr <id> <key> [value] | order clear <id> | order list";
+
+        match args {
+            ["set", id, value] => {
+                let Ok(order) = value.parse::<i64>() else {
+                    println!("Order must be a number: {}", value);
+                    return Ok(());
+                };
+                let Some(metadata) = self.metadata.get_mut(*id) else {
+                    println!("Not subscribed: {}", id);
+                    return Ok(());
+                };
+                metadata.order = Some(order);
+            }
+            ["group", id, group] => {
+                let Some(metadata) = self.metadata.get_mut(*id) else {
+                    println!("Not subscribed: {}", id);
+                    return Ok(());
+                };
+                metadata.group = Some(group.to_string());
+            }
+            ["attr", id, key, value @ ..] => {
+                if key.eq_ignore_ascii_case("id") || key.contains(['"', '{', '}']) {
+                    println!("Invalid attribute name: {}", key);
+                    return Ok(());
+                }
+                let Some(metadata) = self.metadata.get_mut(*id) else {
+                    println!("Not subscribed: {}", id);
+                    return Ok(());
+                };
+                if value.is_empty() {
+                    metadata.attributes.remove(*key);
+                } else {
+                    metadata.attributes.insert(key.to_string(), value.join(" "));
+                }
+            }
+            ["clear", id] => {
+                let Some(metadata) = self.metadata.get_mut(*id) else {
+                    println!("Not subscribed: {}", id);
+                    return Ok(());
+                };
+                metadata.order = None;
+                metadata.group = None;
+            }
+            ["list"] | [] => {
+                for (map_name, id) in self.ordered_maps() {
+                    let metadata = &self.metadata[id];
+                    let attributes: Vec<String> = metadata
+                        .attributes
+                        .iter()
+                        .map(|(k, v)| format!("{}={}", k, v))
+                        .collect();
+                    println!(
+                        "{:>6} {:<12} {:<12} {} {}",
+                        metadata.order.map(|o| o.to_string()).unwrap_or("-".into()),
+                        metadata.group.as_deref().unwrap_or("-"),
+                        id,
+                        map_name,
+                        attributes.join(" ")
+                    );
+                }
+                return Ok(());
+            }
+            _ => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+        }
+
+        self.save_metadata().await?;
+        self.update_outputs().await?;
+        println!("Order updated");
+        Ok(())
+    }
+
+    async fn cmd_open(&self, args: &[&str]) -> Result<()> {
+        let copy = args.contains(&"-c") || args.contains(&"--copy");
+        let Some(workshop_id) = args.iter().find(|a| !a.starts_with('-')) else {
+            println!("usage: open [-c|--copy] <workshop_id>");
+            return Ok(());
+        };
+        let Some(workshop_id) = parse_workshop_id(workshop_id) else {
+            println!("'{}' is not a Workshop item ID or URL", workshop_id);
+            return Ok(());
+        };
+
+        let url = workshop_url(&workshop_id);
+
+        if copy {
+            copy_to_clipboard(&url).await?;
+            println!("Copied {} to clipboard", url);
+        } else {
+            open_in_browser(&url).await?;
+            println!("Opened {}", url);
+        }
+
+        Ok(())
+    }
+
+    async fn managed_file_hashes(&self) -> Result<BTreeMap<String, String>> {
+        let mut files = BTreeMap::new();
+
+        // Deploy targets mirror output_dir, content_dirs roots are not pushed
+        for metadata in self.metadata.values() {
+            for file_info in metadata.files.iter().filter(|f| f.root.is_none()) {
+                let full_path = self.paths.file_path(file_info);
+                if !fs::try_exists(&full_path).await? {
+                    continue;
+                }
+
+                let hash = if file_info.hash.is_empty() {
+                    self.calculate_file_hash(&full_path).await?
+                } else {
+                    file_info.hash.clone()
+                };
+                files.insert(file_info.path.replace('\\', "/"), hash);
+            }
+        }
+
+        for output in &self.paths.outputs {
+            if let Ok(rel) = output.path.strip_prefix(&self.paths.local_files)
+                && fs::try_exists(&output.path).await?
+            {
+                let hash = self.calculate_file_hash(&output.path).await?;
+                files.insert(rel.to_string_lossy().replace('\\', "/"), hash);
+            }
+        }
+
+        Ok(files)
+    }
+
+    async fn cmd_deploy(&self, args: &[&str]) -> Result<()> {
+        if self.config.deploy.is_empty() {
+            println!("No deploy targets configured. Add [[deploy]] entries to config.toml.");
+            return Ok(());
+        }
+
+        let full = args.contains(&"--full");
+        let names: Vec<&str> = args.iter().copied().filter(|a| !a.starts_with('-')).collect();
+
+        if !args.contains(&"--now") && !self.in_update_window() {
+            println!(
+                "Outside the update window ({}), not deploying. Use --now to deploy anyway.",
+                self.config.update_windows.join(", ")
+            );
+            return Ok(());
+        }
+
+        for name in &names {
+            if !self.config.deploy.iter().any(|t| t.name == *name) {
+                println!("Unknown deploy target: {}", name);
+                return Ok(());
             }
         }
 
-        for id in to_remove {
-            self.remove_item(&id).await?;
+        let mut state: BTreeMap<String, BTreeMap<String, String>> =
+            match fs::read_to_string(&self.paths.deploy_state_file).await {
+                Ok(data) => serde_json::from_str(&data).context("Failed to parse deploy_state.json")?,
+                Err(_) => BTreeMap::new(),
+            };
+
+        let current = self.managed_file_hashes().await?;
+
+        for target in &self.config.deploy {
+            if !names.is_empty() && !names.contains(&target.name.as_str()) {
+                continue;
+            }
+
+            let previous = state.remove(&target.name).unwrap_or_default();
+
+            let mut changed: Vec<String> = current
+                .iter()
+                .filter(|(path, hash)| full || previous.get(*path) != Some(*hash))
+                .map(|(path, _)| path.clone())
+                .collect();
+            let mut removed: Vec<String> = previous
+                .keys()
+                .filter(|path| !current.contains_key(*path))
+                .cloned()
+                .collect();
+            changed.sort();
+            removed.sort();
+
+            println!(
+                "Deploying to {} ({}): {} changed, {} removed",
+                target.name,
+                target.describe(),
+                changed.len(),
+                removed.len()
+            );
+
+            match deploy::push(target, &self.paths.local_files, &changed, &removed).await {
+                Ok(()) => {
+                    state.insert(target.name.clone(), current.clone());
+                }
+                Err(e) => {
+                    error!("failed to deploy to {}: {:#}", target.name, e);
+                    state.insert(target.name.clone(), previous);
+                }
+            }
         }
 
+        let data = serde_json::to_string_pretty(&state)?;
+        write_if_changed(&self.paths.deploy_state_file, &data)
+            .await
+            .context(NecoError::Fs("Failed to save deploy state".to_string()))?;
         Ok(())
     }
 
@@ -906,52 +7801,167 @@ impl WorkshopManager {
             .map(|s| s.to_string_lossy().to_string())
     }
 
+    /// Asks a yes/no question on the terminal. Without a TTY (cron, containers)
+    /// the default answer is used; `--yes` always answers yes.
+    fn confirm(&self, prompt: &str, default: bool) -> Result<bool> {
+        if self.assume_yes {
+            return Ok(true);
+        }
+        if !std::io::stdin().is_terminal() {
+            return Ok(default);
+        }
+
+        print!("{} [{}] ", prompt, if default { "Y/n" } else { "y/N" });
+        std::io::stdout().flush()?;
+
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+
+        Ok(match answer.trim().to_lowercase().as_str() {
+            "" => default,
+            "y" | "yes" => true,
+            _ => false,
+        })
+    }
+
     fn show_help(&self) {
         println!("\nAvailable commands:");
-        println!("  download <id>   - Download workshop item or collection");
+        println!("  download <id>   - Download workshop item or collection, several IDs as a batch");
+        println!("                    (-f downloads again from Steam, --reinstall reinstalls from the keep_download_cache copy)");
+        println!("                    (--existing update|verify|skip for already subscribed items)");
         println!("  update          - Update all subscribed items");
-        println!("  list [-v]       - List subscribed items (use -v for details)");
+        println!("                    (--check only reports available updates, --apply overrides auto_update = false)");
+        println!("                    (--report <file> writes a markdown/HTML digest)");
+        println!("                    (--trust-changelog skips hashing items whose changelog is unchanged)");
+        println!("                    (--by-size, --max-size <size>, --only <n> apply small updates first)");
+        println!("  list [-v]       - List subscribed items (use -v for details, --collections for collections)");
+        println!("                    (--format csv|html [-o <path>] exports an inventory)");
+        println!("  order ...       - Set map list order/group (order set <id> <n>, order group <id> <name>)");
+        println!("  history [id]    - Show downloads, updates and removals (-n <count>, -v for files)");
+        println!("  verify [id]     - Check files of all or one item against their recorded hashes");
+        println!("  stats           - Show download speeds over time and the slowest recent downloads");
+        println!("  status <id>     - Show one item's version, files and recent history (--offline skips the Workshop check)");
+        println!("  undo            - Restore the last removed item or roll back the last update");
+        println!("  archive <id>    - Compress an item's files into cold storage and drop it from map lists");
+        println!("  unarchive <id>  - Restore an archived item");
+        println!("  report unused [file...] - List items whose maps the server's map cycle never references");
+        println!("  prune --unavailable - Remove items gone from the Workshop for longer than prune_grace_days");
+        println!("                    (--archive or --keep-files keep their content, --grace <days> overrides)");
+        println!("  dedupe          - List items that look like re-uploads of each other (--merge keeps the newer one)");
+        println!("  search <text>   - Search the Workshop for the app (requires steam_api_key)");
+        println!("  follow ...      - Pick up new items of an author or search (follow author <profile>, follow tag <tag>, follow list)");
+        println!("  review          - Show discovered items waiting for approval");
+        println!("  approve <id>    - Download a discovered item (or 'all')");
+        println!("  reject <id>     - Discard a discovered item (or 'all')");
+        println!("  diff            - Compare subscriptions against the Workshop without downloading");
+        println!("  watch [-n <s>]  - Re-check subscriptions every few minutes and show a live summary");
+        println!("                    (SIGHUP reloads config.toml)");
+        println!("  daemon [-n <s>] - Run 'update' every hour (or -n seconds) until SIGTERM, for services");
+        println!("                    (--systemd reports readiness and pings the watchdog)");
+        println!("  config reload   - Re-read config.toml without restarting");
+        println!("  move-output <path> - Move managed files to a new output_dir and update config.toml");
+        println!("  whitelist test <path...> - Show whether paths pass the whitelist and which pattern matched");
+        println!("  where-used <name> - Show which item owns a map or file and which outputs list it");
         println!("  remove <id>     - Remove workshop item or collection");
-        println!("                    (collections remove orphaned items)");
+        println!("                    (collections ask before removing orphaned items, --cascade or --keep-orphans to choose)");
         println!("  info            - Show configuration and status information");
-        println!("  import <path>    - Import workshop IDs from workshop_maps.txt");
+        println!("                    (--recalculate refreshes the cached storage usage)");
+        println!("                    (--section config|paths|subs|server|storage, --json for scripts)");
+        println!("  doctor          - Check SteamCMD, disk space and how fast each Steam service responds");
+        println!("                    (--no-steamcmd skips the SteamCMD login test)");
+        println!("  import <path>   - Import workshop IDs from workshop_maps.txt or subs.json");
+        println!("                    (--download fetches them right away)");
+        println!("  import --steam-user <id64> - Import a user's subscribed/favorited items (requires steam_api_key)");
+        println!("  export <path>   - Export subscribed IDs to a portable subs.json");
+        println!("                    (--format steam-collection lists Workshop URLs)");
+        println!("  open <id> [-c]  - Open Workshop page in browser (-c copies URL)");
+        println!("  deploy [name]   - Push output files to deploy targets (--full resends all, --now ignores update_windows)");
         println!("  help            - Show this help");
         println!("  exit            - Exit application");
+
+        if !self.config.alias.is_empty() {
+            let mut aliases: Vec<_> = self.config.alias.iter().collect();
+            aliases.sort();
+
+            println!("\nAliases:");
+            for (name, command) in aliases {
+                println!("  {:<15} - {}", name, command);
+            }
+        }
         println!();
     }
 
+    fn expand_alias(&self, input: &str) -> String {
+        let mut parts = input.split_whitespace();
+        let Some(name) = parts.next() else {
+            return String::new();
+        };
+
+        match self.config.alias.get(name) {
+            Some(command) => std::iter::once(command.as_str())
+                .chain(parts)
+                .collect::<Vec<_>>()
+                .join(" "),
+            None => input.to_string(),
+        }
+    }
+
+    /// Whether a command line runs one of STATE_COMMANDS, directly or
+    /// through an alias
+    fn changes_state(&self, input: &str) -> bool {
+        self.expand_alias(input)
+            .split_whitespace()
+            .next()
+            .is_some_and(|name| STATE_COMMANDS.contains(&name.to_lowercase().as_str()))
+    }
+
     async fn process_command(&mut self, input: &str) -> Result<bool> {
-        let parts: Vec<&str> = input.trim().split_whitespace().collect();
+        let input = self.expand_alias(input);
+        let parts: Vec<&str> = input.split_whitespace().collect();
         if parts.is_empty() {
             return Ok(true);
         }
+        // Lookups are only reused within one command
+        self.forget_lookups();
 
         match parts[0].to_lowercase().as_str() {
             "download" => {
                 self.cmd_download(&parts[1..]).await?;
             }
-            "update" => {
-                self.cmd_update(&parts[1..]).await?;
-            }
-            "list" => {
-                let verbose = parts.contains(&"-v") || parts.contains(&"--verbose");
-                self.cmd_list(verbose).await?;
-            }
-            "remove" => {
-                if let Some(id) = parts.get(1) {
-                    self.cmd_remove(id).await?;
-                } else {
-                    println!("Usage: remove <workshop_id>");
-                }
-            }
-            "import" => {
-                if let Some(path) = parts.get(1) {
-                    self.cmd_import(path).await?;
-                } else {
-                    println!("Usage: import <path_to_workshop_maps.txt>");
-                }
+            "update" => {
+                self.cmd_update(&parts[1..]).await?;
             }
-            "info" => self.cmd_info().await?,
+            "list" => self.cmd_list(&parts[1..]).await?,
+            "remove" => self.cmd_remove(&parts[1..]).await?,
+            "import" => self.cmd_import(&parts[1..]).await?,
+            "export" => self.cmd_export(&parts[1..]).await?,
+            "open" => self.cmd_open(&parts[1..]).await?,
+            "order" => self.cmd_order(&parts[1..]).await?,
+            "history" => self.cmd_history(&parts[1..]).await?,
+            "undo" => self.cmd_undo().await?,
+            "archive" => self.cmd_archive(&parts[1..]).await?,
+            "unarchive" => self.cmd_unarchive(&parts[1..]).await?,
+            "prune" => self.cmd_prune(&parts[1..]).await?,
+            "dedupe" => self.cmd_dedupe(&parts[1..]).await?,
+            "move-output" => self.cmd_move_output(&parts[1..]).await?,
+            "report" => self.cmd_report(&parts[1..]).await?,
+            "verify" => self.cmd_verify(&parts[1..]).await?,
+            "status" => self.cmd_status(&parts[1..]).await?,
+            "stats" => self.cmd_stats().await,
+            "search" => self.cmd_search(&parts[1..]).await?,
+            "follow" => self.cmd_follow(&parts[1..]).await?,
+            "review" => self.cmd_review().await?,
+            "approve" => self.cmd_approve(&parts[1..]).await?,
+            "reject" => self.cmd_reject(&parts[1..]).await?,
+            "diff" => self.cmd_diff().await?,
+            "watch" => self.cmd_watch(&parts[1..]).await?,
+            "daemon" => self.cmd_daemon(&parts[1..]).await?,
+            "config" => self.cmd_config(&parts[1..]).await?,
+            "whitelist" => self.cmd_whitelist(&parts[1..]),
+            "where-used" => self.cmd_where_used(&parts[1..]).await?,
+            "deploy" => self.cmd_deploy(&parts[1..]).await?,
+            "info" => self.cmd_info(&parts[1..]).await?,
+            "doctor" => self.cmd_doctor(&parts[1..]).await?,
             "help" => self.show_help(),
             "exit" | "quit" => return Ok(false),
             "" => {}
@@ -966,6 +7976,300 @@ impl WorkshopManager {
         Ok(true)
     }
 
+    async fn run_cli_command(&mut self, command: Commands) -> Result<()> {
+        match command {
+            Commands::Download {
+                workshop_id,
+                force,
+                reinstall,
+                existing,
+                appid,
+            } => {
+                let mut args: Vec<&str> = workshop_id.iter().map(String::as_str).collect();
+                if force {
+                    args.push("--force");
+                }
+                if reinstall {
+                    args.push("--reinstall");
+                }
+                if let Some(action) = &existing {
+                    args.extend(["--existing", action.as_str()]);
+                }
+                if let Some(appid) = &appid {
+                    args.extend(["--appid", appid.as_str()]);
+                }
+                self.cmd_download(&args).await?;
+            }
+            Commands::Update {
+                force,
+                trust_changelog,
+                verify,
+                report,
+                by_size,
+                max_size,
+                only,
+                check,
+                apply,
+                resume,
+            } => {
+                let mut args = Vec::new();
+                if check {
+                    args.push("--check");
+                }
+                if apply {
+                    args.push("--apply");
+                }
+                if resume {
+                    args.push("--resume");
+                }
+                if force {
+                    args.push("--force");
+                }
+                if trust_changelog {
+                    args.push("--trust-changelog");
+                }
+                if verify {
+                    args.push("--verify");
+                }
+                if let Some(path) = &report {
+                    args.extend(["--report", path.as_str()]);
+                }
+                if by_size {
+                    args.push("--by-size");
+                }
+                if let Some(size) = &max_size {
+                    args.extend(["--max-size", size.as_str()]);
+                }
+                let only = only.map(|n| n.to_string());
+                if let Some(n) = &only {
+                    args.extend(["--only", n.as_str()]);
+                }
+                self.cmd_update(&args).await?;
+            }
+            Commands::List {
+                verbose,
+                collections,
+                format,
+                output,
+                appid,
+            } => {
+                let mut args = Vec::new();
+                if let Some(appid) = &appid {
+                    args.extend(["--appid", appid.as_str()]);
+                }
+                if verbose {
+                    args.push("--verbose");
+                }
+                if collections {
+                    args.push("--collections");
+                }
+                if let Some(format) = &format {
+                    args.extend(["--format", format.as_str()]);
+                }
+                if let Some(output) = &output {
+                    args.extend(["--output", output.as_str()]);
+                }
+                self.cmd_list(&args).await?;
+            }
+            Commands::Remove {
+                workshop_id,
+                cascade,
+                keep_orphans,
+                force,
+            } => {
+                let mut args = vec![workshop_id.as_str()];
+                if force {
+                    args.push("--force");
+                }
+                if cascade {
+                    args.push("--cascade");
+                }
+                if keep_orphans {
+                    args.push("--keep-orphans");
+                }
+                self.cmd_remove(&args).await?;
+            }
+            Commands::Info {
+                recalculate,
+                json,
+                section,
+            } => {
+                let mut args = Vec::new();
+                if recalculate {
+                    args.push("--recalculate");
+                }
+                if json {
+                    args.push("--json");
+                }
+                for section in &section {
+                    args.extend(["--section", section.as_str()]);
+                }
+                self.cmd_info(&args).await?;
+            }
+            Commands::Doctor { no_steamcmd } => {
+                let args = if no_steamcmd { vec!["--no-steamcmd"] } else { Vec::new() };
+                self.cmd_doctor(&args).await?;
+            }
+            Commands::Import {
+                path,
+                download,
+                steam_user,
+                subscriptions,
+                favorites,
+            } => {
+                let mut args = Vec::new();
+                if let Some(path) = &path {
+                    args.push(path.as_str());
+                }
+                if download {
+                    args.push("--download");
+                }
+                if let Some(id) = &steam_user {
+                    args.extend(["--steam-user", id.as_str()]);
+                }
+                if subscriptions {
+                    args.push("--subscriptions");
+                }
+                if favorites {
+                    args.push("--favorites");
+                }
+                self.cmd_import(&args).await?;
+            }
+            Commands::Export { path, format } => {
+                let mut args = vec!["--format", format.as_str()];
+                if let Some(path) = &path {
+                    args.push(path.as_str());
+                }
+                self.cmd_export(&args).await?;
+            }
+            Commands::Open { workshop_id, copy } => {
+                let mut args = vec![workshop_id.as_str()];
+                if copy {
+                    args.push("--copy");
+                }
+                self.cmd_open(&args).await?;
+            }
+            Commands::Order { args } => {
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                self.cmd_order(&args).await?;
+            }
+            Commands::History {
+                filter,
+                limit,
+                verbose,
+            } => {
+                let limit = limit.to_string();
+                let mut args = vec!["--limit", limit.as_str()];
+                if verbose {
+                    args.push("--verbose");
+                }
+                if let Some(filter) = &filter {
+                    args.push(filter.as_str());
+                }
+                self.cmd_history(&args).await?;
+            }
+            Commands::Undo => self.cmd_undo().await?,
+            Commands::Archive { workshop_id } => self.cmd_archive(&[&workshop_id]).await?,
+            Commands::Unarchive { workshop_id } => self.cmd_unarchive(&[&workshop_id]).await?,
+            Commands::Follow { args } => {
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                self.cmd_follow(&args).await?;
+            }
+            Commands::Review => self.cmd_review().await?,
+            Commands::Approve { ids } => {
+                let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+                self.cmd_approve(&ids).await?;
+            }
+            Commands::Reject { ids } => {
+                let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+                self.cmd_reject(&ids).await?;
+            }
+            Commands::Search { text, limit } => {
+                let limit = limit.to_string();
+                let mut args: Vec<&str> = text.iter().map(String::as_str).collect();
+                args.extend(["--limit", limit.as_str()]);
+                self.cmd_search(&args).await?;
+            }
+            Commands::Verify { workshop_id } => {
+                let args: Vec<&str> = workshop_id.as_deref().into_iter().collect();
+                self.cmd_verify(&args).await?;
+            }
+            Commands::Status {
+                workshop_id,
+                offline,
+            } => {
+                let mut args = vec![workshop_id.as_str()];
+                if offline {
+                    args.push("--offline");
+                }
+                self.cmd_status(&args).await?;
+            }
+            Commands::Diff => {
+                self.cmd_diff().await?;
+            }
+            Commands::Report { args } => {
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                self.cmd_report(&args).await?;
+            }
+            Commands::Prune {
+                unavailable,
+                archive,
+                keep_files,
+                grace,
+            } => {
+                let grace = grace.map(|days| days.to_string());
+                let mut args = Vec::new();
+                if unavailable {
+                    args.push("--unavailable");
+                }
+                if archive {
+                    args.push("--archive");
+                }
+                if keep_files {
+                    args.push("--keep-files");
+                }
+                if let Some(days) = &grace {
+                    args.extend(["--grace", days.as_str()]);
+                }
+                self.cmd_prune(&args).await?;
+            }
+            Commands::Dedupe { merge } => {
+                let args = if merge { vec!["--merge"] } else { Vec::new() };
+                self.cmd_dedupe(&args).await?;
+            }
+            Commands::Daemon { interval, systemd } => {
+                let interval = interval.to_string();
+                let mut args = vec!["--interval", interval.as_str()];
+                if systemd {
+                    args.push("--systemd");
+                }
+                self.cmd_daemon(&args).await?;
+            }
+            Commands::MoveOutput { path } => {
+                self.cmd_move_output(&[path.as_str()]).await?;
+            }
+            Commands::Stats => self.cmd_stats().await,
+            Commands::WhereUsed { query } => {
+                self.cmd_where_used(&[query.as_str()]).await?;
+            }
+            Commands::Deploy { targets, full, now } => {
+                let mut args: Vec<&str> = targets.iter().map(String::as_str).collect();
+                if full {
+                    args.push("--full");
+                }
+                if now {
+                    args.push("--now");
+                }
+                self.cmd_deploy(&args).await?;
+            }
+            Commands::Alias(args) => {
+                self.process_command(&args.join(" ")).await?;
+            }
+        }
+
+        Ok(())
+    }
+
     pub async fn run(&mut self) -> Result<()> {
         println!(
             r#"Steam Workshop Manager
@@ -973,14 +8277,29 @@ Type 'help' for available commands.
 "#
         );
 
-        let mut rl = Editor::<()>::new().context("Failed to create readline editor")?;
-        let _ = rl.load_history(".history");
+        const HISTORY_SIZE: usize = 10_000;
+        // Ctrl+R searches the history backwards
+        let config = rustyline::Config::builder()
+            .max_history_size(HISTORY_SIZE)
+            .history_ignore_dups(true)
+            .history_ignore_space(true)
+            .build();
+        let mut rl = Editor::<()>::with_config(config).context("Failed to create readline editor")?;
+        // Older versions kept the history in the working directory
+        if rl.load_history(&self.paths.history_file).is_err() {
+            let _ = rl.load_history(".history");
+        }
 
         loop {
             match rl.readline("> ") {
                 Ok(line) => {
                     rl.add_history_entry(&line);
-                    if !self.process_command(&line).await? {
+                    let result = self.process_command(&line).await;
+                    if !line.trim().is_empty() {
+                        let write_status = self.changes_state(&line);
+                        self.record_run(&line, result.as_ref().err(), write_status).await;
+                    }
+                    if !result? {
                         break;
                     }
                 }
@@ -988,53 +8307,342 @@ Type 'help' for available commands.
                     break;
                 }
                 Err(e) => {
-                    eprintln!("Readline error: {}", e);
+                    error!("failed to read input: {}", e);
                     break;
                 }
             }
         }
 
-        let _ = rl.save_history(".history");
+        if let Err(e) = rl.save_history(&self.paths.history_file) {
+            warn!("failed to save command history: {}", e);
+        }
         println!("Goodbye!");
         Ok(())
     }
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
+async fn main() {
+    logging::init();
+    if let Err(e) = run().await {
+        error!("{:?}", e);
+        std::process::exit(error::kind_of(&e).exit_code());
+    }
+}
 
-    let mut manager = WorkshopManager::new()
+async fn run() -> Result<()> {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    let exe_dir = || -> Result<PathBuf> {
+        Ok(std::env::current_exe()
+            .context("Failed to get executable path")?
+            .parent()
+            .context("Couldn't fetch parent directory for executable")?
+            .to_path_buf())
+    };
+    let base_dir = match &cli.chdir {
+        Some(dir) => {
+            std::env::set_current_dir(dir).with_context(|| {
+                NecoError::Config(format!("Failed to change directory to {}", dir.display()))
+            })?;
+            std::env::current_dir()?
+        }
+        None if cli.portable => {
+            let exe_dir = exe_dir()?;
+            let dir = exe_dir.join(PORTABLE_DIR);
+            // Starting fresh next to an existing install would leave its
+            // state and downloads behind, moving them is up to the user
+            if !dir.join("config.toml").exists() && exe_dir.join("config.toml").is_file() {
+                return Err(NecoError::Conflict(format!(
+                    "{} already has an install. To make it portable, move its config.toml and state files into {} \
+                     (use move-output with an absolute path first if output_dir is relative), or run without --portable",
+                    exe_dir.display(),
+                    dir.display()
+                ))
+                .into());
+            }
+            std::fs::create_dir_all(&dir).with_context(|| {
+                NecoError::Fs(format!("Failed to create {}", dir.display()))
+            })?;
+            std::env::set_current_dir(&dir)?;
+            platform::set_portable(&dir);
+            dir
+        }
+        None => exe_dir()?,
+    };
+
+    let mut manager = WorkshopManager::new(&base_dir.clean())
         .await
         .context("Failed to initialize workshop manager")?;
+    manager.assume_yes = cli.yes;
+
+    let command_line = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+
+    let (result, name) = match cli.command {
+        Some(command) => (
+            manager.run_cli_command(command).await,
+            matches.subcommand_name().unwrap_or_default().to_string(),
+        ),
+        None if !std::io::stdin().is_terminal() => {
+            let default_command = std::env::var("NECODL_DEFAULT_COMMAND")
+                .ok()
+                .or_else(|| manager.config.default_command.clone());
+
+            match default_command {
+                Some(command) => (manager.process_command(&command).await.map(|_| ()), command),
+                None => {
+                    manager.show_help();
+                    return Ok(());
+                }
+            }
+        }
+        None => {
+            return manager.run().await; // interactive mode
+        }
+    };
 
-    match cli.command {
-        Some(Commands::Download { workshop_id, force }) => {
-            manager.download_generic(&workshop_id, force).await?;
+    let write_status = manager.changes_state(&name);
+    manager.record_run(&command_line, result.as_ref().err(), write_status).await;
+    result
+}
+
+/// Whether a generated output has an entry for the map: a line of its own in
+/// a list, or the quoted key starting a line in KeyValues
+fn output_lists_map(content: &str, map_name: &str) -> bool {
+    content.lines().map(str::trim).any(|line| {
+        line == map_name || line.strip_prefix('"').and_then(|rest| rest.split('"').next()) == Some(map_name)
+    })
+}
+
+/// Lowercased map names listed in a mapcycle.txt-style file: the first
+/// token of each line, with `//` comments and a `.bsp` suffix dropped
+fn map_cycle_entries(content: &str) -> impl Iterator<Item = String> + '_ {
+    content.lines().filter_map(|line| {
+        let line = line.split("//").next()?.trim();
+        let token = line.split_whitespace().next()?.trim_matches('"').to_lowercase();
+        let name = token.strip_suffix(".bsp").unwrap_or(&token);
+        Some(name.to_string()).filter(|n| !n.is_empty())
+    })
+}
+
+/// Splits an item's files into maps with their companion files, and everything
+/// else. Companions share the map's file stem or prefix (`<map>.nav`,
+/// `<map>_particles.txt`, `overviews/<map>.txt`, ...).
+fn group_map_files(files: &[FileInfo]) -> (Vec<MapFiles<'_>>, Vec<&FileInfo>) {
+    let mut maps: Vec<MapFiles> = files
+        .iter()
+        .filter(|f| f.path.to_lowercase().ends_with(".bsp"))
+        .filter_map(|f| {
+            let name = Path::new(&f.path).file_stem()?.to_string_lossy().to_string();
+            Some(MapFiles {
+                name,
+                bsp: f,
+                companions: Vec::new(),
+            })
+        })
+        .collect();
+
+    // Longest names first so `nmo_foo_v2` wins over `nmo_foo` for its own files
+    maps.sort_by_key(|m| std::cmp::Reverse(m.name.len()));
+
+    let mut others = Vec::new();
+
+    for file in files {
+        if maps.iter().any(|m| std::ptr::eq(m.bsp, file)) {
+            continue;
         }
-        Some(Commands::Update { force }) => {
-            manager
-                .cmd_update(&if force { vec!["--force"] } else { vec![] })
-                .await?;
+
+        let stem = Path::new(&file.path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let owner = maps.iter_mut().find(|m| {
+            let name = m.name.to_lowercase();
+            stem == name || stem.starts_with(&format!("{}_", name))
+        });
+
+        match owner {
+            Some(map) => map.companions.push(file),
+            None => others.push(file),
+        }
+    }
+
+    maps.sort_by(|a, b| a.name.cmp(&b.name));
+    (maps, others)
+}
+
+/// Hex MD5 of a file, read in chunks so large maps aren't loaded whole
+async fn md5_file(path: &Path) -> Result<String> {
+    const BUFFER_SIZE: usize = 64 * 1024;
+    let mut file = fs::File::open(path)
+        .await
+        .with_context(|| NecoError::Fs(format!("Failed to open file: {}", path.display())))?;
+
+    let mut context = md5::Context::new();
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+
+    loop {
+        let bytes_read = file.read(&mut buffer).await?;
+        if bytes_read == 0 {
+            break;
+        }
+        context.consume(&buffer[..bytes_read]);
+    }
+
+    Ok(format!("{:x}", context.compute()))
+}
+
+/// Renames `src` to `dest`, falling back to copy and delete across filesystems
+async fn move_file(src: &Path, dest: &Path) -> Result<()> {
+    let (src, dest) = (platform::long_path(src), platform::long_path(dest));
+    let (src, dest) = (src.as_path(), dest.as_path());
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    if fs::rename(src, dest).await.is_err() {
+        copy_and_remove(src, dest).await?;
+    }
+    Ok(())
+}
+
+/// move_file's fallback where a rename isn't possible
+async fn copy_and_remove(src: &Path, dest: &Path) -> Result<()> {
+    fs::copy(src, dest)
+        .await
+        .with_context(|| NecoError::Fs(format!("Failed to move {} to {}", src.display(), dest.display())))?;
+    fs::remove_file(src).await?;
+    Ok(())
+}
+
+/// platform::file_id of the file at `path`, None if it can't be read
+async fn file_id(path: &Path) -> Option<(u64, u64)> {
+    fs::metadata(path).await.ok().as_ref().and_then(platform::file_id)
+}
+
+/// Sets a top-level `key = "value"` line of a TOML document, leaving the
+/// rest of the file, comments included, untouched. A key that isn't set is
+/// added before the first table.
+fn set_toml_value(content: &str, key: &str, value: &str) -> String {
+    let entry = format!("{} = {}", key, toml::Value::String(value.to_string()));
+    let mut found = false;
+    let mut in_table = false;
+    let mut lines = Vec::new();
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+        if !found && !in_table && trimmed.starts_with('[') {
+            lines.push(entry.clone());
+            found = true;
+        }
+        in_table |= trimmed.starts_with('[');
+        if !found && !in_table && trimmed.split_once('=').is_some_and(|(k, _)| k.trim() == key) {
+            lines.push(entry.clone());
+            found = true;
+        } else {
+            lines.push(line.to_string());
         }
-        Some(Commands::List { verbose }) => {
-            manager.cmd_list(verbose).await?;
+    }
+    if !found {
+        lines.push(entry);
+    }
+
+    let mut updated = lines.join("\n");
+    if content.ends_with('\n') || !found {
+        updated.push('\n');
+    }
+    updated
+}
+
+/// Writes `content` only if it differs from what's on disk, so file watchers and
+/// configuration management don't see spurious changes. Returns whether the
+/// file was written.
+async fn write_if_changed(path: &Path, content: impl AsRef<[u8]>) -> Result<bool> {
+    let content = content.as_ref();
+    if let Ok(existing) = fs::read(path).await
+        && existing == content
+    {
+        return Ok(false);
+    }
+
+    fs::write(path, content).await?;
+    Ok(true)
+}
+
+/// Map entries of a workshop_maps.txt: `"map" "id"`, or `"map" { "id" "..." }`
+/// when attributes are written
+fn parse_workshop_maps(content: &str, root_key: &str) -> Vec<SubscriptionEntry> {
+    let Some(acf::Value::Obj(maps)) = acf::parse(content).and_then(|root| match root {
+        acf::Value::Obj(mut entries) => entries
+            .iter()
+            .position(|(key, _)| key.eq_ignore_ascii_case(root_key))
+            .map(|i| entries.swap_remove(i).1),
+        acf::Value::Str(_) => None,
+    }) else {
+        warn!("the map list is not valid KeyValues, reading it line by line");
+        return parse_workshop_map_lines(content, root_key);
+    };
+
+    maps.into_iter()
+        .filter_map(|(map_name, value)| {
+            let id = match &value {
+                acf::Value::Str(id) => id.clone(),
+                acf::Value::Obj(_) => value.get("id")?.as_str()?.to_string(),
+            };
+            Some(SubscriptionEntry {
+                id,
+                title: map_name,
+                collection_ids: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+/// Lenient reader for hand-edited map lists the KeyValues parser rejects:
+/// every `"map" "id"` line after the root key, up to the first `}`
+fn parse_workshop_map_lines(content: &str, root_key: &str) -> Vec<SubscriptionEntry> {
+    let mut entries = Vec::new();
+    let mut in_workshop_maps = false;
+
+    for line in content.lines() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with("//") {
+            continue;
         }
-        Some(Commands::Remove { workshop_id }) => {
-            manager.cmd_remove(&workshop_id).await?;
+
+        if line.trim_matches('"').eq_ignore_ascii_case(root_key) {
+            in_workshop_maps = true;
+            continue;
         }
-        Some(Commands::Info) => {
-            manager.cmd_info().await?;
+
+        if !in_workshop_maps {
+            continue;
         }
-        Some(Commands::Import { path }) => {
-            manager.cmd_import(&path).await?;
+
+        if line == "}" {
+            break;
         }
-        None => {
-            manager.run().await?; // interactive mode
+
+        let parts: Vec<&str> = line.split_whitespace().map(|p| p.trim_matches('"')).collect();
+        if let [map_name, id, ..] = parts[..]
+            && line.starts_with('"')
+            && id.parse::<u64>().is_ok()
+        {
+            entries.push(SubscriptionEntry {
+                id: id.to_string(),
+                title: map_name.to_string(),
+                collection_ids: Vec::new(),
+            });
         }
     }
 
-    Ok(())
+    entries
+}
+
+/// Escapes a value for a quoted KeyValues string
+fn escape_kv(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
 }
 
 fn format_file_size(bytes: u64) -> String {
@@ -1049,3 +8657,498 @@ fn format_file_size(bytes: u64) -> String {
 
     format!("{:.2} {}", size, UNITS[unit_index])
 }
+
+/// Parses a 1-based selection like `1,3-5` into the picked positions
+fn parse_selection(input: &str, count: usize) -> Option<BTreeSet<usize>> {
+    let mut picked = BTreeSet::new();
+    for part in input.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (start, end): (usize, usize) = match part.split_once('-') {
+            Some((start, end)) => (start.trim().parse().ok()?, end.trim().parse().ok()?),
+            None => {
+                let n = part.parse().ok()?;
+                (n, n)
+            }
+        };
+        if start == 0 || start > end || end > count {
+            return None;
+        }
+        picked.extend(start..=end);
+    }
+    (!picked.is_empty()).then_some(picked)
+}
+
+/// Parses sizes like `500MB`, `1.5 GB` or a plain byte count
+fn parse_file_size(value: &str) -> Option<u64> {
+    const UNITS: [(&str, u64); 5] = [
+        ("TB", 1 << 40),
+        ("GB", 1 << 30),
+        ("MB", 1 << 20),
+        ("KB", 1 << 10),
+        ("B", 1),
+    ];
+
+    let value = value.trim().to_uppercase();
+    let (number, multiplier) = UNITS
+        .iter()
+        .find_map(|(unit, mult)| value.strip_suffix(unit).map(|n| (n.trim(), *mult)))
+        .unwrap_or((value.as_str(), 1));
+
+    let number: f64 = number.parse().ok()?;
+    (number >= 0.0).then_some((number * multiplier as f64) as u64)
+}
+
+/// Workshop ID from a bare number or a Workshop URL with an `id=` parameter
+fn parse_workshop_id(input: &str) -> Option<String> {
+    let input = input.trim();
+    let candidate = if input.contains("://") || input.starts_with("steamcommunity.com") {
+        let query = input.split_once('?').map(|(_, query)| query).unwrap_or_default();
+        let query = query.split('#').next().unwrap_or_default();
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == "id")
+            .map(|(_, value)| value.trim_end_matches('/'))?
+    } else {
+        input
+    };
+    candidate
+        .parse::<u64>()
+        .ok()
+        .filter(|&id| id > 0)
+        .map(|id| id.to_string())
+}
+
+fn workshop_url(workshop_id: &str) -> String {
+    format!(
+        "https://steamcommunity.com/sharedfiles/filedetails/?id={}",
+        workshop_id
+    )
+}
+
+async fn open_in_browser(url: &str) -> Result<()> {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", "start", ""]);
+        cmd
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+
+    let status = command
+        .arg(url)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .context("Failed to launch browser")?;
+
+    if !status.success() {
+        anyhow::bail!("Browser launcher exited with {}", status);
+    }
+    Ok(())
+}
+
+async fn copy_to_clipboard(text: &str) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let candidates: &[(&str, &[&str])] = if cfg!(target_os = "windows") {
+        &[("clip", &[])]
+    } else if cfg!(target_os = "macos") {
+        &[("pbcopy", &[])]
+    } else {
+        &[
+            ("wl-copy", &[]),
+            ("xclip", &["-selection", "clipboard"]),
+            ("xsel", &["--clipboard", "--input"]),
+        ]
+    };
+
+    for (program, args) in candidates {
+        let Ok(mut child) = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            continue;
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes()).await?;
+        }
+
+        if child.wait().await?.success() {
+            return Ok(());
+        }
+    }
+
+    let tried: Vec<&str> = candidates.iter().map(|(program, _)| *program).collect();
+    anyhow::bail!("No clipboard utility found (tried {})", tried.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backend::fake::{FakeDownloader, FakeInfo};
+    use testutil::TempDir;
+
+    /// A NecoDL directory with its own config.toml and an empty SteamCMD
+    struct TestDir(TempDir);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let dir = TempDir::new(name);
+            std::fs::create_dir_all(dir.join("steamcmd")).unwrap();
+            std::fs::write(dir.join("steamcmd").join(platform::STEAMCMD_NAMES[0]), "").unwrap();
+            std::fs::write(
+                dir.join("config.toml"),
+                format!(
+                    "steam_cmd = \"steamcmd/{}\"\noutput_dir = \"out\"\nappid = \"4000\"\nwhitelist = [\"maps/*.bsp\"]\n",
+                    platform::STEAMCMD_NAMES[0]
+                ),
+            )
+            .unwrap();
+            TestDir(dir)
+        }
+
+        /// Adds lines to config.toml, before the manager is created
+        fn config(&self, extra: &str) {
+            let path = self.0.join("config.toml");
+            let content = std::fs::read_to_string(&path).unwrap();
+            std::fs::write(path, content + extra).unwrap();
+        }
+
+        async fn manager(&self, info: FakeInfo, downloader: FakeDownloader) -> WorkshopManager {
+            WorkshopManager::with_backends(&self.0, |_, _, _| backend::Backends {
+                info: Box::new(info),
+                downloader: Arc::new(downloader),
+            })
+            .await
+            .unwrap()
+        }
+    }
+
+
+    #[tokio::test]
+    async fn moves_content_nested_beyond_max_path() {
+        let dir = TempDir::new("long-path");
+        let mut nested = dir.join("content");
+        while nested.as_os_str().len() < 300 {
+            nested.push("materials_models_props");
+        }
+        std::fs::create_dir_all(platform::long_path(&nested)).unwrap();
+        std::fs::write(platform::long_path(&nested.join("prop.vmt")), "vmt").unwrap();
+        std::fs::write(platform::long_path(&nested.join("prop.vtf")), "vtf").unwrap();
+
+        let target = dir.join("output").join("prop.vmt");
+        move_file(&nested.join("prop.vmt"), &target).await.unwrap();
+        assert_eq!(std::fs::read_to_string(platform::long_path(&target)).unwrap(), "vmt");
+        assert!(!platform::long_path(&nested.join("prop.vmt")).exists());
+
+        // The fallback used across filesystems
+        let target = dir.join("output").join("prop.vtf");
+        copy_and_remove(&platform::long_path(&nested.join("prop.vtf")), &platform::long_path(&target))
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "vtf");
+        assert!(!platform::long_path(&nested.join("prop.vtf")).exists());
+    }
+
+    #[test]
+    fn map_cycle_entries_ignore_comments_and_bsp_suffix() {
+        let content = "// rotation\nde_dust2\n\"CS_Office.BSP\" // hostages\n\n  de_nuke.bsp extra\n";
+        let entries: Vec<String> = map_cycle_entries(content).collect();
+        assert_eq!(entries, vec!["de_dust2", "cs_office", "de_nuke"]);
+    }
+
+    #[test]
+    fn matches_whole_output_entries() {
+        let keyvalues = "\"WorkshopMaps\"\n{\n\t\"de_dust2_night\"\t\"100\"\n\t\"cs_office\"\n\t{\n\t\t\"id\"\t\"200\"\n\t}\n}\n";
+        assert!(output_lists_map(keyvalues, "cs_office"));
+        assert!(output_lists_map(keyvalues, "de_dust2_night"));
+        assert!(!output_lists_map(keyvalues, "de_dust2"));
+        assert!(!output_lists_map(keyvalues, "100"));
+        assert!(output_lists_map("de_dust2_night\nde_nuke\n", "de_nuke"));
+        assert!(!output_lists_map("de_dust2_night\n", "de_dust2"));
+    }
+
+    #[test]
+    fn reads_malformed_map_list_line_by_line() {
+        // Missing closing brace
+        let content = "\"WorkshopMaps\"\n{\n\t\"de_test\" \"100\"\n\t\"cs_test\" \"200\"\n";
+        let entries = parse_workshop_maps(content, "WorkshopMaps");
+        let ids: Vec<(&str, &str)> = entries.iter().map(|e| (e.title.as_str(), e.id.as_str())).collect();
+        assert_eq!(ids, vec![("de_test", "100"), ("cs_test", "200")]);
+    }
+
+    #[test]
+    fn parses_workshop_ids_from_urls() {
+        let id = |input| parse_workshop_id(input);
+        assert_eq!(id("123456"), Some("123456".to_string()));
+        assert_eq!(
+            id("https://steamcommunity.com/sharedfiles/filedetails/?id=123456&searchtext="),
+            Some("123456".to_string())
+        );
+        assert_eq!(
+            id("https://steamcommunity.com/sharedfiles/filedetails/?appid=4000&id=123456#comments"),
+            Some("123456".to_string())
+        );
+        assert_eq!(id("https://steamcommunity.com/workshop/browse/?appid=4000"), None);
+        assert_eq!(id("steamcommunity.com/sharedfiles/filedetails/?id=0"), None);
+        assert_eq!(id("de_dust2"), None);
+    }
+
+    #[test]
+    fn sets_top_level_toml_values() {
+        let config = "appid = \"4000\"\noutput_dir = \"old\" # downloads\n\n[http]\noutput_dir = \"x\"\n";
+        assert_eq!(
+            set_toml_value(config, "output_dir", "new"),
+            "appid = \"4000\"\noutput_dir = \"new\"\n\n[http]\noutput_dir = \"x\"\n"
+        );
+        assert_eq!(
+            set_toml_value("appid = \"4000\"\n[http]\n", "output_dir", "new"),
+            "appid = \"4000\"\noutput_dir = \"new\"\n[http]\n"
+        );
+        assert_eq!(set_toml_value("appid = \"4000\"", "output_dir", "new"), "appid = \"4000\"\noutput_dir = \"new\"\n");
+    }
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn installs_whitelisted_files() {
+        let dir = TestDir::new("install");
+        let info = FakeInfo::default().item("100", "Test Map", "1");
+        let downloader = FakeDownloader::default()
+            .files("100", &[("maps/de_test.bsp", "bsp"), ("readme.txt", "hello")]);
+        let mut manager = dir.manager(info, downloader).await;
+
+        let results = manager.download_many(&ids(&["100"]), false).await.unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].status, report::ItemStatus::Updated);
+        assert_eq!(results[0].files, vec!["maps/de_test.bsp".to_string()]);
+        assert_eq!(std::fs::read_to_string(dir.0.join("out/maps/de_test.bsp")).unwrap(), "bsp");
+        assert!(!dir.0.join("out/readme.txt").exists());
+        assert_eq!(manager.metadata["100"].changelog_id, "1");
+    }
+
+    #[tokio::test]
+    async fn where_used_positions_follow_output_filters() {
+        let dir = TestDir::new("where-used");
+        dir.config("[[outputs]]\npath = \"other.txt\"\nformat = \"list\"\nappid = \"5000\"\n");
+        let info = FakeInfo::default().item("100", "First", "1").item("200", "Second", "1");
+        let downloader = FakeDownloader::default()
+            .files("100", &[("maps/de_alpha.bsp", "a"), ("maps/de_beta.bsp", "b")])
+            .files("200", &[("maps/de_gamma.bsp", "c")]);
+        let mut manager = dir.manager(info, downloader).await;
+        manager.download_many(&ids(&["100", "200"]), false).await.unwrap();
+        manager.metadata.get_mut("200").unwrap().appid = Some("5000".to_string());
+
+        let maps = manager.ordered_maps();
+        let [all, other] = &manager.paths.outputs[..] else {
+            panic!("expected the map list and one extra output");
+        };
+        assert_eq!(manager.output_position(all, &maps, "200", "de_gamma"), Some(1));
+        assert_eq!(manager.output_position(other, &maps, "200", "de_gamma"), Some(0));
+        assert_eq!(manager.output_position(other, &maps, "100", "de_alpha"), None);
+        // Only one map of an item is listed
+        let listed = manager.extract_map_name(&manager.metadata["100"]).unwrap();
+        let unlisted = if listed == "de_alpha" { "de_beta" } else { "de_alpha" };
+        assert_eq!(manager.output_position(all, &maps, "100", &listed), Some(0));
+        assert_eq!(manager.output_position(all, &maps, "100", unlisted), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn dedupe_counts_linked_content_once() {
+        let dir = TestDir::new("dedupe");
+        dir.config("dedupe = true\n");
+        let info = FakeInfo::default().item("100", "First", "1").item("200", "Second", "1");
+        let downloader = FakeDownloader::default()
+            .files("100", &[("maps/de_first.bsp", "shared")])
+            .files("200", &[("maps/de_second.bsp", "shared")]);
+        let mut manager = dir.manager(info, downloader).await;
+        manager.download_many(&ids(&["100"]), false).await.unwrap();
+        manager.info_storage(true).await.unwrap();
+        let before = manager.load_size_cache().await.unwrap().bytes;
+
+        manager.download_many(&ids(&["200"]), false).await.unwrap();
+        let shared = manager.shared_content().await;
+        assert_eq!((shared.linked, shared.saved_bytes, shared.copies), (1, 6, 0));
+        let maps = manager.paths.local_files.join("maps");
+        assert_eq!(manager.calculate_directory_size(&maps, &mut HashSet::new()).await.unwrap(), 6);
+        assert_eq!(manager.load_size_cache().await.unwrap().bytes, before);
+
+        // The other link keeps the content on disk
+        manager.remove_item("100").await.unwrap();
+        assert_eq!(manager.load_size_cache().await.unwrap().bytes, before);
+        manager.remove_item("200").await.unwrap();
+        assert_eq!(manager.load_size_cache().await.unwrap().bytes, before - 6);
+    }
+
+    #[tokio::test]
+    async fn failed_download_keeps_going_and_installs_nothing() {
+        let dir = TestDir::new("failure");
+        let info = FakeInfo::default()
+            .item("100", "Private Map", "1")
+            .item("200", "Public Map", "1");
+        let downloader = FakeDownloader::default()
+            .fail("100", steamcmd::Failure::AccessDenied)
+            .files("200", &[("maps/de_public.bsp", "bsp")]);
+        let mut manager = dir.manager(info, downloader).await;
+
+        let results = manager.download_many(&ids(&["100", "missing", "200"]), false).await.unwrap();
+
+        let status: Vec<(&str, report::ItemStatus)> =
+            results.iter().map(|r| (r.id.as_str(), r.status)).collect();
+        assert_eq!(
+            status,
+            vec![
+                ("100", report::ItemStatus::Failed),
+                ("missing", report::ItemStatus::Failed),
+                ("200", report::ItemStatus::Updated),
+            ]
+        );
+        assert!(!manager.metadata.contains_key("100"));
+        assert!(dir.0.join("out/maps/de_public.bsp").exists());
+    }
+
+    #[tokio::test]
+    async fn update_replaces_previous_version() {
+        let dir = TestDir::new("update");
+        let info = FakeInfo::default().item("100", "Test Map", "1");
+        let downloader = FakeDownloader::default().files("100", &[("maps/de_old.bsp", "v1")]);
+        let mut manager = dir.manager(info, downloader).await;
+        manager.download_many(&ids(&["100"]), false).await.unwrap();
+        drop(manager);
+
+        let info = FakeInfo::default().item("100", "Test Map", "2");
+        let downloader = FakeDownloader::default().files("100", &[("maps/de_new.bsp", "v2")]);
+        let mut manager = dir.manager(info, downloader).await;
+        let results = manager.download_many(&ids(&["100"]), false).await.unwrap();
+
+        assert_eq!(results[0].status, report::ItemStatus::Updated);
+        assert_eq!(std::fs::read_to_string(dir.0.join("out/maps/de_new.bsp")).unwrap(), "v2");
+        assert!(!dir.0.join("out/maps/de_old.bsp").exists());
+        assert_eq!(manager.metadata["100"].changelog_id, "2");
+    }
+
+    #[tokio::test]
+    async fn updates_download_ahead_in_spare_sandboxes() {
+        let dir = TestDir::new("sandboxes");
+        dir.config("steamcmd_instances = 2\n");
+        let ids = ids(&["100", "200", "300"]);
+        let v1 = FakeDownloader::default()
+            .files("100", &[("maps/de_a.bsp", "v1")])
+            .files("200", &[("maps/de_b.bsp", "v1")])
+            .files("300", &[("maps/de_c.bsp", "v1")]);
+        let info = FakeInfo::default().item("100", "A", "1").item("200", "B", "1").item("300", "C", "1");
+        let mut manager = dir.manager(info, v1).await;
+        manager.download_many(&ids, false).await.unwrap();
+        drop(manager);
+
+        let v2 = FakeDownloader::default()
+            .files("100", &[("maps/de_a.bsp", "v2")])
+            .files("200", &[("maps/de_b.bsp", "v2")])
+            .files("300", &[("maps/de_c.bsp", "v2")]);
+        let info = FakeInfo::default().item("100", "A", "2").item("200", "B", "2").item("300", "C", "2");
+        let mut manager = dir.manager(info, v2).await;
+        let mut items = Vec::new();
+        for id in &ids {
+            match manager.backends.info.fetch(id).await.unwrap() {
+                ParseResult::Item(item) => items.push(item),
+                _ => unreachable!(),
+            }
+        }
+        let run = resume::RunState::new(None, false, ids.clone());
+        manager.update_items(items, run, false).await.unwrap();
+
+        for map in ["de_a", "de_b", "de_c"] {
+            let path = dir.0.join("out/maps").join(format!("{}.bsp", map));
+            assert_eq!(std::fs::read_to_string(path).unwrap(), "v2");
+        }
+        assert!(manager.ahead.is_empty());
+        for sandbox in 0..2 {
+            let workshop_dir = manager.paths.steamcmd_workshop_dir(sandbox);
+            assert!(workshop_dir.exists(), "sandbox {} was never used", sandbox);
+            for id in &ids {
+                assert!(!steamcmd::content_path(&workshop_dir, "4000", id).exists());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn copies_downloads_from_a_distro_steam_root() {
+        let dir = TestDir::new("distro-root");
+        let steam_root = dir.0.join("home/.local/share/Steam");
+        let info = FakeInfo::default().item("100", "Test Map", "1");
+        let downloader = FakeDownloader::default()
+            .files("100", &[("maps/de_test.bsp", "bsp")])
+            .ignore_install_dir(&steam_root);
+        let mut manager = dir.manager(info, downloader).await;
+        manager.paths.distro_steam_roots = vec![steam_root.clone()];
+
+        let results = manager.download_many(&ids(&["100"]), false).await.unwrap();
+
+        assert_eq!(results[0].status, report::ItemStatus::Updated);
+        assert_eq!(std::fs::read_to_string(dir.0.join("out/maps/de_test.bsp")).unwrap(), "bsp");
+        // The user's Steam library is left as it was
+        let content = steamcmd::content_path(&steam_root.join("steamapps/workshop"), "4000", "100");
+        assert!(content.join("maps/de_test.bsp").is_file());
+    }
+
+    #[tokio::test]
+    async fn restores_deleted_files_of_current_version() {
+        let dir = TestDir::new("repair");
+        let files = [("maps/de_test.bsp", "bsp"), ("maps/de_test_v2.bsp", "bsp2")];
+        let info = FakeInfo::default().item("100", "Test Map", "1");
+        let mut manager = dir.manager(info, FakeDownloader::default().files("100", &files)).await;
+        manager.download_many(&ids(&["100"]), false).await.unwrap();
+        std::fs::remove_file(dir.0.join("out/maps/de_test.bsp")).unwrap();
+
+        let results = manager.download_many(&ids(&["100"]), false).await.unwrap();
+
+        assert_ne!(results[0].status, report::ItemStatus::Failed);
+        assert_eq!(std::fs::read_to_string(dir.0.join("out/maps/de_test.bsp")).unwrap(), "bsp");
+    }
+
+    #[tokio::test]
+    async fn failed_repair_falls_back_to_full_download() {
+        let dir = TestDir::new("repair-fallback");
+        let info = FakeInfo::default().item("100", "Test Map", "1");
+        let downloader =
+            FakeDownloader::default().files("100", &[("maps/de_test.bsp", "old"), ("maps/a.bsp", "a")]);
+        let mut manager = dir.manager(info, downloader).await;
+        manager.download_many(&ids(&["100"]), false).await.unwrap();
+        drop(manager);
+        std::fs::remove_file(dir.0.join("out/maps/de_test.bsp")).unwrap();
+
+        // Same version, but the fresh copy no longer matches the recorded hash
+        let info = FakeInfo::default().item("100", "Test Map", "1");
+        let downloader =
+            FakeDownloader::default().files("100", &[("maps/de_test.bsp", "new"), ("maps/a.bsp", "a")]);
+        let mut manager = dir.manager(info, downloader).await;
+        let results = manager.download_many(&ids(&["100"]), false).await.unwrap();
+
+        assert_ne!(results[0].status, report::ItemStatus::Failed);
+        assert_eq!(std::fs::read_to_string(dir.0.join("out/maps/de_test.bsp")).unwrap(), "new");
+    }
+
+    #[tokio::test]
+    async fn unchanged_item_is_up_to_date() {
+        let dir = TestDir::new("up-to-date");
+        let info = FakeInfo::default().item("100", "Test Map", "1");
+        let downloader = FakeDownloader::default().files("100", &[("maps/de_test.bsp", "bsp")]);
+        let mut manager = dir.manager(info, downloader).await;
+        manager.download_many(&ids(&["100"]), false).await.unwrap();
+
+        let results = manager.download_many(&ids(&["100"]), false).await.unwrap();
+
+        assert_eq!(results[0].status, report::ItemStatus::UpToDate);
+    }
+}
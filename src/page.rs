@@ -0,0 +1,388 @@
+use std::collections::HashSet;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use scraper::{ElementRef, Html, Selector};
+use tracing::warn;
+
+/// Bumped whenever the strategies below change, shown in diagnostics so
+/// layout breakage reports can be matched to a parser revision.
+pub const PARSER_VERSION: u32 = 3;
+
+/// A named way of locating something on a Workshop page. The first strategy
+/// in each list matches the current Steam layout, later ones are fallbacks
+/// for older or partially changed layouts.
+struct Strategy {
+    name: &'static str,
+    selector: Lazy<Selector>,
+}
+
+macro_rules! strategy {
+    ($name:expr, $css:expr) => {
+        Strategy {
+            name: $name,
+            selector: Lazy::new(|| Selector::parse($css).unwrap()),
+        }
+    };
+}
+
+static TITLE_STRATEGIES: [Strategy; 3] = [
+    strategy!("item title", ".workshopItemTitle"),
+    strategy!("og:title", r#"meta[property="og:title"]"#),
+    strategy!("document title", "title"),
+];
+
+static CHANGELOG_STRATEGIES: [Strategy; 2] = [
+    strategy!("changelog container", ".changeLogCtn p[id]"),
+    strategy!("detail box", ".detailBox p[id]"),
+];
+
+static COLLECTION_STRATEGIES: [Strategy; 2] = [
+    strategy!("collection children", ".collectionChildren"),
+    strategy!("collection items", ".collectionItem"),
+];
+
+static COLLECTION_ITEM_SELECTOR: Lazy<Selector> =
+    Lazy::new(|| Selector::parse(r#"[id^="sharedfile_"]"#).unwrap());
+static ERROR_SELECTOR: Lazy<Selector> =
+    Lazy::new(|| Selector::parse(".error_ctn h3, #message h3").unwrap());
+static AGE_GATE_SELECTOR: Lazy<Selector> = Lazy::new(|| {
+    Selector::parse("#age_gate_btn_continue, .mature_content_notice, #agegate_box").unwrap()
+});
+static STEAM_MARKER_SELECTOR: Lazy<Selector> =
+    Lazy::new(|| Selector::parse("#global_header, .responsive_page_frame").unwrap());
+
+/// Markers of pages the fast path leaves to the full parser: Steam error
+/// messages and age gates
+const SPECIAL_PAGE_MARKERS: [&str; 5] = [
+    "error_ctn",
+    "id=\"message\"",
+    "age_gate_btn_continue",
+    "mature_content_notice",
+    "agegate_box",
+];
+
+static CHANGELOG_ID_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?s)changeLogCtn.*?<p\s+id="(\d+)""#).unwrap());
+static COLLECTION_ITEM_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"id="sharedfile_(\d+)""#).unwrap());
+
+#[derive(Debug, PartialEq)]
+pub enum ChangelogPage {
+    Entry {
+        title: String,
+        changelog_id: String,
+        changelog: String,
+    },
+    /// Valid page without a changelog, typically a collection
+    NoEntries,
+    Error(String),
+    AgeGated,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DetailsPage {
+    Collection { title: String, item_ids: Vec<String> },
+    NotCollection,
+    Error(String),
+}
+
+/// Tracks which strategies had to fall back, so callers can warn that the
+/// Steam layout may have changed before it breaks completely.
+#[derive(Default)]
+pub struct Diagnostics {
+    pub fallbacks: Vec<&'static str>,
+}
+
+impl Diagnostics {
+    pub fn warn_if_degraded(&self, workshop_id: &str) {
+        if !self.fallbacks.is_empty() {
+            warn!(
+                "parsed {} using fallback strategies ({}), the Steam page format may have changed (parser v{})",
+                workshop_id,
+                self.fallbacks.join(", "),
+                PARSER_VERSION
+            );
+        }
+    }
+}
+
+pub fn parse_changelog(html: &str, diag: &mut Diagnostics) -> Result<ChangelogPage, String> {
+    match changelog_fast_path(html) {
+        Some(page) => Ok(page),
+        None => parse_changelog_document(html, diag),
+    }
+}
+
+fn parse_changelog_document(html: &str, diag: &mut Diagnostics) -> Result<ChangelogPage, String> {
+    let doc = Html::parse_document(html);
+
+    if let Some(reason) = page_error(&doc) {
+        return Ok(ChangelogPage::Error(reason));
+    }
+    if doc.select(&AGE_GATE_SELECTOR).next().is_some() {
+        return Ok(ChangelogPage::AgeGated);
+    }
+
+    let title = find_title(&doc, diag);
+
+    if let Some(entry) = select_first(&doc, &CHANGELOG_STRATEGIES, diag)
+        && let Some(changelog_id) = entry.value().attr("id")
+    {
+        return Ok(ChangelogPage::Entry {
+            title: title.unwrap_or_else(|| "Untitled".to_string()),
+            changelog_id: changelog_id.to_string(),
+            changelog: entry_text(entry),
+        });
+    }
+
+    if let Some(captures) = CHANGELOG_ID_REGEX.captures(html) {
+        diag.fallbacks.push("changelog regex");
+        return Ok(ChangelogPage::Entry {
+            title: title.unwrap_or_else(|| "Untitled".to_string()),
+            changelog_id: captures[1].to_string(),
+            changelog: String::new(),
+        });
+    }
+
+    if title.is_none() && doc.select(&STEAM_MARKER_SELECTOR).next().is_none() {
+        return Err(format_changed("changelog"));
+    }
+
+    Ok(ChangelogPage::NoEntries)
+}
+
+pub fn parse_details(html: &str, diag: &mut Diagnostics) -> Result<DetailsPage, String> {
+    match collection_fast_path(html) {
+        Some(page) => Ok(page),
+        None => parse_details_document(html, diag),
+    }
+}
+
+fn parse_details_document(html: &str, diag: &mut Diagnostics) -> Result<DetailsPage, String> {
+    let doc = Html::parse_document(html);
+
+    if let Some(reason) = page_error(&doc) {
+        return Ok(DetailsPage::Error(reason));
+    }
+
+    let title = find_title(&doc, diag);
+
+    if select_first(&doc, &COLLECTION_STRATEGIES, diag).is_none() {
+        if title.is_none() && doc.select(&STEAM_MARKER_SELECTOR).next().is_none() {
+            return Err(format_changed("details"));
+        }
+        return Ok(DetailsPage::NotCollection);
+    }
+
+    let mut item_ids: Vec<String> = doc
+        .select(&COLLECTION_ITEM_SELECTOR)
+        .filter_map(|el| el.value().attr("id"))
+        .filter_map(|id| id.strip_prefix("sharedfile_"))
+        .map(String::from)
+        .collect();
+
+    if item_ids.is_empty() {
+        item_ids = COLLECTION_ITEM_REGEX
+            .captures_iter(html)
+            .map(|c| c[1].to_string())
+            .collect();
+        if !item_ids.is_empty() {
+            diag.fallbacks.push("collection item regex");
+        }
+    }
+    dedup_ids(&mut item_ids);
+
+    Ok(DetailsPage::Collection {
+        title: title.unwrap_or_else(|| "Untitled".to_string()),
+        item_ids,
+    })
+}
+
+/// Parsing a whole Workshop page into a DOM takes several times its size in
+/// memory, which adds up for large collections. The fast paths below only
+/// parse the few snippets they read, and return None to fall back to the
+/// full parser whenever the page isn't the usual item or collection page.
+fn changelog_fast_path(html: &str) -> Option<ChangelogPage> {
+    if SPECIAL_PAGE_MARKERS.iter().any(|marker| html.contains(marker)) {
+        return None;
+    }
+
+    let title = snippet_title(html)?;
+    let fragment = Html::parse_fragment(snippet(html, "changeLogCtn", "</p>")?);
+    let entry = fragment.select(&CHANGELOG_STRATEGIES[0].selector).next()?;
+    let changelog_id = entry.value().attr("id")?.to_string();
+
+    Some(ChangelogPage::Entry {
+        title,
+        changelog_id,
+        changelog: entry_text(entry),
+    })
+}
+
+fn collection_fast_path(html: &str) -> Option<DetailsPage> {
+    if SPECIAL_PAGE_MARKERS.iter().any(|marker| html.contains(marker))
+        || !html.contains("collectionChildren")
+    {
+        return None;
+    }
+
+    let title = snippet_title(html)?;
+    let mut item_ids: Vec<String> = COLLECTION_ITEM_REGEX
+        .captures_iter(html)
+        .map(|c| c[1].to_string())
+        .collect();
+    if item_ids.is_empty() {
+        return None;
+    }
+    dedup_ids(&mut item_ids);
+
+    Some(DetailsPage::Collection { title, item_ids })
+}
+
+/// The markup from the tag carrying `marker` up to the first `end` after it
+fn snippet<'a>(html: &'a str, marker: &str, end: &str) -> Option<&'a str> {
+    let at = html.find(marker)?;
+    let start = html[..at].rfind('<')?;
+    let stop = at + html[at..].find(end)? + end.len();
+    Some(&html[start..stop])
+}
+
+fn snippet_title(html: &str) -> Option<String> {
+    let fragment = Html::parse_fragment(snippet(html, "workshopItemTitle", "</div>")?);
+    let el = fragment.select(&TITLE_STRATEGIES[0].selector).next()?;
+    let text = el.text().collect::<String>();
+    Some(text.trim().to_string()).filter(|t| !t.is_empty())
+}
+
+/// Text of a changelog entry, one trimmed line per text node
+fn entry_text(entry: ElementRef) -> String {
+    entry
+        .text()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn find_title(doc: &Html, diag: &mut Diagnostics) -> Option<String> {
+    let el = select_first(doc, &TITLE_STRATEGIES, diag)?;
+    let text = match el.value().attr("content") {
+        Some(content) => content.to_string(),
+        None => el.text().collect::<String>(),
+    };
+    let text = text.trim().trim_start_matches("Steam Workshop::").trim();
+    Some(text.to_string()).filter(|t| !t.is_empty())
+}
+
+fn select_first<'a>(
+    doc: &'a Html,
+    strategies: &[Strategy],
+    diag: &mut Diagnostics,
+) -> Option<ElementRef<'a>> {
+    for (i, strategy) in strategies.iter().enumerate() {
+        if let Some(el) = doc.select(&strategy.selector).next() {
+            if i > 0 {
+                diag.fallbacks.push(strategy.name);
+            }
+            return Some(el);
+        }
+    }
+    None
+}
+
+/// Extracts Steam's "There was a problem accessing the item" style message
+fn page_error(doc: &Html) -> Option<String> {
+    doc.select(&ERROR_SELECTOR)
+        .next()
+        .map(|el| el.text().collect::<String>().trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+/// Drops repeated ids, keeping the collection's order
+fn dedup_ids(ids: &mut Vec<String>) {
+    let mut seen = HashSet::new();
+    ids.retain(|id| seen.insert(id.clone()));
+}
+
+pub fn format_changed(page: &str) -> String {
+    format!(
+        "Steam {} page format changed and could not be recognized by parser v{}, please update NecoDL",
+        page, PARSER_VERSION
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANGELOG: &str = include_str!("../tests/fixtures/changelog.html");
+    const COLLECTION: &str = include_str!("../tests/fixtures/collection.html");
+    const ITEM_DETAILS: &str = include_str!("../tests/fixtures/item_details.html");
+    const ERROR: &str = include_str!("../tests/fixtures/error.html");
+    const AGE_GATE: &str = include_str!("../tests/fixtures/age_gate.html");
+
+    #[test]
+    fn changelog_fast_path_agrees_with_full_parser() {
+        let fast = changelog_fast_path(CHANGELOG).expect("fast path handles a regular changelog");
+        let mut diag = Diagnostics::default();
+        let full = parse_changelog_document(CHANGELOG, &mut diag).unwrap();
+
+        assert_eq!(fast, full);
+        assert!(diag.fallbacks.is_empty());
+        let ChangelogPage::Entry { title, changelog_id, changelog } = fast else {
+            panic!("expected a changelog entry");
+        };
+        assert_eq!(title, "nmo_broadway");
+        assert_eq!(changelog_id, "5212998170912873010");
+        assert_eq!(
+            changelog,
+            "Fixed the stuck spot near the subway entrance\nLowered zombie counts on the last objective"
+        );
+    }
+
+    #[test]
+    fn collection_fast_path_agrees_with_full_parser() {
+        let fast = collection_fast_path(COLLECTION).expect("fast path handles a regular collection");
+        let mut diag = Diagnostics::default();
+        let full = parse_details_document(COLLECTION, &mut diag).unwrap();
+
+        assert_eq!(fast, full);
+        assert!(diag.fallbacks.is_empty());
+        assert_eq!(
+            fast,
+            DetailsPage::Collection {
+                title: "Objective Maps".to_string(),
+                item_ids: vec!["2914337121".to_string(), "1427394853".to_string(), "738112458".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn item_details_are_left_to_full_parser() {
+        assert!(collection_fast_path(ITEM_DETAILS).is_none());
+        let page = parse_details(ITEM_DETAILS, &mut Diagnostics::default()).unwrap();
+        assert_eq!(page, DetailsPage::NotCollection);
+    }
+
+    #[test]
+    fn error_pages_fall_through_to_full_parser() {
+        assert!(changelog_fast_path(ERROR).is_none());
+        assert!(collection_fast_path(ERROR).is_none());
+        let expected = "There was a problem accessing the item.  Please try again.".to_string();
+        assert_eq!(
+            parse_changelog(ERROR, &mut Diagnostics::default()).unwrap(),
+            ChangelogPage::Error(expected.clone())
+        );
+        assert_eq!(parse_details(ERROR, &mut Diagnostics::default()).unwrap(), DetailsPage::Error(expected));
+    }
+
+    #[test]
+    fn age_gated_pages_fall_through_to_full_parser() {
+        assert!(changelog_fast_path(AGE_GATE).is_none());
+        assert_eq!(
+            parse_changelog(AGE_GATE, &mut Diagnostics::default()).unwrap(),
+            ChangelogPage::AgeGated
+        );
+    }
+}
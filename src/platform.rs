@@ -0,0 +1,416 @@
+use once_cell::sync::OnceCell;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Directory NecoDL keeps everything in with `--portable`. It stands in for
+/// the home directory, so nothing is looked up or written outside it.
+static PORTABLE_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+pub fn set_portable(dir: &Path) {
+    let _ = PORTABLE_DIR.set(dir.to_path_buf());
+}
+
+pub fn portable_dir() -> Option<&'static Path> {
+    PORTABLE_DIR.get().map(PathBuf::as_path)
+}
+
+/// File names SteamCMD is installed under, in order of preference
+#[cfg(windows)]
+pub const STEAMCMD_NAMES: &[&str] = &["steamcmd.exe"];
+#[cfg(not(windows))]
+pub const STEAMCMD_NAMES: &[&str] = &["steamcmd.sh", "steamcmd"];
+
+/// Resolves the configured steam_cmd to an executable: accepts the SteamCMD
+/// directory itself, and on Windows a path missing its `.exe` extension.
+/// Falls back to the path as given so the caller reports it.
+pub fn resolve_steamcmd(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        for name in STEAMCMD_NAMES {
+            let candidate = path.join(name);
+            if candidate.is_file() {
+                return candidate;
+            }
+        }
+    }
+
+    if cfg!(windows) && !path.exists() && path.extension().is_none() {
+        let with_exe = path.with_extension("exe");
+        if with_exe.is_file() {
+            return with_exe;
+        }
+    }
+
+    path.to_path_buf()
+}
+
+/// Workshop content often nests deeply enough to exceed MAX_PATH (260) on
+/// Windows. Prefixes absolute paths with `\\?\` there, which also requires
+/// backslash separators. Other platforms get the path unchanged.
+#[cfg(windows)]
+pub fn long_path(path: &Path) -> PathBuf {
+    let raw = path.to_string_lossy();
+    if raw.starts_with(r"\\?\") || !path.is_absolute() {
+        return path.to_path_buf();
+    }
+
+    let raw = raw.replace('/', "\\");
+    match raw.strip_prefix(r"\\") {
+        Some(unc) => PathBuf::from(format!(r"\\?\UNC\{}", unc)),
+        None => PathBuf::from(format!(r"\\?\{}", raw)),
+    }
+}
+
+#[cfg(not(windows))]
+pub fn long_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+/// Path argument for SteamCMD commands like force_install_dir. On Windows
+/// SteamCMD only understands backslashes, and a trailing backslash would
+/// escape the closing quote Command adds around paths with spaces.
+pub fn steamcmd_path_arg(path: &Path) -> String {
+    let raw = path.to_string_lossy();
+    if cfg!(windows) {
+        return raw.replace('/', "\\").trim_end_matches('\\').to_string();
+    }
+    raw.into_owned()
+}
+
+/// Whether the path is a launcher script installed by a distro package (e.g.
+/// Debian's `/usr/games/steamcmd`) rather than SteamCMD itself. Those live in
+/// system directories, so nothing can be installed next to them.
+pub fn is_wrapper(steamcmd: &Path) -> bool {
+    if cfg!(windows) || steamcmd.file_name().is_none_or(|name| name != "steamcmd") {
+        return false;
+    }
+    steamcmd
+        .parent()
+        .is_none_or(|dir| !dir.join("steamcmd.sh").is_file())
+}
+
+fn home_dir() -> Option<PathBuf> {
+    if let Some(dir) = portable_dir() {
+        return Some(dir.to_path_buf());
+    }
+    let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    std::env::var_os(var)
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Looks for SteamCMD on PATH and in the usual install locations. With
+/// `--portable` only the portable directory is searched.
+pub fn detect_steamcmd() -> Option<PathBuf> {
+    let mut candidates = Vec::new();
+    let portable = portable_dir().is_some();
+
+    if let Some(path) = std::env::var_os("PATH").filter(|_| !portable) {
+        for dir in std::env::split_paths(&path) {
+            candidates.extend(STEAMCMD_NAMES.iter().map(|name| dir.join(name)));
+        }
+    }
+    if let Some(home) = home_dir() {
+        for dir in ["steamcmd", "Steam", ".steam/steamcmd"] {
+            candidates.push(home.join(dir));
+        }
+    }
+    if !portable {
+        let system = if cfg!(windows) { r"C:\steamcmd" } else { "/usr/games/steamcmd" };
+        candidates.push(PathBuf::from(system));
+    }
+
+    candidates
+        .iter()
+        .map(|candidate| resolve_steamcmd(candidate))
+        .find(|path| path.is_file())
+}
+
+/// Steam roots a distro-packaged SteamCMD may download into regardless of
+/// force_install_dir, under the home directory it runs with
+pub fn distro_steam_roots() -> Vec<PathBuf> {
+    match home_dir() {
+        Some(home) if !cfg!(windows) => [".local/share/Steam", "Steam", ".steam/steam"]
+            .iter()
+            .map(|dir| home.join(dir))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Mode and ownership applied to installed files and directories (Unix only)
+#[derive(Debug, Default)]
+pub struct InstallPermissions {
+    pub file_mode: Option<u32>,
+    pub dir_mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+impl InstallPermissions {
+    /// Parses octal modes ("644", "0o755") and resolves owner and group,
+    /// given as names or numeric ids
+    pub fn resolve(
+        file_mode: Option<&str>,
+        dir_mode: Option<&str>,
+        owner: Option<&str>,
+        group: Option<&str>,
+    ) -> Result<Self, String> {
+        let mode = |key: &str, value: Option<&str>| -> Result<Option<u32>, String> {
+            value
+                .map(|v| {
+                    let digits = v.trim().trim_start_matches("0o");
+                    u32::from_str_radix(digits, 8)
+                        .ok()
+                        .filter(|&m| m <= 0o7777)
+                        .ok_or_else(|| format!("{} must be an octal mode like \"644\", got '{}'", key, v))
+                })
+                .transpose()
+        };
+        let id = |key: &str, value: Option<&str>, db: &str| -> Result<Option<u32>, String> {
+            value
+                .map(|v| {
+                    v.parse()
+                        .ok()
+                        .or_else(|| lookup_id(db, v))
+                        .ok_or_else(|| format!("{} '{}' not found in {}", key, v, db))
+                })
+                .transpose()
+        };
+
+        let permissions = Self {
+            file_mode: mode("file_mode", file_mode)?,
+            dir_mode: mode("dir_mode", dir_mode)?,
+            uid: id("owner", owner, "/etc/passwd")?,
+            gid: id("group", group, "/etc/group")?,
+        };
+
+        if cfg!(not(unix)) && !permissions.is_empty() {
+            warn!("file_mode, dir_mode, owner and group are ignored on this platform");
+            return Ok(Self::default());
+        }
+        Ok(permissions)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.file_mode.is_none() && self.dir_mode.is_none() && self.uid.is_none() && self.gid.is_none()
+    }
+
+    #[cfg(unix)]
+    pub fn apply(&self, path: &Path, is_dir: bool) -> std::io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = if is_dir { self.dir_mode } else { self.file_mode };
+        if let Some(mode) = mode {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        }
+        if self.uid.is_some() || self.gid.is_some() {
+            std::os::unix::fs::chown(path, self.uid, self.gid)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn apply(&self, _path: &Path, _is_dir: bool) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Id of a user or group by name from /etc/passwd or /etc/group, whose
+/// entries are `name:password:id:...`
+fn lookup_id(db: &str, name: &str) -> Option<u32> {
+    let content = std::fs::read_to_string(db).ok()?;
+    content.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        (fields.next()? == name).then_some(())?;
+        fields.nth(1)?.parse().ok()
+    })
+}
+
+/// Device and inode of a file, the same for every hard link to it. None where
+/// std can't tell links apart, so each link counts as its own copy there.
+#[cfg(unix)]
+pub fn file_id(meta: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+pub fn file_id(_meta: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Resolves on the next SIGHUP, the usual request to reload configuration.
+/// Never resolves on platforms without it.
+#[cfg(unix)]
+pub async fn hangup() {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    match signal(SignalKind::hangup()) {
+        Ok(mut hangup) => {
+            hangup.recv().await;
+        }
+        Err(_) => std::future::pending().await,
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn hangup() {
+    std::future::pending().await
+}
+
+/// Set once SIGTERM arrived, item loops stop after the item in progress
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+pub fn stop_requested() -> bool {
+    STOP_REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Resolves on the next SIGTERM, the signal systemd stops services with,
+/// and remembers it for `stop_requested`. Never resolves on platforms
+/// without it.
+#[cfg(unix)]
+pub async fn terminate() {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            terminate.recv().await;
+            STOP_REQUESTED.store(true, Ordering::Relaxed);
+        }
+        Err(_) => std::future::pending().await,
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn terminate() {
+    std::future::pending().await
+}
+
+/// Sends a state like `READY=1` to systemd's notification socket. Does
+/// nothing unless started by systemd as a `Type=notify` service.
+#[cfg(unix)]
+pub fn sd_notify(state: &str) {
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(socket_path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let socket_path = PathBuf::from(socket_path);
+    let sent = UnixDatagram::unbound().and_then(|socket| {
+        match socket_path.to_str().and_then(|p| p.strip_prefix('@')) {
+            // Abstract socket names start with '@'
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                socket.send_to_addr(state.as_bytes(), &addr)
+            }
+            _ => socket.send_to(state.as_bytes(), &socket_path),
+        }
+    });
+    if let Err(e) = sent {
+        warn!("failed to notify systemd: {}", e);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn sd_notify(_state: &str) {}
+
+/// How often systemd's watchdog expects a ping, half its timeout to leave
+/// some slack. None if the service has no WatchdogSec.
+fn watchdog_interval() -> Option<Duration> {
+    let pid = std::env::var("WATCHDOG_PID").ok();
+    if pid.is_some_and(|pid| pid.parse() != Ok(std::process::id())) {
+        return None;
+    }
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    Some(Duration::from_micros(usec / 2)).filter(|d| !d.is_zero())
+}
+
+/// Watchdog interval and the time of the last ping, once enabled
+static WATCHDOG: OnceCell<(Duration, Mutex<Instant>)> = OnceCell::new();
+
+/// Switches to running under systemd: `watchdog_ping` starts notifying if
+/// the service has a watchdog. Returns the watchdog interval.
+pub fn enable_systemd() -> Option<Duration> {
+    let every = watchdog_interval()?;
+    let _ = WATCHDOG.set((every, Mutex::new(Instant::now())));
+    sd_notify("WATCHDOG=1");
+    Some(every)
+}
+
+/// Tells systemd's watchdog that work is progressing. Called as items and
+/// SteamCMD output go by, so a hung download or update stops the pings.
+pub fn watchdog_ping() {
+    let Some((every, last)) = WATCHDOG.get() else {
+        return;
+    };
+    let mut last = last.lock().unwrap();
+    if last.elapsed() >= *every / 4 {
+        *last = Instant::now();
+        sd_notify("WATCHDOG=1");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn resolves_steamcmd_inside_its_directory() {
+        let dir = TempDir::new("resolve-dir");
+        let exe = dir.0.join(STEAMCMD_NAMES[0]);
+        std::fs::write(&exe, "").unwrap();
+
+        assert_eq!(resolve_steamcmd(&dir.0), exe);
+    }
+
+    #[test]
+    fn keeps_unknown_steamcmd_path_for_reporting() {
+        let dir = TempDir::new("resolve-missing");
+        let missing = dir.0.join("nothing-here");
+
+        assert_eq!(resolve_steamcmd(&missing), missing);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn adds_missing_exe_extension() {
+        let dir = TempDir::new("resolve-exe");
+        std::fs::write(dir.0.join("steamcmd.exe"), "").unwrap();
+
+        assert_eq!(resolve_steamcmd(&dir.0.join("steamcmd")), dir.0.join("steamcmd.exe"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn long_path_prefixes_absolute_paths() {
+        assert_eq!(long_path(Path::new(r"C:\srv/maps")), PathBuf::from(r"\\?\C:\srv\maps"));
+        assert_eq!(
+            long_path(Path::new(r"\\server\share\maps")),
+            PathBuf::from(r"\\?\UNC\server\share\maps")
+        );
+        assert_eq!(long_path(Path::new(r"\\?\C:\srv")), PathBuf::from(r"\\?\C:\srv"));
+        assert_eq!(long_path(Path::new("maps")), PathBuf::from("maps"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn steamcmd_path_arg_uses_backslashes_without_trailing_one() {
+        assert_eq!(
+            steamcmd_path_arg(Path::new(r"C:/Program Files/necodl/sandbox/")),
+            r"C:\Program Files\necodl\sandbox"
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn steamcmd_path_arg_is_unchanged() {
+        assert_eq!(steamcmd_path_arg(Path::new("/srv/necodl/sandbox 1")), "/srv/necodl/sandbox 1");
+    }
+}
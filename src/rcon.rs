@@ -0,0 +1,100 @@
+use crate::error::NecoError;
+use anyhow::{Context, Result};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{Duration, timeout};
+
+const SERVERDATA_AUTH: i32 = 3;
+const SERVERDATA_AUTH_RESPONSE: i32 = 2;
+const SERVERDATA_EXECCOMMAND: i32 = 2;
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct RconClient {
+    stream: TcpStream,
+    next_id: i32,
+}
+
+impl RconClient {
+    pub async fn connect(address: &str, password: &str) -> Result<Self> {
+        let stream = timeout(IO_TIMEOUT, TcpStream::connect(address))
+            .await
+            .context("RCON connection timed out")?
+            .with_context(|| NecoError::Network(format!("Failed to connect to RCON at {}", address)))?;
+
+        let mut client = Self { stream, next_id: 1 };
+        client.authenticate(password).await?;
+        Ok(client)
+    }
+
+    async fn authenticate(&mut self, password: &str) -> Result<()> {
+        let id = self.send(SERVERDATA_AUTH, password).await?;
+
+        // The server may send an empty SERVERDATA_RESPONSE_VALUE before the auth response
+        loop {
+            let (response_id, kind, _) = self.receive().await?;
+            if kind != SERVERDATA_AUTH_RESPONSE {
+                continue;
+            }
+            if response_id == -1 {
+                anyhow::bail!("RCON authentication failed, check rcon_password");
+            }
+            if response_id == id {
+                return Ok(());
+            }
+        }
+    }
+
+    pub async fn execute(&mut self, command: &str) -> Result<String> {
+        let id = self.send(SERVERDATA_EXECCOMMAND, command).await?;
+
+        loop {
+            let (response_id, _, body) = self.receive().await?;
+            if response_id == id {
+                return Ok(body);
+            }
+        }
+    }
+
+    async fn send(&mut self, kind: i32, body: &str) -> Result<i32> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let size = (4 + 4 + body.len() + 2) as i32;
+        let mut packet = Vec::with_capacity(size as usize + 4);
+        packet.extend_from_slice(&size.to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&kind.to_le_bytes());
+        packet.extend_from_slice(body.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+
+        timeout(IO_TIMEOUT, self.stream.write_all(&packet))
+            .await
+            .context("RCON write timed out")??;
+        Ok(id)
+    }
+
+    async fn receive(&mut self) -> Result<(i32, i32, String)> {
+        let size = timeout(IO_TIMEOUT, self.stream.read_i32_le())
+            .await
+            .context("RCON read timed out")??;
+
+        if !(10..=4096 + 10).contains(&size) {
+            anyhow::bail!("Invalid RCON packet size: {}", size);
+        }
+
+        let mut data = vec![0u8; size as usize];
+        timeout(IO_TIMEOUT, self.stream.read_exact(&mut data))
+            .await
+            .context("RCON read timed out")??;
+
+        let id = i32::from_le_bytes(data[0..4].try_into()?);
+        let kind = i32::from_le_bytes(data[4..8].try_into()?);
+        let body_end = data[8..]
+            .iter()
+            .position(|&b| b == 0)
+            .map_or(data.len(), |pos| pos + 8);
+        let body = String::from_utf8_lossy(&data[8..body_end]).to_string();
+
+        Ok((id, kind, body))
+    }
+}
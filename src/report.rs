@@ -0,0 +1,346 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+use crate::format_file_size;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemUpdate {
+    pub id: String,
+    pub title: String,
+    pub old_changelog_id: Option<String>,
+    pub new_changelog_id: String,
+    pub changelog: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+    pub old_size: u64,
+    pub new_size: u64,
+}
+
+impl ItemUpdate {
+    fn is_new(&self) -> bool {
+        self.old_changelog_id.is_none()
+    }
+
+    fn size_delta(&self) -> String {
+        let delta = self.new_size as i64 - self.old_size as i64;
+        let sign = if delta < 0 { "-" } else { "+" };
+        format!("{}{}", sign, format_file_size(delta.unsigned_abs()))
+    }
+
+    fn files_summary(&self) -> String {
+        format!(
+            "{} added, {} removed, {} changed",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        )
+    }
+}
+
+/// Totals of one download or update run, for tuning concurrency and
+/// schedules
+#[derive(Debug, Default)]
+pub struct RunStats {
+    started: Option<Instant>,
+    pub bytes_downloaded: u64,
+    pub bytes_copied: u64,
+    /// Time spent on each item, in the order they were processed
+    pub item_times: Vec<(String, Duration)>,
+    /// What became of each item, in the order they were processed
+    pub results: Vec<ItemResult>,
+}
+
+/// What a batch download or update did with one item
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemResult {
+    pub id: String,
+    pub title: String,
+    pub status: ItemStatus,
+    /// Installed files after the run
+    pub files: Vec<String>,
+    /// Downloaded by SteamCMD for this item
+    pub bytes: u64,
+    pub duration_secs: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemStatus {
+    Updated,
+    UpToDate,
+    /// Left alone on purpose, e.g. archived or deferred while its map is live
+    Skipped,
+    Failed,
+    Unavailable,
+    /// Found by `update --check`, not downloaded
+    UpdateAvailable,
+}
+
+impl ItemStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ItemStatus::Updated => "updated",
+            ItemStatus::UpToDate => "up to date",
+            ItemStatus::Skipped => "skipped",
+            ItemStatus::Failed => "failed",
+            ItemStatus::Unavailable => "unavailable",
+            ItemStatus::UpdateAvailable => "update available",
+        }
+    }
+}
+
+/// Result of `WorkshopManager::update_all`
+#[derive(Debug, Serialize)]
+pub struct UpdateReport {
+    pub started_at: DateTime<Utc>,
+    /// Only checked for updates, nothing was downloaded
+    pub check: bool,
+    pub items: Vec<ItemResult>,
+}
+
+/// RunStats as written to the status file
+#[derive(Debug, Serialize)]
+pub struct RunTotals {
+    pub processed: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub bytes_downloaded: u64,
+    pub bytes_copied: u64,
+    pub wall_time_secs: f64,
+    /// Up to five items that took longest, with their time in seconds
+    pub slowest: Vec<(String, f64)>,
+}
+
+impl RunStats {
+    /// Starts the wall clock unless the run is already timed
+    pub fn start(&mut self) {
+        self.started.get_or_insert_with(Instant::now);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.item_times.is_empty()
+    }
+
+    /// Totals given how many items changed and failed. Items may be
+    /// processed more than once (e.g. a retried download), each counts once.
+    pub fn totals(&self, updated: usize, failed: usize) -> RunTotals {
+        let mut processed: Vec<&str> = self.item_times.iter().map(|(id, _)| id.as_str()).collect();
+        processed.sort_unstable();
+        processed.dedup();
+
+        let mut slowest: Vec<&(String, Duration)> = self.item_times.iter().collect();
+        slowest.sort_by_key(|(_, time)| std::cmp::Reverse(*time));
+
+        RunTotals {
+            processed: processed.len(),
+            updated,
+            skipped: processed.len().saturating_sub(updated + failed),
+            failed,
+            bytes_downloaded: self.bytes_downloaded,
+            bytes_copied: self.bytes_copied,
+            wall_time_secs: self.started.map(|s| s.elapsed().as_secs_f64()).unwrap_or_default(),
+            slowest: slowest
+                .into_iter()
+                .take(5)
+                .map(|(id, time)| (id.clone(), time.as_secs_f64()))
+                .collect(),
+        }
+    }
+}
+
+pub fn print_totals(totals: &RunTotals) {
+    println!(
+        "\n{} items processed: {} updated, {} up to date or skipped, {} failed",
+        totals.processed, totals.updated, totals.skipped, totals.failed
+    );
+    println!(
+        "Downloaded {}, installed {} in {:.1}s",
+        format_file_size(totals.bytes_downloaded),
+        format_file_size(totals.bytes_copied),
+        totals.wall_time_secs
+    );
+    if totals.processed > 1 {
+        let slowest: Vec<String> = totals
+            .slowest
+            .iter()
+            .map(|(id, secs)| format!("{} ({:.1}s)", id, secs))
+            .collect();
+        println!("Slowest: {}", slowest.join(", "));
+    }
+}
+
+pub fn print_summary(updates: &[ItemUpdate]) {
+    if updates.is_empty() {
+        println!("\nNo items changed.");
+        return;
+    }
+
+    println!("\nUpdate summary ({} items):", updates.len());
+    for update in updates {
+        println!("  {} ({})", update.title, update.id);
+        match &update.old_changelog_id {
+            Some(old) => println!("    Changelog : {} -> {}", old, update.new_changelog_id),
+            None => println!("    Changelog : new item ({})", update.new_changelog_id),
+        }
+        if !update.changelog.is_empty() {
+            println!("    Notes     : {}", update.changelog.replace('\n', " "));
+        }
+        println!("    Files     : {}", update.files_summary());
+        println!(
+            "    Size      : {} ({})",
+            format_file_size(update.new_size),
+            update.size_delta()
+        );
+    }
+}
+
+pub fn render_markdown(updates: &[ItemUpdate], generated_at: DateTime<Utc>) -> String {
+    let mut out = format!(
+        "# Workshop updates ({})\n\n",
+        generated_at.format("%Y-%m-%d %H:%M UTC")
+    );
+
+    if updates.is_empty() {
+        out.push_str("No items changed.\n");
+        return out;
+    }
+
+    for update in updates {
+        out.push_str(&format!(
+            "## [{}]({}){}\n\n",
+            update.title,
+            crate::workshop_url(&update.id),
+            if update.is_new() { " (new)" } else { "" }
+        ));
+        if !update.changelog.is_empty() {
+            for line in update.changelog.lines() {
+                out.push_str(&format!("> {}\n", line));
+            }
+            out.push('\n');
+        }
+        out.push_str(&format!("- Files: {}\n", update.files_summary()));
+        out.push_str(&format!(
+            "- Size: {} ({})\n\n",
+            format_file_size(update.new_size),
+            update.size_delta()
+        ));
+    }
+
+    out
+}
+
+pub fn render_html(updates: &[ItemUpdate], generated_at: DateTime<Utc>) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Workshop updates</title></head>\n<body>\n<h1>Workshop updates ({})</h1>\n",
+        generated_at.format("%Y-%m-%d %H:%M UTC")
+    );
+
+    if updates.is_empty() {
+        out.push_str("<p>No items changed.</p>\n");
+    }
+
+    for update in updates {
+        out.push_str(&format!(
+            "<h2><a href=\"{}\">{}</a>{}</h2>\n",
+            crate::workshop_url(&update.id),
+            escape_html(&update.title),
+            if update.is_new() { " (new)" } else { "" }
+        ));
+        if !update.changelog.is_empty() {
+            out.push_str(&format!(
+                "<blockquote>{}</blockquote>\n",
+                escape_html(&update.changelog).replace('\n', "<br>")
+            ));
+        }
+        out.push_str(&format!(
+            "<ul>\n<li>Files: {}</li>\n<li>Size: {} ({})</li>\n</ul>\n",
+            update.files_summary(),
+            format_file_size(update.new_size),
+            update.size_delta()
+        ));
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+pub struct InventoryRow {
+    pub id: String,
+    pub title: String,
+    pub map: String,
+    pub size: u64,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub collections: Vec<String>,
+}
+
+impl InventoryRow {
+    fn updated(&self) -> String {
+        self.updated_at
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default()
+    }
+}
+
+pub fn render_inventory_csv(rows: &[InventoryRow]) -> String {
+    let mut out = String::from("id,title,map,size_bytes,last_updated,collections\n");
+
+    for row in rows {
+        let fields = [
+            row.id.clone(),
+            row.title.clone(),
+            row.map.clone(),
+            row.size.to_string(),
+            row.updated(),
+            row.collections.join(" "),
+        ];
+        let line: Vec<String> = fields.iter().map(|f| escape_csv(f)).collect();
+        out.push_str(&line.join(","));
+        out.push('\n');
+    }
+
+    out
+}
+
+pub fn render_inventory_html(rows: &[InventoryRow], generated_at: DateTime<Utc>) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Workshop items</title></head>\n<body>\n<h1>Workshop items ({})</h1>\n<p>Generated {}</p>\n<table>\n<tr><th>ID</th><th>Title</th><th>Map</th><th>Size</th><th>Last Updated</th><th>Collections</th></tr>\n",
+        rows.len(),
+        generated_at.format("%Y-%m-%d %H:%M UTC")
+    );
+
+    for row in rows {
+        out.push_str(&format!(
+            "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            crate::workshop_url(&row.id),
+            row.id,
+            escape_html(&row.title),
+            escape_html(&row.map),
+            format_file_size(row.size),
+            row.updated(),
+            row.collections.join(", ")
+        ));
+    }
+
+    out.push_str("</table>\n</body>\n</html>\n");
+    out
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
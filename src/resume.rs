@@ -0,0 +1,70 @@
+use crate::error::NecoError;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::fs;
+
+/// Progress of an `update` or collection download, saved after every item
+/// so an interrupted run can continue with `update --resume`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunState {
+    pub started: DateTime<Utc>,
+    /// Collection being downloaded, None for an update
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+    /// Appid the collection was downloaded under with `download --appid`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub appid: Option<String>,
+    #[serde(default)]
+    pub force: bool,
+    pub pending: Vec<String>,
+    #[serde(default)]
+    pub completed: Vec<String>,
+}
+
+impl RunState {
+    pub fn new(collection: Option<&str>, force: bool, pending: Vec<String>) -> Self {
+        Self {
+            started: Utc::now(),
+            collection: collection.map(str::to_string),
+            appid: None,
+            force,
+            pending,
+            completed: Vec::new(),
+        }
+    }
+
+    /// Moves an item from pending to completed
+    pub fn complete(&mut self, workshop_id: &str) {
+        self.pending.retain(|id| id != workshop_id);
+        self.completed.push(workshop_id.to_string());
+    }
+}
+
+pub async fn load(path: &Path) -> Result<Option<RunState>> {
+    match fs::read_to_string(path).await {
+        Ok(data) => serde_json::from_str(&data)
+            .map(Some)
+            .with_context(|| format!("Failed to parse {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| NecoError::Fs(format!("Failed to read {}", path.display()))),
+    }
+}
+
+pub async fn save(path: &Path, state: &RunState) -> Result<()> {
+    let data = serde_json::to_string_pretty(state)?;
+    fs::write(path, data)
+        .await
+        .with_context(|| NecoError::Fs(format!("Failed to save {}", path.display())))
+}
+
+/// Forgets the run once it finished
+pub async fn clear(path: &Path) -> Result<()> {
+    match fs::remove_file(path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| NecoError::Fs(format!("Failed to remove {}", path.display())))
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,47 @@
+use chrono::{NaiveTime, Timelike};
+use std::fmt;
+
+/// Daily time range like `04:00-06:00` in server local time. A range whose
+/// end is before its start wraps past midnight.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl TimeWindow {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (start, end) = value.split_once('-')?;
+        let time = |s: &str| NaiveTime::parse_from_str(s.trim(), "%H:%M").ok();
+        Some(Self {
+            start: time(start)?,
+            end: time(end)?,
+        })
+    }
+
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start.hour(),
+            self.start.minute(),
+            self.end.hour(),
+            self.end.minute()
+        )
+    }
+}
+
+/// Whether `time` falls in one of the windows. No windows means any time.
+pub fn is_open(windows: &[TimeWindow], time: NaiveTime) -> bool {
+    windows.is_empty() || windows.iter().any(|w| w.contains(time))
+}
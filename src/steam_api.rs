@@ -0,0 +1,299 @@
+use crate::error::NecoError;
+use crate::http::RetryPolicy;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+
+const API_BASE: &str = "https://api.steampowered.com";
+const KEY_URL: &str = "https://steamcommunity.com/dev/apikey";
+
+/// Steam Web API client. Endpoints under IPublishedFileService need a key,
+/// the legacy ISteamRemoteStorage ones work anonymously.
+#[derive(Clone)]
+pub struct SteamApi {
+    client: reqwest::Client,
+    key: Option<String>,
+    retry: RetryPolicy,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PublishedFile {
+    pub publishedfileid: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub short_description: String,
+    #[serde(default)]
+    pub file_description: String,
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+    #[serde(default, deserialize_with = "lenient_u64")]
+    pub file_size: u64,
+    #[serde(default, deserialize_with = "lenient_u64")]
+    pub subscriptions: u64,
+    #[serde(default, deserialize_with = "lenient_time")]
+    pub time_updated: Option<DateTime<Utc>>,
+    /// Required items, only returned by IPublishedFileService
+    #[serde(default)]
+    pub children: Option<Vec<Child>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tag {
+    pub tag: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Child {
+    pub publishedfileid: String,
+}
+
+#[derive(Deserialize)]
+struct Envelope<T> {
+    response: T,
+}
+
+#[derive(Deserialize)]
+struct DetailsList {
+    #[serde(default)]
+    total: u64,
+    #[serde(default)]
+    publishedfiledetails: Vec<PublishedFile>,
+}
+
+/// EPublishedFileQueryType values used by QueryFiles
+#[derive(Debug, Clone, Copy)]
+pub enum QueryOrder {
+    ByVote = 0,
+    ByPublicationDate = 1,
+    ByTrend = 3,
+    TextSearch = 12,
+}
+
+/// Per-user item lists exposed by IPublishedFileService/GetUserFiles
+#[derive(Debug, Clone, Copy)]
+pub enum UserList {
+    Subscriptions,
+    Favorites,
+    Published,
+}
+
+impl UserList {
+    fn as_param(&self) -> &'static str {
+        match self {
+            UserList::Subscriptions => "mysubscriptions",
+            UserList::Favorites => "myfavorites",
+            UserList::Published => "myfiles",
+        }
+    }
+}
+
+impl SteamApi {
+    pub fn new(client: reqwest::Client, key: Option<String>, retry: RetryPolicy) -> Self {
+        Self {
+            client,
+            key: key.filter(|k| !k.trim().is_empty()),
+            retry,
+        }
+    }
+
+    pub fn has_key(&self) -> bool {
+        self.key.is_some()
+    }
+
+    fn require_key(&self, what: &str) -> Result<&str> {
+        self.key.as_deref().with_context(|| {
+            NecoError::Config(format!(
+                "{} uses the Steam Web API and requires steam_api_key in config.toml (get one at {})",
+                what, KEY_URL
+            ))
+        })
+    }
+
+    async fn get<T: DeserializeOwned>(&self, endpoint: &str, query: &[(String, String)]) -> Result<T> {
+        let request = self
+            .client
+            .get(format!("{}/{}", API_BASE, endpoint))
+            .query(query);
+        let response = self
+            .retry
+            .send(request)
+            .await
+            .with_context(|| NecoError::Network(format!("Failed to query Steam Web API {}", endpoint)))?;
+
+        match response.status() {
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                return Err(NecoError::Config(format!(
+                    "Steam Web API rejected steam_api_key for {}",
+                    endpoint
+                ))
+                .into());
+            }
+            status if !status.is_success() => {
+                return Err(NecoError::Network(format!(
+                    "Steam Web API {} returned {}",
+                    endpoint, status
+                ))
+                .into());
+            }
+            _ => {}
+        }
+
+        response
+            .json()
+            .await
+            .with_context(|| format!("Failed to parse Steam Web API {} response", endpoint))
+    }
+
+    /// Full details for each item. Uses IPublishedFileService with a key and
+    /// falls back to the anonymous endpoint without one.
+    pub async fn details(&self, ids: &[String]) -> Result<Vec<PublishedFile>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let Some(key) = &self.key else {
+            return self.details_anonymous(ids).await;
+        };
+
+        let mut query = vec![
+            ("key".to_string(), key.clone()),
+            ("short_description".to_string(), "true".to_string()),
+            ("includechildren".to_string(), "true".to_string()),
+        ];
+        for (i, id) in ids.iter().enumerate() {
+            query.push((format!("publishedfileids[{}]", i), id.clone()));
+        }
+
+        let list: Envelope<DetailsList> = self.get("IPublishedFileService/GetDetails/v1/", &query).await?;
+        Ok(list.response.publishedfiledetails)
+    }
+
+    async fn details_anonymous(&self, ids: &[String]) -> Result<Vec<PublishedFile>> {
+        let mut form = vec![("itemcount".to_string(), ids.len().to_string())];
+        for (i, id) in ids.iter().enumerate() {
+            form.push((format!("publishedfileids[{}]", i), id.clone()));
+        }
+
+        let request = self
+            .client
+            .post(format!("{}/ISteamRemoteStorage/GetPublishedFileDetails/v1/", API_BASE))
+            .form(&form);
+        let list: Envelope<DetailsList> = self
+            .retry
+            .send(request)
+            .await
+            .context(NecoError::Network("Failed to query Steam Web API".to_string()))?
+            .error_for_status()
+            .context(NecoError::Network("Steam Web API returned an error".to_string()))?
+            .json()
+            .await
+            .context("Failed to parse Steam Web API response")?;
+
+        Ok(list.response.publishedfiledetails)
+    }
+
+    /// Full text search of the appid's Workshop, most relevant first
+    pub async fn search(&self, appid: &str, text: &str, count: u32) -> Result<Vec<PublishedFile>> {
+        self.query_files(appid, QueryOrder::TextSearch, &[], text, count)
+            .await
+    }
+
+    /// The first `count` items of a Workshop query, optionally limited to
+    /// items carrying all of `tags`
+    pub async fn query_files(
+        &self,
+        appid: &str,
+        order: QueryOrder,
+        tags: &[String],
+        text: &str,
+        count: u32,
+    ) -> Result<Vec<PublishedFile>> {
+        let key = self.require_key("Searching the Workshop")?;
+        let mut query = vec![
+            ("key".to_string(), key.to_string()),
+            ("appid".to_string(), appid.to_string()),
+            ("query_type".to_string(), (order as u32).to_string()),
+            ("numperpage".to_string(), count.to_string()),
+            ("return_details".to_string(), "true".to_string()),
+            ("match_all_tags".to_string(), "true".to_string()),
+        ];
+        if !text.is_empty() {
+            query.push(("search_text".to_string(), text.to_string()));
+        }
+        for (i, tag) in tags.iter().enumerate() {
+            query.push((format!("requiredtags[{}]", i), tag.clone()));
+        }
+
+        let list: Envelope<DetailsList> = self.get("IPublishedFileService/QueryFiles/v1/", &query).await?;
+        Ok(list.response.publishedfiledetails)
+    }
+
+    /// All items in one of a user's lists for the appid. The profile and the
+    /// list have to be public.
+    pub async fn user_files(&self, steamid: &str, appid: &str, list: UserList) -> Result<Vec<PublishedFile>> {
+        const PER_PAGE: u64 = 100;
+        let key = self.require_key("Listing a Steam user's Workshop items")?;
+        let mut files = Vec::new();
+
+        for page in 1.. {
+            let query = vec![
+                ("key".to_string(), key.to_string()),
+                ("steamid".to_string(), steamid.to_string()),
+                ("appid".to_string(), appid.to_string()),
+                ("type".to_string(), list.as_param().to_string()),
+                ("page".to_string(), page.to_string()),
+                ("numperpage".to_string(), PER_PAGE.to_string()),
+                ("return_short_description".to_string(), "true".to_string()),
+            ];
+
+            let response: Envelope<DetailsList> = self.get("IPublishedFileService/GetUserFiles/v1/", &query).await?;
+            let received = response.response.publishedfiledetails.len() as u64;
+            files.extend(response.response.publishedfiledetails);
+
+            if received < PER_PAGE || files.len() as u64 >= response.response.total {
+                break;
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Turns a custom profile URL name into a SteamID64
+    pub async fn resolve_vanity(&self, name: &str) -> Result<String> {
+        let key = self.require_key("Resolving a custom profile URL")?;
+        let query = vec![
+            ("key".to_string(), key.to_string()),
+            ("vanityurl".to_string(), name.to_string()),
+        ];
+
+        let response: Envelope<VanityResponse> = self.get("ISteamUser/ResolveVanityURL/v1/", &query).await?;
+        match response.response {
+            VanityResponse {
+                success: 1,
+                steamid: Some(steamid),
+            } => Ok(steamid),
+            _ => Err(NecoError::NotFound(format!("No Steam profile found for '{}'", name)).into()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct VanityResponse {
+    success: u32,
+    steamid: Option<String>,
+}
+
+fn lenient_u64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(s) => s.parse().unwrap_or_default(),
+        serde_json::Value::Number(n) => n.as_u64().unwrap_or_default(),
+        _ => 0,
+    })
+}
+
+fn lenient_time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+    let secs = lenient_u64(deserializer)?;
+    Ok((secs > 0).then(|| DateTime::from_timestamp(secs as i64, 0)).flatten())
+}
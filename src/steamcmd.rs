@@ -0,0 +1,349 @@
+use crate::error::NecoError;
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::info;
+
+static SUCCESS_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"^Success\. Downloaded item (\d+) to "(.+)" \((\d+) bytes\)"#).unwrap()
+});
+static ITEM_FAILED_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^ERROR! Download item (\d+) failed \((.+)\)").unwrap());
+static TIMEOUT_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^ERROR! Timeout downloading item (\d+)").unwrap());
+static LOGIN_FAILED_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^Logging in user .*FAILED \((.+)\)").unwrap());
+static GENERIC_ERROR_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^ERROR! (.+)").unwrap());
+
+/// Numbered SteamCMD install dirs (`necodl-0`, `necodl-1`, ...) so concurrent
+/// jobs never share a steamapps directory
+pub struct SandboxPool {
+    size: usize,
+    free: Mutex<Vec<usize>>,
+    slots: Arc<Semaphore>,
+}
+
+/// A sandbox reserved for one job, returned to the pool on drop
+pub struct Sandbox {
+    pool: Arc<SandboxPool>,
+    index: usize,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl SandboxPool {
+    pub fn new(size: usize) -> Arc<Self> {
+        let size = size.max(1);
+        Arc::new(Self {
+            size,
+            free: Mutex::new((0..size).rev().collect()),
+            slots: Arc::new(Semaphore::new(size)),
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Waits for a free sandbox
+    pub async fn acquire(self: &Arc<Self>) -> Sandbox {
+        let permit = self
+            .slots
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("sandbox semaphore is never closed");
+        self.reserve(permit)
+    }
+
+    /// A free sandbox, if there is one right now
+    pub fn try_acquire(self: &Arc<Self>) -> Option<Sandbox> {
+        let permit = self.slots.clone().try_acquire_owned().ok()?;
+        Some(self.reserve(permit))
+    }
+
+    fn reserve(self: &Arc<Self>, permit: OwnedSemaphorePermit) -> Sandbox {
+        let index = self
+            .free
+            .lock()
+            .unwrap()
+            .pop()
+            .expect("a permit guarantees a free sandbox");
+
+        Sandbox {
+            pool: self.clone(),
+            index,
+            _permit: permit,
+        }
+    }
+}
+
+impl Sandbox {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        self.pool.free.lock().unwrap().push(self.index);
+    }
+}
+
+/// force_install_dir of a sandbox, relative to the SteamCMD directory. Kept
+/// apart from any game installed with the same SteamCMD.
+pub fn install_dir_name(sandbox: usize) -> String {
+    format!("necodl-{}", sandbox)
+}
+
+/// Where an item lands inside a `steamapps/workshop` directory
+pub fn content_path(workshop_dir: &Path, appid: &str, workshop_id: &str) -> PathBuf {
+    workshop_dir.join("content").join(appid).join(workshop_id)
+}
+
+/// SteamCMD's record of the items it installed for an app
+pub fn manifest_path(workshop_dir: &Path, appid: &str) -> PathBuf {
+    workshop_dir.join(format!("appworkshop_{}.acf", appid))
+}
+
+/// The `steamapps/workshop` directory a reported download path belongs to,
+/// if it has the usual `content/<appid>/<id>` layout
+pub fn workshop_dir_of(path: &Path, appid: &str, workshop_id: &str) -> Option<PathBuf> {
+    let workshop_dir = path.parent()?.parent()?.parent()?;
+    (content_path(workshop_dir, appid, workshop_id) == path).then(|| workshop_dir.to_path_buf())
+}
+
+/// What a SteamCMD `workshop_download_item` run reported
+#[derive(Debug)]
+pub enum Outcome {
+    Downloaded { path: PathBuf, size: u64 },
+    Failed(Failure),
+    /// No result line was printed, the output format may have changed
+    Unknown,
+}
+
+/// Why SteamCMD couldn't download an item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    /// The app requires owning it, anonymous login can't download its content
+    NoSubscription,
+    /// Private, friends-only or otherwise restricted item
+    AccessDenied,
+    Timeout,
+    DiskFull,
+    NoConnection,
+    LoginFailed(String),
+    Other(String),
+}
+
+impl Failure {
+    fn classify(reason: &str) -> Self {
+        let lower = reason.to_lowercase();
+        if lower.contains("no subscription") {
+            Failure::NoSubscription
+        } else if lower.contains("access denied") {
+            Failure::AccessDenied
+        } else if lower.contains("timeout") || lower.contains("timed out") {
+            Failure::Timeout
+        } else if lower.contains("disk")
+            && (lower.contains("full") || lower.contains("space") || lower.contains("write"))
+        {
+            Failure::DiskFull
+        } else if lower.contains("no connection") {
+            Failure::NoConnection
+        } else {
+            Failure::Other(reason.to_string())
+        }
+    }
+
+    /// Stable identifier for the status file
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Failure::NoSubscription => "no_subscription",
+            Failure::AccessDenied => "access_denied",
+            Failure::Timeout => "timeout",
+            Failure::DiskFull => "disk_full",
+            Failure::NoConnection => "no_connection",
+            Failure::LoginFailed(_) => "login_failed",
+            Failure::Other(_) => "other",
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::NoSubscription => {
+                write!(f, "no subscription, the app can't be downloaded anonymously")
+            }
+            Failure::AccessDenied => write!(f, "access denied, the item may be private"),
+            Failure::Timeout => write!(f, "timed out"),
+            Failure::DiskFull => write!(f, "not enough disk space"),
+            Failure::NoConnection => write!(f, "no connection to Steam"),
+            Failure::LoginFailed(reason) => write!(f, "login failed ({})", reason),
+            Failure::Other(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// Result lines recognized in SteamCMD's output
+enum Line {
+    Downloaded { id: String, path: PathBuf, size: u64 },
+    ItemFailed { id: String, reason: String },
+    TimedOut { id: String },
+    LoginFailed(String),
+    Error(String),
+}
+
+fn parse_line(line: &str) -> Option<Line> {
+    let line = line.trim();
+
+    if let Some(c) = SUCCESS_REGEX.captures(line) {
+        return Some(Line::Downloaded {
+            id: c[1].to_string(),
+            path: PathBuf::from(&c[2]),
+            size: c[3].parse().unwrap_or_default(),
+        });
+    }
+    if let Some(c) = ITEM_FAILED_REGEX.captures(line) {
+        return Some(Line::ItemFailed {
+            id: c[1].to_string(),
+            reason: c[2].to_string(),
+        });
+    }
+    if let Some(c) = TIMEOUT_REGEX.captures(line) {
+        return Some(Line::TimedOut { id: c[1].to_string() });
+    }
+    if let Some(c) = LOGIN_FAILED_REGEX.captures(line) {
+        return Some(Line::LoginFailed(c[1].to_string()));
+    }
+    if let Some(c) = GENERIC_ERROR_REGEX.captures(line) {
+        return Some(Line::Error(c[1].trim_end_matches('.').to_string()));
+    }
+    None
+}
+
+/// Collects SteamCMD output lines into the outcome for one workshop item.
+/// The first result line about the item wins; login failures and other
+/// errors are kept as the reason if no item result shows up.
+#[derive(Default)]
+pub struct OutputParser {
+    outcome: Option<Outcome>,
+    last_error: Option<String>,
+}
+
+impl OutputParser {
+    pub fn feed(&mut self, workshop_id: &str, line: &str) {
+        if self.outcome.is_some() {
+            return;
+        }
+
+        match parse_line(line) {
+            Some(Line::Downloaded { id, path, size }) if id == workshop_id => {
+                self.outcome = Some(Outcome::Downloaded { path, size });
+            }
+            Some(Line::ItemFailed { id, reason }) if id == workshop_id => {
+                self.outcome = Some(Outcome::Failed(Failure::classify(&reason)));
+            }
+            Some(Line::TimedOut { id }) if id == workshop_id => {
+                self.outcome = Some(Outcome::Failed(Failure::Timeout));
+            }
+            Some(Line::LoginFailed(reason)) => {
+                self.outcome = Some(Outcome::Failed(Failure::LoginFailed(reason)));
+            }
+            Some(Line::Error(message)) => self.last_error = Some(message),
+            _ => {}
+        }
+    }
+
+    pub fn finish(self) -> Outcome {
+        match (self.outcome, self.last_error) {
+            (Some(outcome), _) => outcome,
+            (None, Some(error)) => Outcome::Failed(Failure::classify(&error)),
+            (None, None) => Outcome::Unknown,
+        }
+    }
+}
+
+/// Logs in anonymously and quits, to check SteamCMD can reach Steam.
+/// Returns why the login failed, if it did.
+pub async fn check_login(steamcmd: &Path, extra_args: &[String]) -> Result<Option<Failure>> {
+    let mut args: Vec<&str> = extra_args.iter().map(String::as_str).collect();
+    args.extend(["+login", "anonymous", "+quit"]);
+    match run(steamcmd, &args, "", false).await? {
+        Outcome::Failed(failure) => Ok(Some(failure)),
+        _ => Ok(None),
+    }
+}
+
+/// Runs SteamCMD and parses its output for the result of downloading
+/// `workshop_id`. Its stderr is always echoed, stdout only when verbose.
+pub async fn run(steamcmd: &Path, args: &[&str], workshop_id: &str, verbose: bool) -> Result<Outcome> {
+    let mut command = Command::new(steamcmd);
+    // SteamCMD keeps logs and runtime links under the home directory
+    if let Some(dir) = crate::platform::portable_dir() {
+        command.env("HOME", dir);
+    }
+    let mut child = command
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| {
+            NecoError::SteamCmd(format!(
+                "Failed to start SteamCMD at {}",
+                steamcmd.display()
+            ))
+        })?;
+
+    let stdout = child
+        .stdout
+        .take()
+        .context("Failed to capture SteamCMD stdout")?;
+    let stderr = child
+        .stderr
+        .take()
+        .context("Failed to capture SteamCMD stderr")?;
+    let mut stdout_lines = BufReader::new(stdout).lines();
+    let mut stderr_lines = BufReader::new(stderr).lines();
+    let (mut stdout_done, mut stderr_done) = (false, false);
+
+    // Keep draining both pipes after the result so SteamCMD never blocks
+    let mut parser = OutputParser::default();
+    while !stdout_done || !stderr_done {
+        let line = tokio::select! {
+            line = stdout_lines.next_line(), if !stdout_done => line?,
+            line = stderr_lines.next_line(), if !stderr_done => match line? {
+                Some(line) => {
+                    if !verbose && !line.trim().is_empty() {
+                        info!("SteamCMD: {}", line);
+                    }
+                    Some(line)
+                }
+                None => {
+                    stderr_done = true;
+                    continue;
+                }
+            },
+        };
+
+        let Some(line) = line else {
+            stdout_done = true;
+            continue;
+        };
+        crate::platform::watchdog_ping();
+        if verbose {
+            println!("{}", line);
+        }
+        parser.feed(workshop_id, &line);
+    }
+
+    child.wait().await?;
+    Ok(parser.finish())
+}
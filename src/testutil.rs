@@ -0,0 +1,31 @@
+//! Fixtures shared by the unit tests
+
+use crate::platform::long_path;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// Fresh directory under the system temp dir, removed on drop
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("necodl-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(long_path(&dir));
+        std::fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(long_path(&self.0));
+    }
+}
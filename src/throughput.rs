@@ -0,0 +1,56 @@
+use crate::error::NecoError;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::fs;
+
+/// Downloads kept in the log, older ones are dropped
+const MAX_SAMPLES: usize = 500;
+
+/// One SteamCMD download: how much it fetched and how long it took
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sample {
+    pub id: String,
+    pub bytes: u64,
+    pub secs: f64,
+    pub at: DateTime<Utc>,
+}
+
+impl Sample {
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes as f64 / self.secs.max(0.001)
+    }
+}
+
+/// Adds a download to the log, dropping the oldest beyond MAX_SAMPLES
+pub fn record(samples: &mut Vec<Sample>, sample: Sample) {
+    samples.push(sample);
+    let excess = samples.len().saturating_sub(MAX_SAMPLES);
+    samples.drain(..excess);
+}
+
+/// Combined speed of the samples in bytes per second: total size over total
+/// time, so small downloads dominated by SteamCMD's startup don't skew it
+pub fn average<'a>(samples: impl IntoIterator<Item = &'a Sample>) -> Option<f64> {
+    let (bytes, secs) = samples
+        .into_iter()
+        .fold((0u64, 0f64), |(bytes, secs), s| (bytes + s.bytes, secs + s.secs));
+    (secs > 0.0).then(|| bytes as f64 / secs)
+}
+
+pub async fn load(path: &Path) -> Result<Vec<Sample>> {
+    match fs::read_to_string(path).await {
+        Ok(data) => serde_json::from_str(&data)
+            .with_context(|| format!("Failed to parse {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).with_context(|| NecoError::Fs(format!("Failed to read {}", path.display()))),
+    }
+}
+
+pub async fn save(path: &Path, samples: &[Sample]) -> Result<()> {
+    let data = serde_json::to_string_pretty(samples)?;
+    fs::write(path, data)
+        .await
+        .with_context(|| NecoError::Fs(format!("Failed to save {}", path.display())))
+}